@@ -0,0 +1,90 @@
+use crate::config::Config;
+use crate::export;
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Records a destructive action. Returns an error only when the audit trail
+// itself could not be written; the outcome of the action is part of the entry.
+pub fn record(config: &Config, action: &str, path: &Path, size: u64, outcome: &Result<(), String>) -> Result<(), String> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let entry = entry(action, &user(), path, size, outcome);
+    if config.audit_syslog {
+        syslog(&entry);
+    }
+    let Some(log) = &config.audit_log else {
+        return Ok(());
+    };
+    if let Some(parent) = log.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("audit log {}: {}", parent.display(), e))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|e| format!("audit log {}: {}", log.display(), e))?;
+    writeln!(file, "{}\t{}", export::timestamp(secs), entry).map_err(|e| format!("audit log {}: {}", log.display(), e))
+}
+
+// One tab-separated line. A name can hold tabs and newlines too, so every
+// field is escaped as in the scan cache, and one entry cannot pass for two.
+fn entry(action: &str, user: &str, path: &Path, size: u64, outcome: &Result<(), String>) -> String {
+    let result = match outcome {
+        Ok(()) => "ok".to_string(),
+        Err(err) => format!("failed: {}", err),
+    };
+    let path = path.to_string_lossy();
+    format!("{}\t{}\t{}\t{}\t{}", escape(action), escape(user), escape(&path), size, escape(&result))
+}
+
+fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Under sudo, the invoking user is the interesting one.
+fn user() -> String {
+    env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_else(|_| format!("uid={}", unsafe { libc::getuid() }))
+}
+
+fn syslog(entry: &str) {
+    let Ok(msg) = CString::new(entry.replace('\t', " ")) else {
+        return;
+    };
+    unsafe {
+        libc::openlog(c"duviz".as_ptr(), libc::LOG_PID, libc::LOG_AUTHPRIV);
+        libc::syslog(libc::LOG_NOTICE, c"%s".as_ptr(), msg.as_ptr());
+        libc::closelog();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_cannot_forge_entries() {
+        let path = Path::new("/data/a\nremove\tbob\t/etc\t0\tok");
+        let line = entry("remove", "alice", path, 5, &Err("no\tway".to_string()));
+        assert_eq!(line, "remove\talice\t/data/a\\nremove\\tbob\\t/etc\\t0\\tok\t5\tfailed: no\\tway");
+        assert_eq!(line.split('\t').count(), 5);
+        assert_eq!(escape("C:\\x"), "C:\\\\x");
+    }
+}
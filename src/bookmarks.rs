@@ -0,0 +1,36 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// Bookmarked folders, one path per line as raw bytes, in the order they were
+// added. The file is meant to be edited by hand too: blank lines and lines
+// starting with # are skipped.
+
+// A missing or unreadable file is just no bookmarks.
+pub fn load(file: &Path) -> Vec<PathBuf> {
+    let Ok(data) = fs::read(file) else {
+        return Vec::new();
+    };
+    data.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(|line| PathBuf::from(OsStr::from_bytes(line)))
+        .collect()
+}
+
+// Written to a temporary file first, like the scan cache.
+pub fn save(file: &Path, bookmarks: &[PathBuf]) -> Result<(), String> {
+    let mut out = Vec::new();
+    for path in bookmarks {
+        out.extend_from_slice(path.as_os_str().as_bytes());
+        out.push(b'\n');
+    }
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    let mut tmp = file.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, out).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, file).map_err(|e| format!("{}: {}", file.display(), e))
+}
@@ -0,0 +1,525 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Run,
+    ConfigInit { path: Option<PathBuf>, force: bool },
+    ConfigCheck { path: Option<PathBuf> },
+    Diff { old: PathBuf, new: PathBuf, limit: usize },
+    Serve { bind: String, port: u16 },
+    Snapshot { dir: PathBuf, keep: Option<usize> },
+    Watch { interval: u64 },
+    Version,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Html,
+    Prometheus,
+    JsonLines,
+    Snapshot,
+    Markdown,
+    Du,
+    Ncdu,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSize {
+    pub bytes: u64,
+    // du prints the unit after each number when SIZE was given without a count, as in -BM.
+    pub suffix: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub path: PathBuf,
+    pub profile: Option<String>,
+    pub report: Option<ReportFormat>,
+    pub out: Option<PathBuf>,
+    pub choose_files: Option<PathBuf>,
+    pub import: Option<PathBuf>,
+    pub errors_out: Option<PathBuf>,
+    pub block_size: Option<BlockSize>,
+    pub follow_links: bool,
+    pub no_mouse: bool,
+    pub allocated: bool,
+    pub count_links: bool,
+    pub deep: bool,
+    // Start in the files view.
+    pub files: bool,
+    // Start on the list of mounted filesystems.
+    pub devices: bool,
+    pub exclude: Vec<String>,
+    pub one_fs: bool,
+    pub apparent: bool,
+    pub depth: Option<usize>,
+}
+
+pub fn parse<I: Iterator<Item = OsString>>(args: I) -> Result<Options, String> {
+    let mut args = args.peekable();
+    // Only an exact first argument is a subcommand, so a folder of the same
+    // name is still reached as `./watch` or after `--`.
+    let command = match args.peek().and_then(|arg| arg.to_str()) {
+        Some("config") => {
+            args.next();
+            Some(parse_config_command(&mut args)?)
+        }
+        Some("diff") => {
+            args.next();
+            Some(parse_diff_command(&mut args)?)
+        }
+        Some("serve") => {
+            args.next();
+            let (command, path) = parse_serve_command(&mut args)?;
+            return Ok(subcommand_options(command, path));
+        }
+        Some("watch") => {
+            args.next();
+            let (command, path, profile) = parse_watch_command(&mut args)?;
+            let mut options = subcommand_options(command, path);
+            options.profile = profile;
+            return Ok(options);
+        }
+        Some("snapshot") => {
+            args.next();
+            let (command, path) = parse_snapshot_command(&mut args)?;
+            return Ok(subcommand_options(command, path));
+        }
+        _ => None,
+    };
+    if let Some(command) = command {
+        return Ok(subcommand_options(command, PathBuf::from(".")));
+    }
+
+    let mut path: Option<PathBuf> = None;
+    let mut profile: Option<String> = None;
+    let mut report: Option<ReportFormat> = None;
+    let mut out: Option<PathBuf> = None;
+    let mut choose_files: Option<PathBuf> = None;
+    let mut import: Option<PathBuf> = None;
+    let mut errors_out: Option<PathBuf> = None;
+    let mut block_size: Option<BlockSize> = None;
+    let mut follow_links = false;
+    let mut no_mouse = false;
+    let mut allocated = false;
+    let mut count_links = false;
+    let mut deep = false;
+    let mut files = false;
+    let mut devices = false;
+    let mut exclude: Vec<String> = Vec::new();
+    let mut one_fs = false;
+    let mut apparent = false;
+    let mut depth: Option<usize> = None;
+
+    while let Some(raw) = args.next() {
+        let (arg, mut inline) = split_inline(&raw);
+        match arg.as_str() {
+            "--profile" => profile = Some(take_text(&arg, &mut inline, &mut args)?),
+            "--html" => {
+                set_report(&mut report, ReportFormat::Html)?;
+                out = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?));
+            }
+            "--prometheus" => set_report(&mut report, ReportFormat::Prometheus)?,
+            "--export" => {
+                set_report(&mut report, ReportFormat::Ncdu)?;
+                out = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?));
+            }
+            "--jsonl" => set_report(&mut report, ReportFormat::JsonLines)?,
+            "--json" => set_report(&mut report, ReportFormat::Snapshot)?,
+            "--markdown" | "--md" => set_report(&mut report, ReportFormat::Markdown)?,
+            "--du-format" => set_report(&mut report, ReportFormat::Du)?,
+            "-k" => {
+                block_size = Some(BlockSize {
+                    bytes: 1024,
+                    suffix: String::new(),
+                })
+            }
+            "-B" | "--block-size" => block_size = Some(parse_block_size(&take_text(&arg, &mut inline, &mut args)?)?),
+            _ if arg.starts_with("-B") => block_size = Some(parse_block_size(&arg[2..])?),
+            "--follow-links" | "-L" => follow_links = true,
+            "--no-mouse" => no_mouse = true,
+            "--allocated" => allocated = true,
+            "--count-links" | "-l" => count_links = true,
+            "--deep" => deep = true,
+            "--files" => files = true,
+            "--devices" => devices = true,
+            "--exclude" => exclude.push(take_text(&arg, &mut inline, &mut args)?),
+            "--one-file-system" | "-x" => one_fs = true,
+            "--apparent-size" => apparent = true,
+            "--depth" | "--max-depth" | "-d" => {
+                let value = take_text(&arg, &mut inline, &mut args)?;
+                depth = Some(value.parse().map_err(|_| format!("invalid depth '{}'", value))?);
+            }
+            "--version" | "-V" => return Ok(subcommand_options(Command::Version, PathBuf::from("."))),
+            "--choosefiles" => {
+                choose_files = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?))
+            }
+            "--import" => import = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?)),
+            "--errors" => errors_out = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?)),
+            "--out" | "-o" => out = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?)),
+            "--" => {
+                if let Some(value) = args.next() {
+                    set_path(&mut path, value)?;
+                }
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => set_path(&mut path, raw)?,
+        }
+        if inline.is_some() {
+            return Err(format!("option '{}' does not take a value", arg));
+        }
+    }
+
+    if block_size.is_some() && report != Some(ReportFormat::Du) {
+        return Err("-k and -B only apply to --du-format".to_string());
+    }
+    if follow_links && report.is_none() {
+        return Err("--follow-links only applies to report modes".to_string());
+    }
+    if deep && report.is_some() {
+        return Err("--deep only applies to the interactive view".to_string());
+    }
+    if files && report.is_some() {
+        return Err("--files only applies to the interactive view".to_string());
+    }
+    if devices && (report.is_some() || import.is_some()) {
+        return Err("--devices only applies to the interactive view of a live scan".to_string());
+    }
+    if apparent && allocated {
+        return Err("--apparent-size and --allocated cannot be combined".to_string());
+    }
+
+    Ok(Options {
+        command: Command::Run,
+        path: path.unwrap_or_else(|| PathBuf::from(".")),
+        profile,
+        report,
+        out,
+        choose_files,
+        import,
+        errors_out,
+        block_size,
+        follow_links,
+        no_mouse,
+        allocated,
+        count_links,
+        deep,
+        files,
+        devices,
+        exclude,
+        one_fs,
+        apparent,
+        depth,
+    })
+}
+
+fn subcommand_options(command: Command, path: PathBuf) -> Options {
+    Options {
+        command,
+        path,
+        profile: None,
+        report: None,
+        out: None,
+        choose_files: None,
+        import: None,
+        errors_out: None,
+        block_size: None,
+        follow_links: false,
+        no_mouse: false,
+        allocated: false,
+        count_links: false,
+        deep: false,
+        files: false,
+        devices: false,
+        exclude: Vec::new(),
+        one_fs: false,
+        apparent: false,
+        depth: None,
+    }
+}
+
+fn parse_config_command<I: Iterator<Item = OsString>>(mut args: I) -> Result<Command, String> {
+    let action = args.next().ok_or("usage: duviz config <init|check> [PATH]")?;
+    let action = action.to_string_lossy();
+    let mut path: Option<PathBuf> = None;
+    let mut force = false;
+    for raw in args {
+        let arg = raw.to_string_lossy();
+        match arg.as_ref() {
+            "--force" if action == "init" => force = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => set_path(&mut path, raw)?,
+        }
+    }
+    match action.as_ref() {
+        "init" => Ok(Command::ConfigInit { path, force }),
+        "check" => Ok(Command::ConfigCheck { path }),
+        _ => Err(format!("unknown config action '{}' (expected init or check)", action)),
+    }
+}
+
+fn parse_diff_command<I: Iterator<Item = OsString>>(mut args: I) -> Result<Command, String> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut limit = 50usize;
+    while let Some(raw) = args.next() {
+        let (arg, mut inline) = split_inline(&raw);
+        match arg.as_str() {
+            "--limit" | "-n" => {
+                let value = take_text(&arg, &mut inline, &mut args)?;
+                limit = value
+                    .parse()
+                    .map_err(|_| format!("{} expects a number, got '{}'", arg, value))?;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => files.push(PathBuf::from(raw)),
+        }
+    }
+    let [old, new]: [PathBuf; 2] = files
+        .try_into()
+        .map_err(|_| "usage: duviz diff OLD.json NEW.json [--limit N]".to_string())?;
+    Ok(Command::Diff { old, new, limit })
+}
+
+fn parse_serve_command<I: Iterator<Item = OsString>>(mut args: I) -> Result<(Command, PathBuf), String> {
+    let mut path: Option<PathBuf> = None;
+    let mut bind = "127.0.0.1".to_string();
+    let mut port = 8080u16;
+    while let Some(raw) = args.next() {
+        let (arg, mut inline) = split_inline(&raw);
+        match arg.as_str() {
+            "--port" | "-p" => {
+                let value = take_text(&arg, &mut inline, &mut args)?;
+                port = value
+                    .parse()
+                    .map_err(|_| format!("{} expects a port number, got '{}'", arg, value))?;
+            }
+            "--bind" => bind = take_text(&arg, &mut inline, &mut args)?,
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => set_path(&mut path, raw)?,
+        }
+    }
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+    Ok((Command::Serve { bind, port }, path))
+}
+
+fn parse_snapshot_command<I: Iterator<Item = OsString>>(mut args: I) -> Result<(Command, PathBuf), String> {
+    let mut path: Option<PathBuf> = None;
+    let mut out: Option<PathBuf> = None;
+    let mut keep: Option<usize> = None;
+    while let Some(raw) = args.next() {
+        let (arg, mut inline) = split_inline(&raw);
+        match arg.as_str() {
+            "--out" | "-o" => out = Some(PathBuf::from(take_value(&arg, &mut inline, &mut args)?)),
+            "--keep" => {
+                let value = take_text(&arg, &mut inline, &mut args)?;
+                keep = match value.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(format!("{} expects a positive number, got '{}'", arg, value)),
+                };
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => set_path(&mut path, raw)?,
+        }
+    }
+    let dir = out.ok_or("usage: duviz snapshot PATH --out DIR [--keep N]")?;
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+    Ok((Command::Snapshot { dir, keep }, path))
+}
+
+fn parse_watch_command<I: Iterator<Item = OsString>>(
+    mut args: I,
+) -> Result<(Command, PathBuf, Option<String>), String> {
+    let mut path: Option<PathBuf> = None;
+    let mut profile: Option<String> = None;
+    let mut interval = 300u64;
+    while let Some(raw) = args.next() {
+        let (arg, mut inline) = split_inline(&raw);
+        match arg.as_str() {
+            "--interval" | "-i" => {
+                let value = take_text(&arg, &mut inline, &mut args)?;
+                interval = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("{} expects a number of seconds, got '{}'", arg, value)),
+                };
+            }
+            "--profile" => profile = Some(take_text(&arg, &mut inline, &mut args)?),
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => set_path(&mut path, raw)?,
+        }
+    }
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+    Ok((Command::Watch { interval }, path, profile))
+}
+
+fn set_report(report: &mut Option<ReportFormat>, format: ReportFormat) -> Result<(), String> {
+    if report.is_some_and(|r| r != format) {
+        return Err("only one report format can be selected".to_string());
+    }
+    *report = Some(format);
+    Ok(())
+}
+
+// Accepts du's SIZE syntax: an optional integer followed by an optional unit,
+// where K, M, G, ... (or KiB, MiB, ...) are powers of 1024 and KB, MB, ... of 1000.
+fn parse_block_size(value: &str) -> Result<BlockSize, String> {
+    let invalid = || format!("invalid block size '{}'", value);
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let count: u64 = if digits.is_empty() {
+        1
+    } else {
+        digits.parse().map_err(|_| invalid())?
+    };
+    let (letter, base) = match unit.as_bytes() {
+        [] if count > 0 && !digits.is_empty() => {
+            return Ok(BlockSize {
+                bytes: count,
+                suffix: String::new(),
+            })
+        }
+        [] => return Err(invalid()),
+        [l] | [l, b'i', b'B'] => (*l, 1024u64),
+        [l, b'B'] => (*l, 1000u64),
+        _ => return Err(invalid()),
+    };
+    let power = match letter.to_ascii_uppercase() {
+        b'K' => 1,
+        b'M' => 2,
+        b'G' => 3,
+        b'T' => 4,
+        b'P' => 5,
+        b'E' => 6,
+        _ => return Err(invalid()),
+    };
+    match count.checked_mul(base.pow(power)) {
+        Some(bytes) if bytes > 0 => Ok(BlockSize {
+            bytes,
+            suffix: match unit {
+                _ if !digits.is_empty() => String::new(),
+                "KB" => "kB".to_string(),
+                _ => unit.to_string(),
+            },
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn split_inline(raw: &OsStr) -> (String, Option<OsString>) {
+    let bytes = raw.as_bytes();
+    if bytes.starts_with(b"--") {
+        if let Some(eq) = bytes.iter().position(|&b| b == b'=') {
+            let value = OsString::from_vec(bytes[eq + 1..].to_vec());
+            return (String::from_utf8_lossy(&bytes[..eq]).into_owned(), Some(value));
+        }
+    }
+    (raw.to_string_lossy().into_owned(), None)
+}
+
+fn take_value<I: Iterator<Item = OsString>>(
+    name: &str,
+    inline: &mut Option<OsString>,
+    args: &mut I,
+) -> Result<OsString, String> {
+    inline
+        .take()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{} requires a value", name))
+}
+
+// A value that is not a path, so it has to be text.
+fn take_text<I: Iterator<Item = OsString>>(
+    name: &str,
+    inline: &mut Option<OsString>,
+    args: &mut I,
+) -> Result<String, String> {
+    take_value(name, inline, args)?
+        .into_string()
+        .map_err(|value| format!("{} expects UTF-8 text, got '{}'", name, value.to_string_lossy()))
+}
+
+fn set_path(path: &mut Option<PathBuf>, value: OsString) -> Result<(), String> {
+    if path.is_some() {
+        return Err(format!("unexpected argument '{}'", value.to_string_lossy()));
+    }
+    *path = Some(PathBuf::from(value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Options, String> {
+        parse(list.iter().map(OsString::from))
+    }
+
+    fn block(value: &str) -> Result<(u64, String), String> {
+        parse_block_size(value).map(|b| (b.bytes, b.suffix))
+    }
+
+    #[test]
+    fn block_sizes_follow_du() {
+        assert_eq!(block("512"), Ok((512, String::new())));
+        assert_eq!(block("K"), Ok((1024, "K".to_string())));
+        assert_eq!(block("M"), Ok((1 << 20, "M".to_string())));
+        assert_eq!(block("MiB"), Ok((1 << 20, "MiB".to_string())));
+        assert_eq!(block("KB"), Ok((1000, "kB".to_string())));
+        assert_eq!(block("kB"), Ok((1000, "kB".to_string())));
+        assert_eq!(block("4k"), Ok((4096, String::new())));
+        assert_eq!(block("1E"), Ok((1 << 60, String::new())));
+    }
+
+    #[test]
+    fn bad_block_sizes_are_refused() {
+        for value in ["", "0", "0K", "Q", "KiBB", "1.5M", "-1K", "Mb", "16E", "99999999999999999999", "20000000T"] {
+            assert_eq!(block(value), Err(format!("invalid block size '{}'", value)), "{}", value);
+        }
+    }
+
+    #[test]
+    fn options_take_values_inline_or_after() {
+        let options = args(&["--du-format", "-BM", "--exclude=*.o", "--exclude", "tmp", "-d", "2", "/srv"]).unwrap();
+        assert_eq!(options.report, Some(ReportFormat::Du));
+        assert_eq!(options.block_size, Some(BlockSize { bytes: 1 << 20, suffix: "M".to_string() }));
+        assert_eq!(options.exclude, ["*.o", "tmp"]);
+        assert_eq!((options.depth, options.path), (Some(2), PathBuf::from("/srv")));
+        assert_eq!(args(&["--depth"]).unwrap_err(), "--depth requires a value");
+        assert_eq!(args(&["--deep=yes"]).unwrap_err(), "option '--deep' does not take a value");
+        assert_eq!(args(&["--depth", "-1"]).unwrap_err(), "invalid depth '-1'");
+    }
+
+    #[test]
+    fn paths_are_taken_as_given() {
+        let latin1 = OsString::from_vec(b"/srv/caf\xe9".to_vec());
+        let options = parse([OsString::from("--jsonl"), latin1.clone()].into_iter()).unwrap();
+        assert_eq!(options.path.as_os_str(), latin1);
+        let out = OsString::from_vec(b"--out=/tmp/\xff.json".to_vec());
+        let options = parse([OsString::from("--json"), out].into_iter()).unwrap();
+        assert_eq!(options.out.unwrap().as_os_str().as_bytes(), b"/tmp/\xff.json");
+        let profile = [OsString::from("--profile"), OsString::from_vec(b"\xff".to_vec())];
+        assert_eq!(parse(profile.into_iter()).unwrap_err(), "--profile expects UTF-8 text, got '\u{fffd}'");
+    }
+
+    #[test]
+    fn subcommands_only_match_the_first_argument() {
+        assert!(matches!(args(&["watch"]).unwrap().command, Command::Watch { interval: 300 }));
+        for given in [&["./watch"][..], &["--", "watch"]] {
+            let options = args(given).unwrap();
+            assert!(matches!(options.command, Command::Run));
+            assert!(options.path.ends_with("watch"));
+        }
+        assert_eq!(args(&["/a", "/b"]).unwrap_err(), "unexpected argument '/b'");
+    }
+
+    #[test]
+    fn conflicting_options_are_refused() {
+        assert_eq!(args(&["--jsonl", "--html", "x.html"]).unwrap_err(), "only one report format can be selected");
+        assert_eq!(args(&["-BK"]).unwrap_err(), "-k and -B only apply to --du-format");
+        let both = args(&["--apparent-size", "--allocated"]).unwrap_err();
+        assert_eq!(both, "--apparent-size and --allocated cannot be combined");
+        assert_eq!(args(&["--bogus"]).unwrap_err(), "unknown option '--bogus'");
+    }
+}
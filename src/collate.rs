@@ -0,0 +1,44 @@
+use std::cmp::Ordering;
+
+// Case-insensitive "natural" order: runs of digits compare by value, so
+// file2 sorts before file10 and "a" next to "A". Names that only differ in
+// case or leading zeros fall back to byte order to keep the order total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut x = a.chars().peekable();
+    let mut y = b.chars().peekable();
+    loop {
+        match (x.peek().copied(), y.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let m = take_number(&mut x);
+                let n = take_number(&mut y);
+                let ord = m.len().cmp(&n.len()).then_with(|| m.cmp(&n));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(c), Some(d)) => {
+                let ord = c.to_lowercase().cmp(d.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                x.next();
+                y.next();
+            }
+        }
+    }
+}
+
+// Returns the digit run without leading zeros so lengths compare like values.
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        if !(digits.is_empty() && c == '0') {
+            digits.push(c);
+        }
+        chars.next();
+    }
+    digits
+}
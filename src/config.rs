@@ -0,0 +1,859 @@
+use crate::graphics;
+use crate::keys::{Action, KeyMap};
+use crate::log::Level;
+use crate::fsinfo::PSEUDO_FSTYPES;
+use crate::palette::{Background, ColorDepth, Palette};
+use crate::units::UnitMode;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG: &str = r#"# duviz configuration
+#
+# Lines are `key = value`. Strings may be quoted, booleans are true/false.
+# Settings under [profile.NAME] override the top-level values when duviz
+# is started with `--profile NAME`.
+
+# Show "duviz — /current/path" in the terminal window title.
+# set_title = true
+
+# Capture the mouse for click navigation. Disable for terminals where
+# mouse reporting misbehaves or to keep the terminal's own selection.
+# mouse = true
+
+# Refuse to delete anything from the UI.
+# read_only = false
+
+# Deleting moves things to the trash so they can be restored. Set to true to
+# remove them for good instead. Shift+right-click and Shift+Delete always
+# delete permanently, and t always uses the trash.
+# permanent_delete = false
+
+# Append every delete (time, user, path, size, outcome) to this file.
+# Set to "" to disable. Defaults to $XDG_STATE_HOME/duviz/audit.log,
+# i.e. ~/.local/state/duviz/audit.log.
+# audit_log = "~/.local/state/duviz/audit.log"
+
+# Also send audit entries to syslog (picked up by journald).
+# audit_syslog = false
+
+# Diagnostics (scan and sizing timings, cache activity, actions) are written
+# to log_file at this level or more severe: off, error, warn, info, debug or
+# trace. F11 cycles the level while duviz runs. Set log_file to "" to disable.
+# log_file = "~/.local/state/duviz/duviz.log"
+# log_level = "warn"
+
+# Folder scans are kept in this file when duviz exits and reused next time
+# for folders whose modification times have not changed, marked "(cached)";
+# r scans the current folder again. Changes deeper down than the folder's
+# subfolders go unnoticed until then. Set to "" to disable. Defaults to
+# $XDG_CACHE_HOME/duviz/scans, i.e. ~/.cache/duviz/scans.
+# cache_file = "~/.cache/duviz/scans"
+
+# Folders bookmarked with b and listed by B, one path per line. Set to "" to
+# keep none. Defaults to $XDG_CONFIG_HOME/duviz/bookmarks, i.e.
+# ~/.config/duviz/bookmarks.
+# bookmarks_file = "~/.config/duviz/bookmarks"
+
+# Watch the folder on screen and its subfolders (inotify, Linux only) and
+# rescan it a moment after files in them are created, deleted or grow.
+# live_refresh = true
+
+# Under WSL, include Windows drives (/mnt/c and friends) when scanning the
+# directory that contains them. They are skipped by default because reading
+# them through drvfs/9p is extremely slow.
+# wsl_windows_drives = false
+
+# Color palette: "auto" detects it from COLORTERM/TERM and terminfo.
+# "truecolor" and "256" use smooth gradients, "16" the named ANSI colors,
+# and "8" only colors that the Linux console can show as backgrounds.
+# colors = "auto"
+
+# Light or dark terminal background: "auto" asks the terminal (OSC 11) and
+# lifts the treemap colors on light backgrounds.
+# theme = "auto"
+
+# A base16 or base24 scheme file (YAML) to take the treemap colors from, so
+# duviz matches the rest of a themed terminal. Its base00 decides between
+# light and dark.
+# color_scheme = "~/.config/tinted-theming/base16/ocean.yaml"
+
+# Colors of the treemap, bars and text: "default", "deuteranopia" (blue
+# against orange, safe with red-green color blindness), "high-contrast" or
+# "monochrome". A color_scheme only applies to the default one. Press C to
+# cycle through them while duviz runs.
+# palette = "default"
+
+# Order names case-insensitively with numbers by value (file2 before
+# file10). Set to false for raw byte order.
+# natural_sort = true
+
+# Color blocks by how long ago anything in them changed, from green (this
+# week) to red (two years or more), instead of by size rank. Press c to
+# switch while duviz runs.
+# age_colors = false
+
+# Resolution in pixels of PNG treemap exports (key X).
+# png_width = 1920
+# png_height = 1080
+
+# Directories in the folders view are sized by this many workers at once
+# (default: number of CPUs, at most 8). A directory that takes longer than
+# size_timeout seconds, e.g. on a dead network mount, is shown with what was
+# counted so far, marked with "≥". 0 waits forever.
+# size_workers = 8
+# size_timeout = 120
+
+# The files view lists this many of the largest files; the rest are shown
+# together as one "(N more)" block.
+# files_limit = 1000
+
+# Items that would get a block of fewer than this many character cells are
+# shown together as one "Other (N items)" block; clicking it opens the list.
+# 0 gives every item its own block. With half_blocks or a picture these are
+# half cells.
+# min_block_area = 4
+
+# Lay the treemap out in half rows and draw where two blocks meet inside a
+# cell with ▀, so small items still show and proportions are closer on short
+# terminals. Set to false if the font leaves gaps in half blocks.
+# half_blocks = true
+
+# Draw the treemap as a picture on terminals that can show one: smooth edges,
+# exact proportions and shading, with the labels still drawn as text. auto
+# asks the terminal for the kitty graphics protocol and then Sixel, and stays
+# with cells inside tmux or screen; kitty or sixel skips the question.
+# graphics = "auto"   # auto, kitty, sixel or off
+
+# Visited directories are cached in memory so going back is instant. Past
+# this many MiB the least recently visited ones are dropped. The tree of a
+# deep scan or an import counts toward it too. 0 keeps all.
+# memory_limit = 256
+
+# While you look at a folder, its three largest subfolders are scanned in
+# the background so opening one of them is instant.
+# prefetch = true
+
+# Scan the whole tree below the start folder once, like ncdu, instead of
+# every folder as you open it. Moving around is then instant and folder
+# blocks show what is inside them, at the cost of keeping every file in
+# memory. Also --deep.
+# deep_scan = false
+
+# Show the blocks allocated on disk instead of apparent sizes (file
+# lengths), so sparse files and compressed filesystems count what they
+# really use. Press a to switch while duviz runs.
+# allocated = false
+
+# How sizes are written, on the screen and in exports and reports: "binary"
+# (KiB, MiB: powers of 1024), "si" (kB, MB: powers of 1000) or "bytes"
+# (exact, with thousands separators). Press u to cycle while duviz runs.
+# units = "binary"
+
+# A file with several hard links is counted once, in the first directory
+# where it is found. Set to true to count it under every link, like du -l.
+# count_links = false
+
+# Leave out entries matching these shell patterns (separated by spaces), in
+# the UI and in reports. A pattern with a slash is matched against the whole
+# path, any other against the name. --exclude adds more.
+# exclude = ".git node_modules *.iso"
+
+# Filesystems of these types (space-separated, as in /proc/self/mounts) hold
+# no user data: /proc, /sys, /dev, /run and the like. Where one is mounted
+# inside the scanned folder it is not sized; the UI shows it as a dimmed
+# block. The default covers these and a few rarer ones; "" scans them all.
+# pseudo_filesystems = "proc sysfs devtmpfs devpts tmpfs cgroup cgroup2 debugfs"
+
+# Leave out other filesystems mounted inside the scanned folder, like du -x.
+# Also --one-file-system. The UI shows them as dimmed, uncounted blocks; m
+# switches this on and off.
+# one_file_system = false
+
+# Folders that are mount points (/boot, or /home on another disk) are drawn
+# with a double border. Set to true to size them by the used space of their
+# filesystem instead of what the scan counted under them.
+# mount_usage = false
+
+# At the root of a filesystem, show its free space as a block of its own, so
+# the treemap covers the whole disk. Press F to switch while duviz runs.
+# free_space = true
+
+# Keys of the main screen, as a space-separated list per action. A key given
+# here is taken away from whatever it did before. Names are like "q", "T",
+# "Ctrl+r", "Shift+Delete", "Space", "Enter", "PageUp" or "F5". The actions
+# are row_up, row_down, page_up, page_down, first, last (moving in the list),
+# quit, help, up, back, forward, next, previous, open, launch, reveal, shell,
+# mark, delete, trash, delete_folder, delete_folder_permanently, refresh,
+# one_file_system, free_space, files, types, largest, list, search, find,
+# size_mode, units, sort, labels, age_colors, palette, volumes, go_to,
+# compare, bookmark, bookmarks, export_svg, export_png, export_errors, errors,
+# delete_script, debug and log_level. The help (?) shows the keys in effect.
+#
+# [keys]
+# up = "Backspace u Left Esc"
+# quit = "q Ctrl+c"
+# refresh = "r F5"
+
+# Hooks run while `duviz watch PATH` is rescanning. A hook fires once when
+# its threshold is crossed and re-arms when usage drops below it again. The
+# threshold is either a filesystem fill level ("90%") or a size of the
+# watched tree ("500G"). The command runs through sh with DUVIZ_HOOK,
+# DUVIZ_PATH, DUVIZ_BYTES and DUVIZ_FS_PERCENT set; a webhook receives the
+# same values as a JSON POST (plain http:// only).
+#
+# [hook.var-full]
+# threshold = "90%"
+# command = "notify-send 'disk almost full'"
+# webhook = "http://alerts.local:9000/duviz"
+#
+# Instead of a threshold, a hook can run on an event of the interactive UI:
+# "pre-scan", "post-scan", "pre-delete" or "post-delete" (trashing counts as
+# deleting). DUVIZ_EVENT, DUVIZ_PATH and the event's details are set, and
+# the command also gets them as JSON on stdin. A failing pre- hook stops the
+# scan or delete; post- hooks run in the background.
+#
+# [hook.cmdb]
+# on = "post-delete"
+# command = "cmdb-update \"$DUVIZ_PATH\""
+
+# Example profile for shared servers:
+#
+# [profile.server]
+# read_only = true
+# mouse = false
+"#;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub set_title: bool,
+    pub mouse: bool,
+    pub read_only: bool,
+    pub permanent_delete: bool,
+    pub png_width: u16,
+    pub png_height: u16,
+    pub size_workers: u16,
+    pub size_timeout: u16,
+    pub files_limit: u16,
+    pub min_block_area: u16,
+    pub half_blocks: bool,
+    pub graphics: graphics::Mode,
+    pub memory_limit: u16,
+    pub prefetch: bool,
+    pub deep_scan: bool,
+    pub count_links: bool,
+    pub allocated: bool,
+    pub units: UnitMode,
+    pub exclude: Vec<String>,
+    pub pseudo_filesystems: Vec<String>,
+    pub one_file_system: bool,
+    pub mount_usage: bool,
+    pub free_space: bool,
+    pub audit_log: Option<PathBuf>,
+    pub audit_syslog: bool,
+    pub log_file: Option<PathBuf>,
+    pub log_level: Level,
+    pub cache_file: Option<PathBuf>,
+    pub bookmarks_file: Option<PathBuf>,
+    pub live_refresh: bool,
+    pub wsl_windows_drives: bool,
+    pub colors: Option<ColorDepth>,
+    pub theme: Option<Background>,
+    pub color_scheme: Option<PathBuf>,
+    pub palette: Palette,
+    pub natural_sort: bool,
+    pub age_colors: bool,
+    pub keys: KeyMap,
+    pub hooks: Vec<Hook>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    FsPercent(f64),
+    Bytes(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    PreScan,
+    PostScan,
+    PreDelete,
+    PostDelete,
+}
+
+impl HookEvent {
+    const ALL: [HookEvent; 4] = [HookEvent::PreScan, HookEvent::PostScan, HookEvent::PreDelete, HookEvent::PostDelete];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::PreScan => "pre-scan",
+            HookEvent::PostScan => "post-scan",
+            HookEvent::PreDelete => "pre-delete",
+            HookEvent::PostDelete => "post-delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub name: String,
+    pub threshold: Option<Threshold>,
+    pub on: Option<HookEvent>,
+    pub command: Option<String>,
+    pub webhook: Option<String>,
+}
+
+impl Hook {
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), ApplyError> {
+        match key {
+            "threshold" => self.threshold = Some(parse_threshold(value)?),
+            "on" => {
+                self.on = Some(HookEvent::ALL.into_iter().find(|e| e.name() == value).ok_or_else(|| {
+                    ApplyError::Value(format!(
+                        "expected pre-scan, post-scan, pre-delete or post-delete, got '{}'",
+                        value
+                    ))
+                })?)
+            }
+            "command" => self.command = Some(value.to_string()).filter(|c| !c.is_empty()),
+            "webhook" => {
+                if !value.starts_with("http://") {
+                    return Err(ApplyError::Value("only http:// webhooks are supported".to_string()));
+                }
+                self.webhook = Some(value.to_string());
+            }
+            _ => return Err(ApplyError::Key(format!("unknown hook key '{}'", key))),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            set_title: true,
+            mouse: true,
+            read_only: false,
+            permanent_delete: false,
+            png_width: 1920,
+            png_height: 1080,
+            size_workers: std::thread::available_parallelism().map_or(2, |n| n.get().min(8) as u16),
+            size_timeout: 120,
+            files_limit: 1000,
+            min_block_area: 4,
+            half_blocks: true,
+            graphics: graphics::Mode::Auto,
+            memory_limit: 256,
+            prefetch: true,
+            deep_scan: false,
+            count_links: false,
+            allocated: false,
+            units: UnitMode::Binary,
+            exclude: Vec::new(),
+            pseudo_filesystems: PSEUDO_FSTYPES.iter().map(|t| t.to_string()).collect(),
+            one_file_system: false,
+            mount_usage: false,
+            free_space: true,
+            audit_log: state_file("audit.log"),
+            audit_syslog: false,
+            log_file: state_file("duviz.log"),
+            log_level: Level::Warn,
+            cache_file: cache_file("scans"),
+            bookmarks_file: config_file("bookmarks"),
+            live_refresh: true,
+            wsl_windows_drives: false,
+            colors: None,
+            theme: None,
+            color_scheme: None,
+            palette: Palette::Default,
+            natural_sort: true,
+            age_colors: false,
+            keys: KeyMap::default(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    fn hook_mut(&mut self, name: &str) -> &mut Hook {
+        if let Some(i) = self.hooks.iter().position(|h| h.name == name) {
+            return &mut self.hooks[i];
+        }
+        self.hooks.push(Hook {
+            name: name.to_string(),
+            threshold: None,
+            on: None,
+            command: None,
+            webhook: None,
+        });
+        self.hooks.last_mut().unwrap()
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), ApplyError> {
+        match key {
+            "set_title" => self.set_title = parse_bool(value)?,
+            "mouse" => self.mouse = parse_bool(value)?,
+            "read_only" => self.read_only = parse_bool(value)?,
+            "permanent_delete" => self.permanent_delete = parse_bool(value)?,
+            "png_width" => self.png_width = parse_u16(value, 16)?,
+            "png_height" => self.png_height = parse_u16(value, 16)?,
+            "size_workers" => self.size_workers = parse_u16(value, 1)?,
+            "size_timeout" => self.size_timeout = parse_u16(value, 0)?,
+            "files_limit" => self.files_limit = parse_u16(value, 1)?,
+            "min_block_area" => self.min_block_area = parse_u16(value, 0)?,
+            "half_blocks" => self.half_blocks = parse_bool(value)?,
+            "graphics" => {
+                self.graphics = graphics::Mode::parse(value)
+                    .ok_or_else(|| ApplyError::Value(format!("expected auto, kitty, sixel or off, got '{}'", value)))?
+            }
+            "memory_limit" => self.memory_limit = parse_u16(value, 0)?,
+            "prefetch" => self.prefetch = parse_bool(value)?,
+            "deep_scan" => self.deep_scan = parse_bool(value)?,
+            "count_links" => self.count_links = parse_bool(value)?,
+            "allocated" => self.allocated = parse_bool(value)?,
+            "units" => {
+                self.units = UnitMode::parse(value)
+                    .ok_or_else(|| ApplyError::Value(format!("expected binary, si or bytes, got '{}'", value)))?
+            }
+            "exclude" => self.exclude = value.split_whitespace().map(str::to_string).collect(),
+            "pseudo_filesystems" => {
+                self.pseudo_filesystems = value.split_whitespace().map(str::to_string).collect()
+            }
+            "one_file_system" => self.one_file_system = parse_bool(value)?,
+            "mount_usage" => self.mount_usage = parse_bool(value)?,
+            "free_space" => self.free_space = parse_bool(value)?,
+            "audit_log" => self.audit_log = parse_path(value),
+            "audit_syslog" => self.audit_syslog = parse_bool(value)?,
+            "log_file" => self.log_file = parse_path(value),
+            "cache_file" => self.cache_file = parse_path(value),
+            "bookmarks_file" => self.bookmarks_file = parse_path(value),
+            "live_refresh" => self.live_refresh = parse_bool(value)?,
+            "log_level" => {
+                self.log_level = Level::parse(value).ok_or_else(|| {
+                    ApplyError::Value(format!("expected off, error, warn, info, debug or trace, got '{}'", value))
+                })?
+            }
+            "wsl_windows_drives" => self.wsl_windows_drives = parse_bool(value)?,
+            "natural_sort" => self.natural_sort = parse_bool(value)?,
+            "age_colors" => self.age_colors = parse_bool(value)?,
+            "colors" => {
+                self.colors = ColorDepth::parse(value).ok_or_else(|| {
+                    ApplyError::Value(format!("expected auto, truecolor, 256, 16 or 8, got '{}'", value))
+                })?
+            }
+            "theme" => {
+                self.theme = Background::parse(value)
+                    .ok_or_else(|| ApplyError::Value(format!("expected auto, dark or light, got '{}'", value)))?
+            }
+            "color_scheme" => self.color_scheme = parse_path(value),
+            "palette" => {
+                self.palette = Palette::parse(value).ok_or_else(|| {
+                    ApplyError::Value(format!(
+                        "expected default, deuteranopia, high-contrast or monochrome, got '{}'",
+                        value
+                    ))
+                })?
+            }
+            _ => return Err(ApplyError::Key(format!("unknown key '{}'", key))),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
+
+enum ApplyError {
+    Key(String),
+    Value(String),
+}
+
+struct Parsed {
+    entries: Vec<Entry>,
+    profiles: Vec<String>,
+    hooks: Vec<(String, usize)>,
+    errors: Vec<ConfigError>,
+}
+
+struct Entry {
+    line: usize,
+    key_col: usize,
+    value_col: usize,
+    section: Option<String>,
+    key: String,
+    value: String,
+}
+
+impl Entry {
+    fn is_profile(&self) -> bool {
+        self.section.as_deref().is_some_and(|s| s.starts_with("profile."))
+    }
+
+    fn apply_to(&self, config: &mut Config) -> Result<(), ConfigError> {
+        let result = match self.section.as_deref() {
+            Some("keys") => bind_key(config, &self.key, &self.value),
+            Some(section) if section.starts_with("hook.") => {
+                config.hook_mut(&section["hook.".len()..]).apply(&self.key, &self.value)
+            }
+            _ => config.apply(&self.key, &self.value),
+        };
+        result.map_err(|e| match e {
+            ApplyError::Key(msg) => ConfigError { line: self.line, col: self.key_col, msg },
+            ApplyError::Value(msg) => ConfigError { line: self.line, col: self.value_col, msg },
+        })
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_file("config.toml")
+}
+
+fn config_file(name: &str) -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("duviz").join(name))
+}
+
+fn state_file(name: &str) -> Option<PathBuf> {
+    let state = match env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+    Some(state.join("duviz").join(name))
+}
+
+fn cache_file(name: &str) -> Option<PathBuf> {
+    let cache = match env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("duviz").join(name))
+}
+
+pub fn load(profile: Option<&str>) -> Result<Config, String> {
+    let missing_profile = |name: &str| format!("profile '{}' not found in config", name);
+    let Some(path) = config_path() else {
+        return match profile {
+            Some(name) => Err(missing_profile(name)),
+            None => Ok(Config::default()),
+        };
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return match profile {
+                Some(name) => Err(missing_profile(name)),
+                None => Ok(Config::default()),
+            };
+        }
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let parsed = parse_entries(&text);
+    if let Some(err) = parsed.errors.first() {
+        return Err(format!("{}:{}", path.display(), err));
+    }
+    if let Some(name) = profile {
+        if !parsed.profiles.iter().any(|p| p == name) {
+            return Err(missing_profile(name));
+        }
+    }
+    let config = build(&parsed.entries, profile).map_err(|e| format!("{}:{}", path.display(), e))?;
+    if let Some(err) = check_hooks(&config, &parsed.hooks).first() {
+        return Err(format!("{}:{}", path.display(), err));
+    }
+    Ok(config)
+}
+
+pub fn init(path: &Path, force: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!("{} already exists (use --force to overwrite)", path.display()));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    fs::write(path, DEFAULT_CONFIG).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn check(path: &Path) -> Result<Vec<ConfigError>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let parsed = parse_entries(&text);
+    let mut errors = parsed.errors;
+    let mut scratch = Config::default();
+    for entry in &parsed.entries {
+        if let Err(err) = entry.apply_to(&mut scratch) {
+            errors.push(err);
+        }
+    }
+    errors.extend(check_hooks(&scratch, &parsed.hooks));
+    errors.sort_by_key(|e| (e.line, e.col));
+    Ok(errors)
+}
+
+fn check_hooks(config: &Config, headers: &[(String, usize)]) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    for (name, line) in headers {
+        let Some(hook) = config.hooks.iter().find(|h| &h.name == name) else {
+            continue;
+        };
+        let problem = if hook.threshold.is_none() && hook.on.is_none() {
+            "needs a threshold or an on event"
+        } else if hook.threshold.is_some() && hook.on.is_some() {
+            "cannot have both a threshold and an on event"
+        } else if hook.command.is_none() && hook.webhook.is_none() {
+            "needs a command or webhook"
+        } else {
+            continue;
+        };
+        errors.push(ConfigError {
+            line: *line,
+            col: 1,
+            msg: format!("hook '{}' {}", name, problem),
+        });
+    }
+    errors
+}
+
+fn build(entries: &[Entry], profile: Option<&str>) -> Result<Config, ConfigError> {
+    let selected = profile.map(|name| format!("profile.{}", name));
+    let mut config = Config::default();
+    let mut scratch = Config::default();
+    for entry in entries.iter().filter(|e| !e.is_profile()) {
+        entry.apply_to(&mut config)?;
+    }
+    for entry in entries.iter().filter(|e| e.is_profile()) {
+        if entry.section == selected {
+            entry.apply_to(&mut config)?;
+        } else {
+            entry.apply_to(&mut scratch)?;
+        }
+    }
+    Ok(config)
+}
+
+fn parse_entries(text: &str) -> Parsed {
+    let mut entries = Vec::new();
+    let mut profiles = Vec::new();
+    let mut hooks = Vec::new();
+    let mut errors = Vec::new();
+    let mut section: Option<String> = None;
+    for (i, raw) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let content = strip_comment(raw);
+        let line = content.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let indent = content.len() - content.trim_start().len();
+        let col_of = |offset: usize| raw[..offset].chars().count() + 1;
+        let err = |offset: usize, msg: String| ConfigError {
+            line: line_no,
+            col: col_of(offset),
+            msg,
+        };
+
+        if let Some(header) = line.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']').map(str::trim) else {
+                errors.push(err(indent, "unterminated section header".to_string()));
+                continue;
+            };
+            match name.split_once('.') {
+                Some(("profile", profile)) if !profile.is_empty() => profiles.push(profile.to_string()),
+                Some(("hook", hook)) if !hook.is_empty() => hooks.push((hook.to_string(), line_no)),
+                None if name == "keys" => {}
+                _ => errors.push(err(indent + 1, format!("unknown section '{}'", name))),
+            }
+            section = Some(name.to_string());
+            continue;
+        }
+        let Some(eq) = content.find('=') else {
+            errors.push(err(indent, "expected 'key = value'".to_string()));
+            continue;
+        };
+        let key = content[..eq].trim();
+        if key.is_empty() {
+            errors.push(err(indent, "missing key".to_string()));
+            continue;
+        }
+        let value_part = &content[eq + 1..];
+        let value_offset = eq + 1 + (value_part.len() - value_part.trim_start().len());
+        let value = value_part.trim();
+        if value.starts_with('"') && (value.len() < 2 || !value.ends_with('"')) {
+            errors.push(err(value_offset, "unterminated string".to_string()));
+            continue;
+        }
+        entries.push(Entry {
+            line: line_no,
+            key_col: col_of(indent),
+            value_col: col_of(value_offset),
+            section: section.clone(),
+            key: key.to_string(),
+            value: unquote(value),
+        });
+    }
+    Parsed {
+        entries,
+        profiles,
+        hooks,
+        errors,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// \" and \\ stand for themselves; any other backslash is kept, for patterns.
+fn unquote(value: &str) -> String {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return value.to_string();
+    }
+    let mut out = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some(next @ ('"' | '\\'))) => {
+                out.push(next);
+                chars.next();
+            }
+            (ch, _) => out.push(ch),
+        }
+    }
+    out
+}
+
+fn bind_key(config: &mut Config, action: &str, keys: &str) -> Result<(), ApplyError> {
+    let action = Action::from_name(action).ok_or_else(|| ApplyError::Key(format!("unknown action '{}'", action)))?;
+    config.keys.bind(action, keys).map_err(ApplyError::Value)
+}
+
+fn parse_bool(value: &str) -> Result<bool, ApplyError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ApplyError::Value(format!("expected true or false, got '{}'", value))),
+    }
+}
+
+fn parse_path(value: &str) -> Option<PathBuf> {
+    if value.is_empty() {
+        return None;
+    }
+    match (value.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Some(PathBuf::from(home).join(rest)),
+        _ => Some(PathBuf::from(value)),
+    }
+}
+
+fn parse_threshold(value: &str) -> Result<Threshold, ApplyError> {
+    let invalid = || ApplyError::Value(format!("expected a percentage like \"90%\" or a size like \"500G\", got '{}'", value));
+    if let Some(pct) = value.strip_suffix('%') {
+        return match pct.trim().parse::<f64>() {
+            Ok(p) if (0.0..=100.0).contains(&p) => Ok(Threshold::FsPercent(p)),
+            _ => Err(invalid()),
+        };
+    }
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let scale = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 1u64,
+        "K" | "k" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    Ok(Threshold::Bytes((number * scale as f64) as u64))
+}
+
+fn parse_u16(value: &str, min: u16) -> Result<u16, ApplyError> {
+    match value.parse::<u16>() {
+        Ok(n) if n >= min => Ok(n),
+        _ => Err(ApplyError::Value(format!(
+            "expected a number between {} and {}, got '{}'",
+            min,
+            u16::MAX,
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, profile: Option<&str>) -> Result<Config, String> {
+        let parsed = parse_entries(text);
+        if let Some(err) = parsed.errors.first() {
+            return Err(err.to_string());
+        }
+        build(&parsed.entries, profile).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn comments_end_lines_outside_strings() {
+        let text = r##"# a comment
+mouse = false # and another
+[hook.disk]
+on = "post-delete"
+command = "echo \"# not a comment\" C:\\" # but this is
+"##;
+        let config = parse(text, None).unwrap();
+        assert!(!config.mouse);
+        assert_eq!(config.hooks[0].command.as_deref(), Some("echo \"# not a comment\" C:\\"));
+    }
+
+    #[test]
+    fn quoted_values_keep_other_backslashes() {
+        assert_eq!(unquote(r#""a\d+\"b\\""#), r#"a\d+"b\"#);
+        assert_eq!(unquote("bare"), "bare");
+        assert_eq!(parse("exclude = \"*.tmp  cache\"\n", None).unwrap().exclude, ["*.tmp", "cache"]);
+    }
+
+    #[test]
+    fn the_selected_profile_overrides_the_top_level() {
+        let text = "size_workers = 3\n[profile.big]\nsize_workers = 12\n[profile.small]\nsize_workers = 1\n\
+                    mouse = false\n";
+        assert_eq!(parse(text, None).unwrap().size_workers, 3);
+        let big = parse(text, Some("big")).unwrap();
+        assert_eq!((big.size_workers, big.mouse), (12, true));
+        assert_eq!(parse(text, Some("small")).unwrap().size_workers, 1);
+        // A profile that is not selected is still checked.
+        let broken = format!("{}[profile.old]\ncolour = 8\n", text);
+        assert_eq!(parse(&broken, Some("big")).unwrap_err(), "8:1: unknown key 'colour'");
+    }
+
+    #[test]
+    fn unknown_keys_and_sections_are_rejected_where_they_are() {
+        assert_eq!(parse("mouse = true\n  colour = 8\n", None).unwrap_err(), "2:3: unknown key 'colour'");
+        assert_eq!(parse("[profile]\n", None).unwrap_err(), "1:2: unknown section 'profile'");
+        assert_eq!(parse("[keys]\nfly = \"f\"\n", None).unwrap_err(), "2:1: unknown action 'fly'");
+        assert_eq!(parse("mouse = maybe\n", None).unwrap_err().split_once(": ").unwrap().0, "1:9");
+        assert_eq!(parse("command = \"open\n", None).unwrap_err(), "1:11: unterminated string");
+        assert_eq!(parse("[hook.x]\nwho = 1\n", None).unwrap_err(), "2:1: unknown hook key 'who'");
+    }
+}
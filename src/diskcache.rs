@@ -0,0 +1,270 @@
+use crate::scan::{Item, ItemKind, ScanError};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// Folder scans kept between sessions, one line per record and tab-separated:
+//
+//   D  stamp  path                  starts the scan of a folder
+//   I  kind  size  allocated  count  mtime  name  path
+//   E  errno  path  message
+//
+// Tabs, newlines and backslashes in names are escaped. Paths are written as
+// raw bytes, so names that are not UTF-8 survive the round trip. The header
+// carries a digest of the settings the scans were made with (excludes,
+// count_links, one_file_system and the like); scans made with others are
+// not loaded.
+const HEADER: &str = "duviz-cache\t2";
+
+pub struct Entry {
+    pub path: PathBuf,
+    // The dir_stamp of the folder when it was scanned; a different one on
+    // load means it changed since.
+    pub stamp: u64,
+    pub items: Vec<Item>,
+    pub errors: Vec<ScanError>,
+}
+
+// A missing, unreadable or outdated file is just an empty cache, and so is
+// one written with other settings.
+pub fn load(file: &Path, settings: u64) -> Vec<Entry> {
+    let Ok(data) = fs::read(file) else {
+        return Vec::new();
+    };
+    let mut lines = data.split(|&b| b == b'\n');
+    if lines.next() != Some(header(settings).as_bytes()) {
+        return Vec::new();
+    }
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in lines.filter(|l| !l.is_empty()) {
+        let fields: Vec<Vec<u8>> = line.split(|&b| b == b'\t').map(unescape).collect();
+        let parsed = match fields.first().map(Vec::as_slice) {
+            Some(b"D") => parse_dir(&fields).map(|entry| entries.push(entry)).is_some(),
+            Some(b"I") => entries.last_mut().zip(parse_item(&fields)).map(|(e, item)| e.items.push(item)).is_some(),
+            Some(b"E") => entries.last_mut().zip(parse_error(&fields)).map(|(e, err)| e.errors.push(err)).is_some(),
+            _ => false,
+        };
+        if !parsed {
+            // Better to rescan everything than to show half a folder.
+            return Vec::new();
+        }
+    }
+    entries
+}
+
+// Written to a temporary file first, so a crash never leaves half a cache.
+// The temporary file is per process, so two sessions saving at once do not
+// write into the same one; the last to finish wins.
+pub fn save(file: &Path, settings: u64, entries: &[Entry]) -> Result<(), String> {
+    let mut out = header(settings).into_bytes();
+    out.push(b'\n');
+    for entry in entries {
+        line(&mut out, &[b"D", entry.stamp.to_string().as_bytes(), entry.path.as_os_str().as_bytes()]);
+        for item in &entry.items {
+            line(
+                &mut out,
+                &[
+                    b"I",
+                    kind_code(item.kind),
+                    item.size.to_string().as_bytes(),
+                    item.allocated.to_string().as_bytes(),
+                    item.count.to_string().as_bytes(),
+                    item.mtime.to_string().as_bytes(),
+                    item.name.as_bytes(),
+                    item.path.as_os_str().as_bytes(),
+                ],
+            );
+        }
+        for error in &entry.errors {
+            let errno = error.errno.map(|e| e.to_string()).unwrap_or_default();
+            line(
+                &mut out,
+                &[b"E", errno.as_bytes(), error.path.as_os_str().as_bytes(), error.message.as_bytes()],
+            );
+        }
+    }
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    let mut tmp = file.as_os_str().to_os_string();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, out).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, file).map_err(|e| format!("{}: {}", file.display(), e))
+}
+
+fn header(settings: u64) -> String {
+    format!("{}\t{:016x}", HEADER, settings)
+}
+
+// FNV-1a over explicit bytes. The digests are written to the cache file, so
+// they must come out the same from one build to the next, which the
+// standard library's hashers do not promise.
+pub struct Digest(u64);
+
+impl Digest {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn number(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    // The count and the lengths go in too, so ["ab"] and ["a", "b"] differ.
+    pub fn list<T: AsRef<OsStr>>(&mut self, items: &[T]) {
+        self.number(items.len() as u64);
+        for item in items {
+            let bytes = item.as_ref().as_bytes();
+            self.number(bytes.len() as u64);
+            self.write(bytes);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn parse_dir(fields: &[Vec<u8>]) -> Option<Entry> {
+    let [_, stamp, path] = fields else {
+        return None;
+    };
+    Some(Entry {
+        path: to_path(path),
+        stamp: number(stamp)?,
+        items: Vec::new(),
+        errors: Vec::new(),
+    })
+}
+
+fn parse_item(fields: &[Vec<u8>]) -> Option<Item> {
+    let [_, kind, size, allocated, count, mtime, name, path] = fields else {
+        return None;
+    };
+    Some(Item {
+        name: String::from_utf8(name.clone()).ok()?.into(),
+        path: to_path(path).into(),
+        size: number(size)?,
+        allocated: number(allocated)?,
+        kind: kind_from_code(kind)?,
+        count: number(count)?,
+        partial: false,
+        mtime: number(mtime)?,
+    })
+}
+
+fn parse_error(fields: &[Vec<u8>]) -> Option<ScanError> {
+    let [_, errno, path, message] = fields else {
+        return None;
+    };
+    Some(ScanError {
+        path: to_path(path),
+        errno: if errno.is_empty() { None } else { Some(number(errno)?) },
+        message: String::from_utf8(message.clone()).ok()?,
+    })
+}
+
+fn kind_code(kind: ItemKind) -> &'static [u8] {
+    match kind {
+        ItemKind::Dir => b"d",
+        ItemKind::File => b"f",
+        ItemKind::FilesAggregate => b"a",
+        ItemKind::Others => b"o",
+        ItemKind::Extension => b"x",
+        ItemKind::Mount => b"m",
+        ItemKind::FreeSpace => b"s",
+    }
+}
+
+fn kind_from_code(code: &[u8]) -> Option<ItemKind> {
+    match code {
+        b"d" => Some(ItemKind::Dir),
+        b"f" => Some(ItemKind::File),
+        b"a" => Some(ItemKind::FilesAggregate),
+        b"o" => Some(ItemKind::Others),
+        b"x" => Some(ItemKind::Extension),
+        b"m" => Some(ItemKind::Mount),
+        b"s" => Some(ItemKind::FreeSpace),
+        _ => None,
+    }
+}
+
+fn number<T: std::str::FromStr>(field: &[u8]) -> Option<T> {
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
+fn to_path(field: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(field))
+}
+
+fn line(out: &mut Vec<u8>, fields: &[&[u8]]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(b'\t');
+        }
+        for &b in *field {
+            match b {
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b => out.push(b),
+            }
+        }
+    }
+    out.push(b'\n');
+}
+
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut bytes = field.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b't') => out.push(b'\t'),
+            Some(b'n') => out.push(b'\n'),
+            Some(&other) => out.push(other),
+            None => out.push(b'\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_is_fnv1a() {
+        let fnv = |bytes: &[u8]| {
+            let mut digest = Digest::new();
+            digest.write(bytes);
+            digest.finish()
+        };
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn digest_keeps_list_items_apart() {
+        let digest = |items: &[&str]| {
+            let mut digest = Digest::new();
+            digest.list(items);
+            digest.finish()
+        };
+        assert_ne!(digest(&["ab"]), digest(&["a", "b"]));
+        assert_ne!(digest(&[]), digest(&[""]));
+        assert_eq!(digest(&["a", "b"]), digest(&["a", "b"]));
+    }
+}
@@ -0,0 +1,545 @@
+use crate::json;
+use crate::png::Canvas;
+use crate::scan::{self, Node, ScanError};
+use crate::units::{self, format_size};
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const CELL_W: u32 = 8;
+const CELL_H: u32 = 16;
+pub const MARKDOWN_ROWS: usize = 15;
+
+pub struct SvgBlock {
+    pub rect: Rect,
+    pub bg: Color,
+    pub fg: Color,
+    pub label: Option<String>,
+}
+
+pub fn write_html(out_path: &Path, root: &Node, errors: u64, natural: bool) -> Result<(), String> {
+    fs::write(out_path, html_report(root, errors, natural)).map_err(|e| format!("{}: {}", out_path.display(), e))
+}
+
+// `natural` sorts names as natural_sort does in the app, rather than by their
+// code points.
+pub fn html_report(root: &Node, errors: u64, natural: bool) -> String {
+    let mut data = String::new();
+    node_json(root, &mut data);
+    HTML_TEMPLATE
+        .replace("{{TITLE}}", &html_escape(&format!("duviz — {}", scan::display_name(&root.name))))
+        .replace("{{SUMMARY}}", &html_escape(&format!(
+            "{} in {} files, {} errors",
+            format_size(root.size),
+            root.count,
+            errors
+        )))
+        .replace("{{UNITS}}", units::mode().name())
+        .replace("{{NATURAL}}", if natural { "true" } else { "false" })
+        .replace("{{DATA}}", &data.replace('<', "\\u003c"))
+}
+
+pub fn prometheus(root: &Node, errors: u64, elapsed: Duration) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP duviz_dir_bytes Apparent size of the directory in bytes.\n");
+    out.push_str("# TYPE duviz_dir_bytes gauge\n");
+    let subdirs = root.children.iter().filter(|c| c.is_dir).map(|c| (root.path().join(&c.name), c));
+    let dirs = std::iter::once((root.path().to_path_buf(), root)).chain(subdirs);
+    for (path, dir) in dirs.clone() {
+        let _ = writeln!(out, "duviz_dir_bytes{{path={}}} {}", prom_label(&path), dir.size);
+    }
+    out.push_str("# HELP duviz_dir_files Number of files below the directory.\n");
+    out.push_str("# TYPE duviz_dir_files gauge\n");
+    for (path, dir) in dirs {
+        let _ = writeln!(out, "duviz_dir_files{{path={}}} {}", prom_label(&path), dir.count);
+    }
+    let root_label = prom_label(root.path());
+    out.push_str("# HELP duviz_scan_errors Entries that could not be read during the scan.\n");
+    out.push_str("# TYPE duviz_scan_errors gauge\n");
+    let _ = writeln!(out, "duviz_scan_errors{{path={}}} {}", root_label, errors);
+    out.push_str("# HELP duviz_scan_duration_seconds Wall time spent scanning.\n");
+    out.push_str("# TYPE duviz_scan_duration_seconds gauge\n");
+    let _ = writeln!(
+        out,
+        "duviz_scan_duration_seconds{{path={}}} {:.3}",
+        root_label,
+        elapsed.as_secs_f64()
+    );
+    out.push_str("# HELP duviz_last_scan_timestamp_seconds Unix time the scan finished.\n");
+    out.push_str("# TYPE duviz_last_scan_timestamp_seconds gauge\n");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let _ = writeln!(out, "duviz_last_scan_timestamp_seconds{{path={}}} {}", root_label, now);
+    out
+}
+
+fn prom_label(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn markdown(root: &Node, errors: u64, largest: &[(u64, PathBuf)], usage: Option<(u64, u64)>) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut out = String::new();
+    let _ = writeln!(out, "# Disk usage of `{}`\n", root.path().display());
+    let _ = writeln!(out, "- Scanned: {} UTC", timestamp(now));
+    let _ = writeln!(out, "- Total: {} in {} files", md_size(root.size), root.count);
+    let _ = writeln!(out, "- Scan errors: {}", errors);
+    if let Some((used, total)) = usage.filter(|&(_, total)| total > 0) {
+        let _ = writeln!(
+            out,
+            "- Filesystem: {} of {} used ({:.1}%)",
+            format_size(used),
+            format_size(total),
+            used as f64 * 100.0 / total as f64
+        );
+    }
+
+    out.push_str("\n## Top directories\n\n");
+    out.push_str("| Directory | Size | Bytes | Files |\n|---|---:|---:|---:|\n");
+    let dirs: Vec<&Node> = root.children.iter().filter(|c| c.is_dir).take(MARKDOWN_ROWS).collect();
+    for dir in &dirs {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} |",
+            md_cell(&root.path().join(&dir.name)),
+            format_size(dir.size),
+            dir.size,
+            dir.count
+        );
+    }
+    if dirs.is_empty() {
+        out.push_str("| (none) | | | |\n");
+    }
+
+    out.push_str("\n## Largest files\n\n");
+    out.push_str("| File | Size | Bytes |\n|---|---:|---:|\n");
+    for (size, path) in largest {
+        let _ = writeln!(out, "| {} | {} | {} |", md_cell(path), format_size(*size), size);
+    }
+    if largest.is_empty() {
+        out.push_str("| (none) | | |\n");
+    }
+    out
+}
+
+fn md_size(bytes: u64) -> String {
+    match units::mode() {
+        units::UnitMode::Bytes => format_size(bytes),
+        _ => format!("{} ({} bytes)", format_size(bytes), bytes),
+    }
+}
+
+fn md_cell(path: &Path) -> String {
+    path.to_string_lossy().replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn json_line(path: &Path, node: &Node) -> String {
+    format!(
+        "{{\"path\":{},\"type\":\"{}\",\"size\":{},\"files\":{}}}",
+        json::escape(&path.to_string_lossy()),
+        if node.is_dir { "dir" } else { "file" },
+        node.size,
+        node.count
+    )
+}
+
+pub fn write_errors(out_path: &Path, errors: &[ScanError]) -> Result<(), String> {
+    let mut out = String::from("# path\terrno\tmessage\n");
+    for err in errors {
+        let errno = err.errno.map(|e| e.to_string()).unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(out, "{}\t{}\t{}", err.path.display(), errno, err.message);
+    }
+    fs::write(out_path, out).map_err(|e| format!("{}: {}", out_path.display(), e))
+}
+
+pub fn write_delete_script(out_path: &Path, entries: &[(PathBuf, u64)]) -> Result<(), String> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let total: u64 = entries.iter().map(|(_, size)| size).sum();
+    let mut out: Vec<u8> = Vec::new();
+    let header = format!(
+        "#!/bin/sh\n# Generated by duviz at {} UTC.\n# Removes {} paths, {} ({} bytes) in total.\n# Review before running; nothing has been deleted yet.\nset -eu\n\n",
+        timestamp(secs),
+        entries.len(),
+        format_size(total),
+        total
+    );
+    out.extend_from_slice(header.as_bytes());
+    for (path, size) in entries {
+        out.extend_from_slice(format!("# {}\n", format_size(*size)).as_bytes());
+        out.extend_from_slice(b"rm -rf -- ");
+        out.extend_from_slice(&shell_quote(path.as_os_str().as_bytes()));
+        out.push(b'\n');
+    }
+    fs::write(out_path, out).map_err(|e| format!("{}: {}", out_path.display(), e))
+}
+
+fn shell_quote(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len() + 2);
+    out.push(b'\'');
+    for &b in raw {
+        if b == b'\'' {
+            out.extend_from_slice(b"'\\''");
+        } else {
+            out.push(b);
+        }
+    }
+    out.push(b'\'');
+    out
+}
+
+pub fn write_output(out_path: Option<&Path>, content: &str) -> Result<(), String> {
+    let Some(path) = out_path else {
+        print!("{}", content);
+        return Ok(());
+    };
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, content).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn write_svg(out_path: &Path, area: Rect, blocks: &[SvgBlock]) -> Result<(), String> {
+    let width = area.width as u32 * CELL_W;
+    let height = area.height as u32 * CELL_H;
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+         font-family=\"monospace\" font-size=\"{fs}\">",
+        w = width,
+        h = height,
+        fs = CELL_H - 3
+    );
+    let _ = writeln!(svg, "<rect width=\"{}\" height=\"{}\" fill=\"#000000\"/>", width, height);
+    for block in blocks {
+        let x = (block.rect.x - area.x) as u32 * CELL_W;
+        let y = (block.rect.y - area.y) as u32 * CELL_H;
+        let w = block.rect.width as u32 * CELL_W;
+        let h = block.rect.height as u32 * CELL_H;
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            x,
+            y,
+            w,
+            h,
+            color_hex(block.bg)
+        );
+        if let Some(label) = &block.label {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\" fill=\"{}\" xml:space=\"preserve\">{}</text>",
+                x,
+                y + CELL_H - 4,
+                color_hex(block.fg),
+                html_escape(label)
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    fs::write(out_path, svg).map_err(|e| format!("{}: {}", out_path.display(), e))
+}
+
+pub fn write_png(out_path: &Path, width: u16, height: u16, blocks: &[(Rect, Color)]) -> Result<(), String> {
+    let mut canvas = Canvas::new(width as u32, height as u32, [0, 0, 0])?;
+    for (rect, color) in blocks {
+        let (x, y, w, h) = (rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32);
+        if w > 2 && h > 2 {
+            canvas.fill_rect(x + 1, y + 1, w - 2, h - 2, color_rgb(*color));
+        } else {
+            canvas.fill_rect(x, y, w, h, color_rgb(*color));
+        }
+    }
+    fs::write(out_path, canvas.encode()).map_err(|e| format!("{}: {}", out_path.display(), e))
+}
+
+pub fn color_hex(color: Color) -> String {
+    let [r, g, b] = color_rgb(color);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub fn color_rgb(color: Color) -> [u8; 3] {
+    match color {
+        Color::Red => [0xcd, 0x00, 0x00],
+        Color::Green => [0x00, 0xcd, 0x00],
+        Color::Yellow => [0xcd, 0xcd, 0x00],
+        Color::Blue => [0x00, 0x00, 0xee],
+        Color::Magenta => [0xcd, 0x00, 0xcd],
+        Color::Cyan => [0x00, 0xcd, 0xcd],
+        Color::Gray => [0xe5, 0xe5, 0xe5],
+        Color::DarkGray => [0x7f, 0x7f, 0x7f],
+        Color::LightRed => [0xff, 0x00, 0x00],
+        Color::LightGreen => [0x00, 0xff, 0x00],
+        Color::LightYellow => [0xff, 0xff, 0x00],
+        Color::LightBlue => [0x5c, 0x5c, 0xff],
+        Color::LightMagenta => [0xff, 0x00, 0xff],
+        Color::LightCyan => [0x00, 0xff, 0xff],
+        Color::White => [0xff, 0xff, 0xff],
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Indexed(n) => crate::palette::indexed_rgb(n),
+        _ => [0x00, 0x00, 0x00],
+    }
+}
+
+pub fn timestamped_name(prefix: &str, ext: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("{}-{}.{}", prefix, timestamp(secs), ext))
+}
+
+pub fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        y,
+        m,
+        d,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// For people rather than file names; UTC like the timestamps above.
+pub fn date_time(secs: u64) -> String {
+    let rem = secs % 86_400;
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", y, m, d, rem / 3600, (rem % 3600) / 60)
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+pub fn node_json(node: &Node, out: &mut String) {
+    let _ = write!(
+        out,
+        "{{\"name\":{},\"size\":{},\"count\":{},\"dir\":{}",
+        json::escape(&scan::display_name(&node.name)),
+        node.size,
+        node.count,
+        node.is_dir
+    );
+    if !node.children.is_empty() {
+        out.push_str(",\"children\":[");
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            node_json(child, out);
+        }
+        out.push(']');
+    }
+    out.push('}');
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+body { margin: 0; font: 14px sans-serif; background: #1e1e1e; color: #ddd; }
+header { padding: 8px 12px; background: #111; }
+header h1 { font-size: 16px; margin: 0 0 4px; }
+#crumbs span { cursor: pointer; color: #8cf; }
+#crumbs span:hover { text-decoration: underline; }
+#map { position: relative; height: 60vh; margin: 8px 12px; background: #000; }
+.blk { position: absolute; box-sizing: border-box; border: 1px solid #000; overflow: hidden;
+       font-size: 12px; padding: 2px; color: #000; cursor: default; }
+.blk.dir { cursor: zoom-in; }
+#tip { position: fixed; pointer-events: none; background: #222; border: 1px solid #555;
+       padding: 4px 8px; display: none; white-space: nowrap; }
+table { border-collapse: collapse; margin: 8px 12px; width: calc(100% - 24px); }
+th, td { padding: 3px 8px; border-bottom: 1px solid #333; text-align: left; }
+th { cursor: pointer; background: #111; user-select: none; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+</style>
+</head>
+<body>
+<header><h1>{{TITLE}}</h1><div>{{SUMMARY}}</div><div id="crumbs"></div></header>
+<div id="map"></div>
+<div id="tip"></div>
+<table><thead><tr>
+<th data-k="name">Name</th><th data-k="size">Size</th><th data-k="pct">%</th><th data-k="count">Files</th>
+</tr></thead><tbody id="rows"></tbody></table>
+<script>
+const DATA = {{DATA}};
+const UNITS = "{{UNITS}}";
+const NATURAL = {{NATURAL}};
+const COLORS = ["#5b8def", "#4fc1c9", "#6cc46c", "#e5c447", "#c774d6", "#8fb4ff", "#9be39b", "#f2e08a"];
+const FILE_COLOR = "#b0b0b0";
+let stack = [DATA];
+let sortKey = "size", sortDesc = true;
+
+function fmt(b) {
+  if (UNITS === "bytes") return b.toLocaleString("en-US") + " B";
+  const base = UNITS === "si" ? 1000 : 1024;
+  const u = UNITS === "si" ? ["B", "kB", "MB", "GB", "TB"] : ["B", "KiB", "MiB", "GiB", "TiB"];
+  let i = 0, s = b;
+  while (s >= base && i + 1 < u.length) { s /= base; i++; }
+  if (i === 0) return b + " B";
+  return (s >= 100 ? s.toFixed(0) : s >= 10 ? s.toFixed(1) : s.toFixed(2)) + " " + u[i];
+}
+
+function worst(row, side) {
+  const sum = row.reduce((a, r) => a + r.area, 0);
+  let max = 0, min = Infinity;
+  for (const r of row) { max = Math.max(max, r.area); min = Math.min(min, r.area); }
+  return Math.max(side * side * max / (sum * sum), (sum * sum) / (side * side * min));
+}
+
+function squarify(items, x, y, w, h) {
+  const total = items.reduce((a, n) => a + Math.max(n.size, 1), 0);
+  const nodes = items.map(n => ({ node: n, area: Math.max(n.size, 1) / total * w * h }));
+  const out = [];
+  let row = [];
+  while (nodes.length) {
+    const side = Math.min(w, h);
+    const next = nodes[0];
+    if (row.length === 0 || worst(row.concat([next]), side) <= worst(row, side)) {
+      row.push(nodes.shift());
+      continue;
+    }
+    [x, y, w, h] = place(row, x, y, w, h, out);
+    row = [];
+  }
+  if (row.length) place(row, x, y, w, h, out);
+  return out;
+}
+
+function place(row, x, y, w, h, out) {
+  const sum = row.reduce((a, r) => a + r.area, 0);
+  if (w >= h) {
+    const rw = sum / h;
+    let cy = y;
+    for (const r of row) { const rh = r.area / rw; out.push({ node: r.node, x, y: cy, w: rw, h: rh }); cy += rh; }
+    return [x + rw, y, w - rw, h];
+  }
+  const rh = sum / w;
+  let cx = x;
+  for (const r of row) { const rw = r.area / rh; out.push({ node: r.node, x: cx, y, w: rw, h: rh }); cx += rw; }
+  return [x, y + rh, w, h - rh];
+}
+
+function render() {
+  const node = stack[stack.length - 1];
+  const crumbs = document.getElementById("crumbs");
+  crumbs.innerHTML = "";
+  stack.forEach((n, i) => {
+    if (i > 0) crumbs.append(" / ");
+    const s = document.createElement("span");
+    s.textContent = n.name;
+    s.onclick = () => { stack = stack.slice(0, i + 1); render(); };
+    crumbs.append(s);
+  });
+
+  const map = document.getElementById("map");
+  map.innerHTML = "";
+  const W = map.clientWidth, H = map.clientHeight;
+  const kids = (node.children || []).filter(c => c.size > 0 || node.size === 0);
+  squarify(kids, 0, 0, W, H).forEach((r, i) => {
+    const d = document.createElement("div");
+    d.className = "blk" + (r.node.children ? " dir" : "");
+    Object.assign(d.style, {
+      left: r.x + "px", top: r.y + "px", width: r.w + "px", height: r.h + "px",
+      background: r.node.dir ? COLORS[i % COLORS.length] : FILE_COLOR,
+    });
+    if (r.w > 40 && r.h > 14) d.textContent = r.node.name + " " + fmt(r.node.size);
+    d.onmousemove = e => showTip(e, r.node, node);
+    d.onmouseleave = hideTip;
+    if (r.node.children) d.onclick = () => { hideTip(); stack.push(r.node); render(); };
+    map.append(d);
+  });
+
+  const rows = document.getElementById("rows");
+  rows.innerHTML = "";
+  const list = (node.children || []).map(c => ({ c, pct: node.size ? c.size / node.size * 100 : 0 }));
+  list.sort((a, b) => {
+    const va = sortKey === "pct" ? a.pct : a.c[sortKey], vb = sortKey === "pct" ? b.pct : b.c[sortKey];
+    const r = typeof va !== "string" ? va - vb
+      : NATURAL ? va.localeCompare(vb, undefined, { numeric: true, sensitivity: "base" })
+      : va < vb ? -1 : va > vb ? 1 : 0;
+    return sortDesc ? -r : r;
+  });
+  for (const { c, pct } of list) {
+    const tr = document.createElement("tr");
+    tr.innerHTML = "<td></td><td class=num></td><td class=num></td><td class=num></td>";
+    tr.children[0].textContent = c.name + (c.dir ? "/" : "");
+    tr.children[1].textContent = fmt(c.size);
+    tr.children[1].title = c.size + " bytes";
+    tr.children[2].textContent = pct.toFixed(1);
+    tr.children[3].textContent = c.count;
+    if (c.children) { tr.style.cursor = "zoom-in"; tr.onclick = () => { stack.push(c); render(); }; }
+    rows.append(tr);
+  }
+}
+
+function showTip(e, n, parent) {
+  const tip = document.getElementById("tip");
+  const pct = parent.size ? (n.size / parent.size * 100).toFixed(1) : "0";
+  tip.textContent = n.name + " — " + fmt(n.size) + " (" + n.size + " bytes, " + pct + "%, " + n.count + " files)";
+  tip.style.display = "block";
+  tip.style.left = (e.clientX + 12) + "px";
+  tip.style.top = (e.clientY + 12) + "px";
+}
+
+function hideTip() { document.getElementById("tip").style.display = "none"; }
+
+document.querySelectorAll("th").forEach(th => th.onclick = () => {
+  const k = th.dataset.k;
+  sortDesc = sortKey === k ? !sortDesc : k !== "name";
+  sortKey = k;
+  render();
+});
+window.onresize = render;
+render();
+</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,303 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub device: String,
+    pub path: PathBuf,
+    pub fstype: String,
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+pub fn fs_usage(path: &Path) -> Option<(u64, u64)> {
+    let c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut vfs: libc::statvfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statvfs(c.as_ptr(), &mut vfs) };
+    if rc != 0 {
+        return None;
+    }
+    // Some FUSE and sdcardfs mounts on Android leave f_frsize at zero.
+    let frsize = match vfs.f_frsize as u64 {
+        0 => vfs.f_bsize as u64,
+        n => n,
+    };
+    if vfs.f_blocks == 0 || frsize == 0 {
+        return None;
+    }
+    let total = (vfs.f_blocks as u64).saturating_mul(frsize);
+    let avail = (vfs.f_bavail as u64).saturating_mul(frsize);
+    let used = total.saturating_sub(avail);
+    Some((used, total))
+}
+
+// statvfs on macOS reports 32-bit block counts, and the BSDs disagree on
+// its field types, so these go through statfs.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+pub fn fs_usage(path: &Path) -> Option<(u64, u64)> {
+    let c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statfs(c.as_ptr(), &mut st) };
+    if rc != 0 {
+        return None;
+    }
+    let bsize = st.f_bsize as u64;
+    let total = (st.f_blocks as u64).saturating_mul(bsize);
+    // f_bavail is signed on the BSDs and goes negative once the reserve is in use.
+    let avail = (st.f_bavail as i64).max(0) as u64;
+    let avail = avail.saturating_mul(bsize);
+    Some((total.saturating_sub(avail), total))
+}
+
+// The mount with the longest matching mount point.
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+pub fn device_of(path: &Path) -> Option<String> {
+    let canon = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    mount_table()
+        .into_iter()
+        .filter(|m| canon.starts_with(&m.path))
+        .max_by_key(|m| m.path.as_os_str().len())
+        .map(|m| m.device)
+}
+
+// statfs names the device directly. Matching paths against the mount table
+// would go wrong on macOS, where /Users and /Applications live on the data
+// volume behind firmlinks while their paths start at the system volume's /.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+pub fn device_of(path: &Path) -> Option<String> {
+    let c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(st.f_mntfromname.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub device: String,
+    pub path: PathBuf,
+    pub fstype: String,
+    pub used: u64,
+    pub total: u64,
+    pub removable: bool,
+}
+
+// Kernel and runtime filesystems that hold no user data worth browsing.
+pub const PSEUDO_FSTYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "tmpfs",
+    "cgroup",
+    "cgroup2",
+    "securityfs",
+    "pstore",
+    "bpf",
+    "tracefs",
+    "debugfs",
+    "mqueue",
+    "hugetlbfs",
+    "configfs",
+    "fusectl",
+    "autofs",
+    "binfmt_misc",
+    "efivarfs",
+    "rpc_pipefs",
+    "nsfs",
+    "devfs",
+    "fdescfs",
+];
+
+// Where filesystems of these types are mounted, e.g. /sys and /dev on Linux.
+// "/" is never one of them, whatever it is.
+pub fn pseudo_mounts(fstypes: &[String]) -> Vec<PathBuf> {
+    if fstypes.is_empty() {
+        return Vec::new();
+    }
+    mount_table()
+        .into_iter()
+        .filter(|m| m.path != Path::new("/") && fstypes.contains(&m.fstype))
+        .map(|m| m.path)
+        .collect()
+}
+
+// Every mounted filesystem with data on it, once per device (bind mounts
+// and btrfs subvolumes keep the shortest mount point).
+pub fn volumes() -> Vec<Volume> {
+    let mut out: Vec<Volume> = Vec::new();
+    let mut mounts = mount_table();
+    mounts.sort_by_key(|m| m.path.as_os_str().len());
+    for m in mounts {
+        if PSEUDO_FSTYPES.contains(&m.fstype.as_str()) || out.iter().any(|v| v.device == m.device) {
+            continue;
+        }
+        let Some((used, total)) = fs_usage(&m.path) else {
+            continue;
+        };
+        if total == 0 {
+            continue;
+        }
+        out.push(Volume {
+            removable: is_removable(&m.device, &m.path),
+            device: m.device,
+            path: m.path,
+            fstype: m.fstype,
+            used,
+            total,
+        });
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    out
+}
+
+// Linux exposes the removable flag per disk in sysfs; elsewhere fall back to
+// the usual automount locations.
+fn is_removable(device: &str, path: &Path) -> bool {
+    if let Some(name) = device.strip_prefix("/dev/") {
+        let dev = Path::new("/sys/class/block").join(name);
+        let disk = fs::canonicalize(&dev).ok().and_then(|p| {
+            if p.join("partition").exists() {
+                p.parent().map(Path::to_path_buf)
+            } else {
+                Some(p)
+            }
+        });
+        if let Some(flag) = disk.and_then(|d| fs::read_to_string(d.join("removable")).ok()) {
+            return flag.trim() == "1";
+        }
+    }
+    ["/media", "/run/media", "/Volumes"].iter().any(|p| path.starts_with(p))
+}
+
+pub fn is_wsl() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+    std::env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease").is_ok_and(|r| r.to_ascii_lowercase().contains("microsoft"))
+}
+
+fn is_android() -> bool {
+    cfg!(target_os = "android") || std::env::var_os("TERMUX_VERSION").is_some()
+}
+
+// Shared storage on Android is exposed again under several aliases and
+// per-permission views of the same files. Skipping them keeps a scan of /
+// or /storage from counting the user's files several times.
+pub fn android_duplicate_views() -> Vec<PathBuf> {
+    if !is_android() {
+        return Vec::new();
+    }
+    [
+        "/sdcard",
+        "/mnt/sdcard",
+        "/storage/self",
+        "/storage/emulated/legacy",
+        "/mnt/runtime",
+        "/mnt/user",
+        "/mnt/pass_through",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect()
+}
+
+// Windows drives show up as drvfs (WSL 1) or 9p (WSL 2) mounts whose source
+// is the drive root, e.g. "C:\". Returns each mount point with its drive
+// letter, or nothing outside WSL.
+pub fn windows_drives() -> Vec<(PathBuf, String)> {
+    if !is_wsl() {
+        return Vec::new();
+    }
+    mount_table()
+        .into_iter()
+        .filter(|m| m.fstype == "drvfs" || m.fstype == "9p")
+        .filter_map(|m| {
+            let letter = m.device.chars().next().filter(|c| c.is_ascii_alphabetic())?;
+            m.device[1..].starts_with(':').then(|| (m.path, format!("{}:", letter.to_ascii_uppercase())))
+        })
+        .collect()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn mount_table() -> Vec<Mount> {
+    // Android may deny /proc/self/mounts to apps while still allowing /proc/mounts.
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts").or_else(|_| fs::read_to_string("/proc/mounts")) else {
+        return Vec::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let device = unescape_mount_field(parts.next()?);
+            let path = PathBuf::from(unescape_mount_field(parts.next()?));
+            let fstype = parts.next().unwrap_or_default().to_string();
+            Some(Mount { device, path, fstype })
+        })
+        .collect()
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+pub fn mount_table() -> Vec<Mount> {
+    use std::ffi::CStr;
+    let mut buf: *mut libc::statfs = std::ptr::null_mut();
+    // getmntinfo owns the buffer; it stays valid until the next call.
+    let n = unsafe { libc::getmntinfo(&mut buf, libc::MNT_NOWAIT) };
+    if n <= 0 || buf.is_null() {
+        return Vec::new();
+    }
+    let entries = unsafe { std::slice::from_raw_parts(buf, n as usize) };
+    let text = |field: &[libc::c_char]| unsafe { CStr::from_ptr(field.as_ptr()) }.to_string_lossy().into_owned();
+    entries
+        .iter()
+        .map(|st| Mount {
+            device: text(&st.f_mntfromname),
+            path: PathBuf::from(text(&st.f_mntonname)),
+            fstype: text(&st.f_fstypename),
+        })
+        .collect()
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd"
+)))]
+pub fn mount_table() -> Vec<Mount> {
+    Vec::new()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unescape_mount_field(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            let a = chars.next();
+            let b = chars.next();
+            let c = chars.next();
+            match (a, b, c) {
+                (Some('0'), Some('4'), Some('0')) => out.push(' '),
+                (Some('0'), Some('1'), Some('1')) => out.push('\t'),
+                (Some('0'), Some('1'), Some('2')) => out.push('\n'),
+                (Some('1'), Some('3'), Some('4')) => out.push('\\'),
+                (Some(x), Some(y), Some(z)) => {
+                    out.push('\\');
+                    out.push(x);
+                    out.push(y);
+                    out.push(z);
+                }
+                _ => out.push('\\'),
+            }
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
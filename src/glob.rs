@@ -0,0 +1,69 @@
+// Shell wildcards as du --exclude takes them: * matches any run of characters
+// (slashes included), ? any one character, [abc], [a-z] and [!abc] one from a
+// set, and a backslash makes the next character literal.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Where the last * was and how much text it has taken so far, to retry
+    // with one more character when the rest does not match.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if p.get(pi) == Some(&'*') {
+            star = Some((pi, ti));
+            pi += 1;
+            continue;
+        }
+        if let Some(len) = p.get(pi..).filter(|rest| !rest.is_empty()).and_then(|rest| one(rest, t[ti])) {
+            pi += len;
+            ti += 1;
+            continue;
+        }
+        match star {
+            Some((sp, st)) => {
+                pi = sp + 1;
+                ti = st + 1;
+                star = Some((sp, st + 1));
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// Whether the pattern element at the start of `p` matches `c`, and how many
+// pattern characters it spans.
+fn one(p: &[char], c: char) -> Option<usize> {
+    match p[0] {
+        '?' => Some(1),
+        '\\' if p.len() > 1 => (p[1] == c).then_some(2),
+        '[' => match set_end(p) {
+            Some(close) => {
+                let negate = matches!(p[1], '!' | '^');
+                let set = &p[if negate { 2 } else { 1 }..close];
+                let mut hit = false;
+                let mut i = 0;
+                while i < set.len() {
+                    if i + 2 < set.len() && set[i + 1] == '-' {
+                        hit |= set[i] <= c && c <= set[i + 2];
+                        i += 3;
+                    } else {
+                        hit |= set[i] == c;
+                        i += 1;
+                    }
+                }
+                (hit != negate).then_some(close + 1)
+            }
+            // An unclosed bracket is an ordinary character.
+            None => (c == '[').then_some(1),
+        },
+        x => (x == c).then_some(1),
+    }
+}
+
+// Index of the ] closing the set that starts `p`. A ] right after the opening
+// bracket (or its negation) is a member, not the end.
+fn set_end(p: &[char]) -> Option<usize> {
+    let first = if matches!(p.get(1), Some('!' | '^')) { 2 } else { 1 };
+    p.get(first + 1..)?.iter().position(|&x| x == ']').map(|i| first + 1 + i)
+}
@@ -0,0 +1,246 @@
+use crate::palette;
+use crate::png::Canvas;
+use std::collections::HashMap;
+use std::env;
+
+// The treemap as a picture, on terminals that can show one: the kitty
+// graphics protocol (kitty, WezTerm, Ghostty, Konsole) or Sixel (foot,
+// mlterm, xterm -ti vt340, Windows Terminal and others). The cells are still
+// drawn underneath, so the picture only ever adds to them and anything that
+// covers it (a dialog, the list, a new scan) simply takes it away.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Auto,
+    Kitty,
+    Sixel,
+    Off,
+}
+
+impl Mode {
+    pub fn parse(value: &str) -> Option<Mode> {
+        match value {
+            "auto" => Some(Mode::Auto),
+            "kitty" => Some(Mode::Kitty),
+            "sixel" => Some(Mode::Sixel),
+            "off" => Some(Mode::Off),
+            _ => None,
+        }
+    }
+}
+
+pub trait ImageProtocol {
+    // The bytes that show `canvas` with its top left corner at the cursor,
+    // over `cols` by `rows` cells, replacing the one shown before.
+    fn show(&self, canvas: &Canvas, cols: u16, rows: u16) -> Vec<u8>;
+    // The bytes that take it away again. Empty when the picture is gone as
+    // soon as the cells under it are drawn again.
+    fn hide(&self) -> Vec<u8>;
+    // Whether text in the cells stays on top of the picture, rather than
+    // having to be written again after it.
+    fn text_on_top(&self) -> bool;
+    // Whether the picture may use more colors than fit in a palette.
+    fn gradients(&self) -> bool;
+}
+
+// Asks the terminal, unless the config says which. Inside tmux or screen the
+// escape sequences would need wrapping, so auto leaves them alone.
+pub fn detect(mode: Mode) -> Option<Box<dyn ImageProtocol>> {
+    match mode {
+        Mode::Off => None,
+        Mode::Kitty => Some(Box::new(Kitty)),
+        Mode::Sixel => Some(Box::new(Sixel)),
+        Mode::Auto => {
+            let term = env::var("TERM").unwrap_or_default();
+            let multiplexed = env::var_os("TMUX").is_some() || term.starts_with("screen") || term.starts_with("tmux");
+            if multiplexed || term.is_empty() || term == "linux" || term == "dumb" || cell_size().is_none() {
+                return None;
+            }
+            // A kitty query with a made-up one pixel image, then the device
+            // attributes, which every terminal answers; 4 among them is Sixel.
+            let query = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+            let reply = palette::query_terminal(query, std::time::Duration::from_millis(300))?;
+            if reply.contains("_Gi=31;OK") {
+                return Some(Box::new(Kitty));
+            }
+            let attributes = reply.rfind("\x1b[?").map(|i| &reply[i + 3..])?;
+            let attributes = &attributes[..attributes.find('c')?];
+            attributes.split(';').any(|a| a == "4").then(|| Box::new(Sixel) as Box<dyn ImageProtocol>)
+        }
+    }
+}
+
+// The size of a character cell in pixels, if the terminal tells.
+pub fn cell_size() -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    if size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 || size.ws_ypixel == 0 {
+        return None;
+    }
+    Some((size.ws_xpixel / size.ws_col, size.ws_ypixel / size.ws_row)).filter(|&(w, h)| w > 1 && h > 1)
+}
+
+const KITTY_ID: u32 = 4242;
+
+// Sent as PNG in base64 pieces of at most 4096 bytes, placed under the text
+// (z=-1) but over the cell backgrounds. q=2 keeps the terminal from answering,
+// which would show up as input.
+struct Kitty;
+
+impl ImageProtocol for Kitty {
+    fn show(&self, canvas: &Canvas, cols: u16, rows: u16) -> Vec<u8> {
+        let data = base64(&canvas.encode());
+        let mut out = self.hide();
+        let mut pieces = data.as_bytes().chunks(4096).peekable();
+        let mut first = true;
+        while let Some(piece) = pieces.next() {
+            let more = u8::from(pieces.peek().is_some());
+            if first {
+                out.extend_from_slice(
+                    format!("\x1b_Ga=T,f=100,i={},q=2,C=1,z=-1,c={},r={},m={};", KITTY_ID, cols, rows, more).as_bytes(),
+                );
+                first = false;
+            } else {
+                out.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+            }
+            out.extend_from_slice(piece);
+            out.extend_from_slice(b"\x1b\\");
+        }
+        out
+    }
+
+    fn hide(&self) -> Vec<u8> {
+        format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", KITTY_ID).into_bytes()
+    }
+
+    fn text_on_top(&self) -> bool {
+        true
+    }
+
+    fn gradients(&self) -> bool {
+        true
+    }
+}
+
+// Sixel draws over the cells, so the text is written again afterwards, and
+// it takes a palette of at most 256 colors.
+struct Sixel;
+
+impl ImageProtocol for Sixel {
+    fn show(&self, canvas: &Canvas, _cols: u16, _rows: u16) -> Vec<u8> {
+        sixel(canvas)
+    }
+
+    fn hide(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn text_on_top(&self) -> bool {
+        false
+    }
+
+    fn gradients(&self) -> bool {
+        false
+    }
+}
+
+// Six rows of pixels at a time: per color, a character for each column with
+// a bit for each of the six rows that has it, runs of the same character
+// written as !N. A picture of more than 256 colors is reduced to a 6x6x6 cube.
+fn sixel(canvas: &Canvas) -> Vec<u8> {
+    let (width, height) = (canvas.width as usize, canvas.height as usize);
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut index: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let rgb = canvas.rgb(x as u32, y as u32);
+            let i = match index.get(&rgb) {
+                Some(&i) => i,
+                None if colors.len() < 256 => {
+                    index.insert(rgb, colors.len() as u8);
+                    colors.push(rgb);
+                    (colors.len() - 1) as u8
+                }
+                None => return sixel_cube(canvas),
+            };
+            pixels.push(i);
+        }
+    }
+    encode_sixel(width, height, &colors, &pixels)
+}
+
+fn sixel_cube(canvas: &Canvas) -> Vec<u8> {
+    let level = |c: u8| (c as u16 * 6 / 256) as u8;
+    let colors: Vec<[u8; 3]> = (0..216u16).map(|i| [i / 36, i / 6 % 6, i % 6].map(|l| (l * 51) as u8)).collect();
+    let (width, height) = (canvas.width as usize, canvas.height as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = canvas.rgb(x as u32, y as u32).map(level);
+            pixels.push(r * 36 + g * 6 + b);
+        }
+    }
+    encode_sixel(width, height, &colors, &pixels)
+}
+
+fn encode_sixel(width: usize, height: usize, colors: &[[u8; 3]], pixels: &[u8]) -> Vec<u8> {
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height).into_bytes();
+    for (i, [r, g, b]) in colors.iter().enumerate() {
+        let percent = |c: &u8| *c as u32 * 100 / 255;
+        out.extend_from_slice(format!("#{};2;{};{};{}", i, percent(r), percent(g), percent(b)).as_bytes());
+    }
+    let mut bits: Vec<Option<Vec<u8>>> = vec![None; colors.len()];
+    for top in (0..height).step_by(6) {
+        for row in top..(top + 6).min(height) {
+            for x in 0..width {
+                let color = pixels[row * width + x] as usize;
+                bits[color].get_or_insert_with(|| vec![0; width])[x] |= 1 << (row - top);
+            }
+        }
+        let mut first = true;
+        for (color, line) in bits.iter_mut().enumerate() {
+            let Some(line) = line.take() else {
+                continue;
+            };
+            if !first {
+                out.push(b'$');
+            }
+            first = false;
+            out.extend_from_slice(format!("#{}", color).as_bytes());
+            let mut x = 0;
+            while x < width {
+                let run = line[x..].iter().take_while(|&&b| b == line[x]).count();
+                let c = b'?' + line[x];
+                if run > 3 {
+                    out.extend_from_slice(format!("!{}", run).as_bytes());
+                    out.push(c);
+                } else {
+                    out.extend(std::iter::repeat_n(c, run));
+                }
+                x += run;
+            }
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,133 @@
+use crate::config::{Hook, HookEvent};
+use crate::json;
+use crate::log;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+pub enum Field {
+    Text(String),
+    Number(u64),
+    // Shown with one decimal, empty (null in JSON) when unknown.
+    Decimal(Option<f64>),
+}
+
+impl Field {
+    fn env(&self) -> String {
+        match self {
+            Field::Text(text) => text.clone(),
+            Field::Number(n) => n.to_string(),
+            Field::Decimal(d) => d.map(|d| format!("{:.1}", d)).unwrap_or_default(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Field::Text(text) => json::escape(text),
+            Field::Number(n) => n.to_string(),
+            Field::Decimal(d) => d.map(|d| format!("{:.1}", d)).unwrap_or_else(|| "null".to_string()),
+        }
+    }
+}
+
+pub type Fields = Vec<(&'static str, Field)>;
+
+// Runs the hooks registered for a pre- event one after the other. The first
+// failure is returned so the caller can call the action off.
+pub fn run(hooks: &[Hook], event: HookEvent, fields: &Fields) -> Result<(), String> {
+    for hook in hooks.iter().filter(|h| h.on == Some(event)) {
+        log::info!("hook", "{} on {}", hook.name, event.name());
+        fire(hook, event.name(), fields).map_err(|e| format!("{} hook '{}': {}", event.name(), hook.name, e))?;
+    }
+    Ok(())
+}
+
+// Post- events have nothing left to stop, so their hooks run on their own
+// thread and failures only go to the log.
+pub fn spawn(hooks: &[Hook], event: HookEvent, fields: Fields) {
+    let hooks: Vec<Hook> = hooks.iter().filter(|h| h.on == Some(event)).cloned().collect();
+    if hooks.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        for hook in &hooks {
+            log::info!("hook", "{} on {}", hook.name, event.name());
+            if let Err(err) = fire(hook, event.name(), &fields) {
+                log::warning!("hook", "{} hook '{}': {}", event.name(), hook.name, err);
+            }
+        }
+    });
+}
+
+// Every field is passed as DUVIZ_<NAME> and as a key of the JSON object that
+// a command reads on stdin and a webhook receives.
+pub fn fire(hook: &Hook, event: &str, fields: &Fields) -> Result<(), String> {
+    let mut body = format!("{{\"hook\":{},\"event\":{}", json::escape(&hook.name), json::escape(event));
+    for (name, value) in fields {
+        body.push_str(&format!(",\"{}\":{}", name, value.json()));
+    }
+    body.push('}');
+    if let Some(command) = &hook.command {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("DUVIZ_HOOK", &hook.name)
+            .env("DUVIZ_EVENT", event)
+            .envs(fields.iter().map(|(name, value)| (format!("DUVIZ_{}", name.to_uppercase()), value.env())))
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run command: {}", e))?;
+        // Commands that never read stdin close it early; that is fine.
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = writeln!(stdin, "{}", body);
+        }
+        let status = child.wait().map_err(|e| format!("cannot run command: {}", e))?;
+        if !status.success() {
+            return Err(format!("command exited with {}", status));
+        }
+    }
+    if let Some(url) = &hook.webhook {
+        post(url, &body)?;
+    }
+    Ok(())
+}
+
+fn post(url: &str, body: &str) -> Result<(), String> {
+    let rest = url.strip_prefix("http://").ok_or("only http:// webhooks are supported")?;
+    let (authority, target) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr_text = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let addr = addr_text
+        .to_socket_addrs()
+        .map_err(|e| format!("{}: {}", authority, e))?
+        .next()
+        .ok_or_else(|| format!("{}: no address", authority))?;
+    let timeout = Duration::from_secs(10);
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| format!("{}: {}", authority, e))?;
+    let _ = stream.set_read_timeout(Some(timeout));
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        target,
+        authority,
+        body.len(),
+        body
+    )
+    .map_err(|e| format!("{}: {}", authority, e))?;
+    let mut status = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut status)
+        .map_err(|e| format!("{}: {}", authority, e))?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("webhook answered '{}'", status.trim())),
+    }
+}
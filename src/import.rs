@@ -0,0 +1,175 @@
+use crate::ncdu;
+use crate::scan::Node;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+#[derive(Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    files: BTreeMap<String, u64>,
+}
+
+// Reads `path,bytes` rows (comma or tab separated, optionally quoted, with an
+// optional header line) and assembles them into a tree rooted at "/". An
+// ncdu export is read as the tree it already is.
+pub fn read_listing(path: &Path) -> Result<Node, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_listing(&text, path)
+}
+
+// `path` only names the listing in errors.
+fn parse_listing(text: &str, path: &Path) -> Result<Node, String> {
+    if text.trim_start().starts_with('[') {
+        return ncdu::read(text).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let mut root = Dir::default();
+    let mut rows = 0usize;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sep = if line.contains('\t') { '\t' } else { ',' };
+        let fields = split_fields(line, sep);
+        let (name, size) = match (fields.first(), fields.get(1)) {
+            (Some(name), Some(size)) => (name.trim(), size.trim()),
+            _ => return Err(format!("{}:{}: expected 'path{}bytes'", path.display(), i + 1, sep)),
+        };
+        let Ok(size) = size.parse::<u64>() else {
+            if i == 0 {
+                continue;
+            }
+            return Err(format!("{}:{}: invalid size '{}'", path.display(), i + 1, size));
+        };
+        insert(&mut root, name, size);
+        rows += 1;
+    }
+    if rows == 0 {
+        return Err(format!("{}: no rows found", path.display()));
+    }
+    Ok(build(root, "/".into()))
+}
+
+fn split_fields(line: &str, sep: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == sep && !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn insert(root: &mut Dir, path: &str, size: u64) {
+    let is_dir_marker = path.ends_with('/');
+    let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty() && *p != ".").collect();
+    let file = if is_dir_marker { None } else { parts.pop() };
+    let mut dir = root;
+    for part in parts {
+        dir = dir.dirs.entry(part.to_string()).or_default();
+    }
+    if let Some(file) = file {
+        let entry = dir.files.entry(file.to_string()).or_insert(0);
+        *entry = entry.saturating_add(size);
+    }
+}
+
+fn build(dir: Dir, name: OsString) -> Node {
+    let mut node = Node {
+        name,
+        size: 0,
+        allocated: 0,
+        count: 0,
+        is_dir: true,
+        mount: false,
+        mtime: 0,
+        children: Vec::new(),
+    };
+    for (name, child) in dir.dirs {
+        let child = build(child, name.into());
+        node.size = node.size.saturating_add(child.size);
+        node.allocated = node.allocated.saturating_add(child.allocated);
+        node.count += child.count;
+        node.children.push(child);
+    }
+    for (name, size) in dir.files {
+        node.size = node.size.saturating_add(size);
+        node.allocated = node.allocated.saturating_add(size);
+        node.count += 1;
+        node.children.push(Node {
+            name: name.into(),
+            size,
+            // Listings only carry one size, so it stands in for both.
+            allocated: size,
+            count: 1,
+            is_dir: false,
+            mount: false,
+            mtime: 0,
+            children: Vec::new(),
+        });
+    }
+    node.children.sort_by_key(|c| std::cmp::Reverse(c.size));
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // The files of the tree as `path,bytes` rows again.
+    fn rows(node: &Node, path: PathBuf, out: &mut Vec<(String, u64)>) {
+        for child in &node.children {
+            let child_path = path.join(&child.name);
+            if child.is_dir {
+                rows(child, child_path, out);
+            } else {
+                out.push((child_path.to_string_lossy().into_owned(), child.size));
+            }
+        }
+    }
+
+    fn listed(text: &str) -> Vec<(String, u64)> {
+        let root = parse_listing(text, Path::new("test.csv")).unwrap();
+        let mut out = Vec::new();
+        rows(&root, PathBuf::from("/"), &mut out);
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn quoted_fields_keep_commas_and_quotes() {
+        let text = "\"/a/b, c.txt\",10\n\"/a/say \"\"hi\"\"\",5\n/a/plain,1\n";
+        let expected = [("/a/b, c.txt", 10), ("/a/plain", 1), ("/a/say \"hi\"", 5)];
+        assert_eq!(listed(text), expected.map(|(p, s)| (p.to_string(), s)));
+    }
+
+    #[test]
+    fn crlf_tabs_and_a_header_are_read() {
+        let crlf = "path,bytes\r\n/x/y,3\r\n/x/z,4\r\n";
+        assert_eq!(listed(crlf), [("/x/y".to_string(), 3), ("/x/z".to_string(), 4)]);
+        let tabs = "/x/with, comma\t3\r\n/x/z\t4\n";
+        assert_eq!(listed(tabs), [("/x/with, comma".to_string(), 3), ("/x/z".to_string(), 4)]);
+        let root = parse_listing(crlf, Path::new("test.csv")).unwrap();
+        assert_eq!((root.size, root.count), (7, 2));
+    }
+
+    #[test]
+    fn bad_rows_are_reported_with_their_line() {
+        let err = parse_listing("/a,1\n/b,lots\n", Path::new("test.csv")).err().unwrap();
+        assert_eq!(err, "test.csv:2: invalid size 'lots'");
+        assert!(parse_listing("path,bytes\n", Path::new("test.csv")).is_err());
+    }
+}
@@ -0,0 +1,439 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// Everything a key can do on the main screen. The dialogs (delete
+// confirmation, filesystem list, help) keep their own fixed keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // In the list these move along the rows. Elsewhere they do nothing, so a
+    // key bound to one of them and to another action falls through to that.
+    RowUp,
+    RowDown,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    Quit,
+    Help,
+    Up,
+    Back,
+    Forward,
+    Next,
+    Previous,
+    Open,
+    Launch,
+    Reveal,
+    Shell,
+    Mark,
+    Delete,
+    Trash,
+    DeleteFolder,
+    DeleteFolderPermanently,
+    Refresh,
+    OneFileSystem,
+    FreeSpace,
+    Files,
+    Types,
+    Largest,
+    List,
+    Search,
+    Find,
+    SizeMode,
+    Units,
+    Sort,
+    Labels,
+    AgeColors,
+    Palette,
+    Volumes,
+    GoTo,
+    Compare,
+    Bookmark,
+    Bookmarks,
+    ExportSvg,
+    ExportPng,
+    ExportErrors,
+    Errors,
+    DeleteScript,
+    Debug,
+    LogLevel,
+}
+
+impl Action {
+    const ALL: [Action; 49] = [
+        Action::RowUp,
+        Action::RowDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::First,
+        Action::Last,
+        Action::Quit,
+        Action::Help,
+        Action::Up,
+        Action::Back,
+        Action::Forward,
+        Action::Next,
+        Action::Previous,
+        Action::Open,
+        Action::Launch,
+        Action::Reveal,
+        Action::Shell,
+        Action::Mark,
+        Action::Delete,
+        Action::Trash,
+        Action::DeleteFolder,
+        Action::DeleteFolderPermanently,
+        Action::Refresh,
+        Action::OneFileSystem,
+        Action::FreeSpace,
+        Action::Files,
+        Action::Types,
+        Action::Largest,
+        Action::List,
+        Action::Search,
+        Action::Find,
+        Action::SizeMode,
+        Action::Units,
+        Action::Sort,
+        Action::Labels,
+        Action::AgeColors,
+        Action::Palette,
+        Action::Volumes,
+        Action::GoTo,
+        Action::Compare,
+        Action::Bookmark,
+        Action::Bookmarks,
+        Action::ExportSvg,
+        Action::ExportPng,
+        Action::ExportErrors,
+        Action::Errors,
+        Action::DeleteScript,
+        Action::Debug,
+        Action::LogLevel,
+    ];
+
+    // The name used in the [keys] section of the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::RowUp => "row_up",
+            Action::RowDown => "row_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Up => "up",
+            Action::Back => "back",
+            Action::Forward => "forward",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::Open => "open",
+            Action::Launch => "launch",
+            Action::Reveal => "reveal",
+            Action::Shell => "shell",
+            Action::Mark => "mark",
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+            Action::DeleteFolder => "delete_folder",
+            Action::DeleteFolderPermanently => "delete_folder_permanently",
+            Action::Refresh => "refresh",
+            Action::OneFileSystem => "one_file_system",
+            Action::FreeSpace => "free_space",
+            Action::Files => "files",
+            Action::Types => "types",
+            Action::Largest => "largest",
+            Action::List => "list",
+            Action::Search => "search",
+            Action::Find => "find",
+            Action::SizeMode => "size_mode",
+            Action::Units => "units",
+            Action::Sort => "sort",
+            Action::Labels => "labels",
+            Action::AgeColors => "age_colors",
+            Action::Palette => "palette",
+            Action::Volumes => "volumes",
+            Action::GoTo => "go_to",
+            Action::Compare => "compare",
+            Action::Bookmark => "bookmark",
+            Action::Bookmarks => "bookmarks",
+            Action::ExportSvg => "export_svg",
+            Action::ExportPng => "export_png",
+            Action::ExportErrors => "export_errors",
+            Action::Errors => "errors",
+            Action::DeleteScript => "delete_script",
+            Action::Debug => "debug",
+            Action::LogLevel => "log_level",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    fn default_keys(self) -> &'static str {
+        match self {
+            Action::RowUp => "Up",
+            Action::RowDown => "Down",
+            Action::PageUp => "PageUp",
+            Action::PageDown => "PageDown",
+            Action::First => "Home",
+            Action::Last => "End",
+            Action::Quit => "q",
+            Action::Help => "? F1",
+            Action::Up => "Backspace h Left Up Esc",
+            Action::Back => "Alt+Left [",
+            Action::Forward => "Alt+Right ]",
+            Action::Next => "Tab j Down",
+            Action::Previous => "Shift+Tab k",
+            Action::Open => "Enter Right",
+            Action::Launch => "o",
+            Action::Reveal => "O",
+            Action::Shell => "!",
+            Action::Mark => "Space",
+            Action::Delete => "d",
+            Action::Trash => "t",
+            Action::DeleteFolder => "Delete",
+            Action::DeleteFolderPermanently => "Shift+Delete",
+            Action::Refresh => "r",
+            Action::OneFileSystem => "m",
+            Action::FreeSpace => "F",
+            Action::Files => "f",
+            Action::Types => "e",
+            Action::Largest => "T",
+            Action::List => "l",
+            Action::Search => "/",
+            Action::Find => "Ctrl+f",
+            Action::SizeMode => "a",
+            Action::Units => "u",
+            Action::Sort => "s",
+            Action::Labels => "%",
+            Action::AgeColors => "c",
+            Action::Palette => "C",
+            Action::Volumes => "V",
+            Action::GoTo => "g",
+            Action::Compare => "=",
+            Action::Bookmark => "b",
+            Action::Bookmarks => "B",
+            Action::ExportSvg => "x",
+            Action::ExportPng => "X",
+            Action::ExportErrors => "E",
+            Action::Errors => "Ctrl+e",
+            Action::DeleteScript => "D",
+            Action::Debug => "F12",
+            Action::LogLevel => "F11",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    // Shift is part of what a character or Shift+Tab already is, and
+    // terminals disagree on whether they report it as well. So Shift+a is A.
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let shift = modifiers.contains(KeyModifiers::SHIFT);
+        let (code, modifiers) = match code {
+            KeyCode::Tab if shift => (KeyCode::BackTab, modifiers),
+            KeyCode::Char(c) if shift && c.is_lowercase() => {
+                let mut upper = c.to_uppercase();
+                match (upper.next(), upper.next()) {
+                    (Some(u), None) => (KeyCode::Char(u), modifiers),
+                    _ => (code, modifiers),
+                }
+            }
+            _ => (code, modifiers),
+        };
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Key { code, modifiers: modifiers & (KeyModifiers::SHIFT | KeyModifiers::CONTROL | KeyModifiers::ALT) }
+    }
+
+    // "q", "F1", "Enter", "Ctrl+r", "Shift+Delete" and so on.
+    pub fn parse(text: &str) -> Result<Key, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // A lone "+" is the key itself, not a separator.
+        while let Some((modifier, tail)) = rest.split_once('+').filter(|(_, tail)| !tail.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+            };
+            rest = tail;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", text)),
+                },
+            },
+        };
+        Ok(Key::new(code, modifiers))
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        let labels = [(KeyModifiers::CONTROL, "Ctrl+"), (KeyModifiers::ALT, "Alt+"), (KeyModifiers::SHIFT, "Shift+")];
+        for (modifier, label) in labels {
+            if self.modifiers.contains(modifier) {
+                name.push_str(label);
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => name.push_str("Space"),
+            KeyCode::Char(c) => name.push(c),
+            KeyCode::BackTab => name.push_str("Shift+Tab"),
+            KeyCode::PageUp => name.push_str("PgUp"),
+            KeyCode::PageDown => name.push_str("PgDn"),
+            KeyCode::F(n) => name.push_str(&format!("F{}", n)),
+            code => name.push_str(&format!("{:?}", code)),
+        }
+        name
+    }
+}
+
+// Which keys do what. A key may be bound to several actions; they are tried
+// in the order of Action::ALL until one applies.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let keys = action.default_keys().split_whitespace().filter_map(|k| Key::parse(k).ok()).collect();
+                (action, keys)
+            })
+            .collect();
+        KeyMap { bindings }
+    }
+}
+
+impl KeyMap {
+    // Replaces the keys of `action` with a space-separated list. The keys are
+    // taken away from whatever they did before, so a remap never leaves two
+    // actions fighting over one key.
+    pub fn bind(&mut self, action: Action, keys: &str) -> Result<(), String> {
+        let keys = keys.split_whitespace().map(Key::parse).collect::<Result<Vec<Key>, String>>()?;
+        for (other, bound) in &mut self.bindings {
+            if *other == action {
+                bound.clone_from(&keys);
+            } else {
+                bound.retain(|k| !keys.contains(k));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn actions(&self, event: &KeyEvent) -> Vec<Action> {
+        let key = Key::new(event.code, event.modifiers);
+        self.bindings
+            .iter()
+            .filter(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    pub fn is(&self, action: Action, event: &KeyEvent) -> bool {
+        self.actions(event).contains(&action)
+    }
+
+    // The keys of an action as the help shows them, e.g. "Enter, Right".
+    pub fn label(&self, action: Action) -> String {
+        self.keys(action).iter().map(Key::name).collect::<Vec<_>>().join(", ")
+    }
+
+    // The first key of an action, for the short hints in the bottom bar.
+    pub fn first(&self, action: Action) -> String {
+        self.keys(action).first().map_or_else(|| "-".to_string(), Key::name)
+    }
+
+    fn keys(&self, action: Action) -> &[Key] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn shift_and_a_letter_is_the_capital() {
+        assert_eq!(Key::parse("Shift+a"), Key::parse("A"));
+        assert_eq!(Key::parse("shift+o").unwrap().name(), "O");
+        assert_eq!(Key::parse("Shift+Tab"), Key::parse("BackTab"));
+        assert_ne!(Key::parse("Shift+Delete"), Key::parse("Delete"));
+        let map = KeyMap::default();
+        // Terminals send a capital with or without Shift, and some a small letter with it.
+        for code in [KeyCode::Char('O'), KeyCode::Char('o')] {
+            assert_eq!(map.actions(&press(code, KeyModifiers::SHIFT)), [Action::Reveal]);
+        }
+        assert_eq!(map.actions(&press(KeyCode::Char('O'), KeyModifiers::NONE)), [Action::Reveal]);
+        assert_eq!(map.actions(&press(KeyCode::Char('o'), KeyModifiers::NONE)), [Action::Launch]);
+    }
+
+    #[test]
+    fn key_names_parse_and_print() {
+        assert_eq!(Key::parse("ctrl+R").unwrap().name(), "Ctrl+R");
+        assert_eq!(Key::parse("+").unwrap().name(), "+");
+        assert_eq!(Key::parse("space").unwrap().name(), "Space");
+        assert_eq!(Key::parse("pgdn").unwrap().name(), "PgDn");
+        assert_eq!(Key::parse("Hyper+a").unwrap_err(), "unknown modifier 'Hyper' in 'Hyper+a'");
+        assert_eq!(Key::parse("F25").unwrap_err(), "unknown key 'F25'");
+    }
+
+    #[test]
+    fn a_rebound_key_leaves_its_old_action() {
+        let mut map = KeyMap::default();
+        map.bind(Action::Quit, "d Shift+f").unwrap();
+        assert_eq!(map.label(Action::Quit), "d, F");
+        assert_eq!(map.actions(&press(KeyCode::Char('d'), KeyModifiers::NONE)), [Action::Quit]);
+        assert_eq!(map.actions(&press(KeyCode::Char('F'), KeyModifiers::SHIFT)), [Action::Quit]);
+        // What the keys did before is now unbound, and so is the old q.
+        assert_eq!((map.first(Action::Delete), map.first(Action::FreeSpace)), ("-".to_string(), "-".to_string()));
+        assert!(map.actions(&press(KeyCode::Char('q'), KeyModifiers::NONE)).is_empty());
+        // A key shared by default loses all of its other actions.
+        map.bind(Action::Help, "Up").unwrap();
+        assert_eq!(map.actions(&press(KeyCode::Up, KeyModifiers::NONE)), [Action::Help]);
+        assert_eq!(map.label(Action::Up), "Backspace, h, Left, Esc");
+        // A bad key changes nothing.
+        assert!(map.bind(Action::Quit, "x Nope").is_err());
+        assert_eq!(map.label(Action::Quit), "d, F");
+        assert_eq!(map.first(Action::ExportSvg), "x");
+    }
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Hands a file or folder to the desktop: the application registered for its
+// type, or the file manager for a folder.
+pub fn open(path: &Path) -> Result<(), String> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let mut child = Command::new(opener)
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", opener, e))?;
+    // Some openers wait for the application, so they are reaped elsewhere.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// $VISUAL, then $EDITOR, if either is set.
+pub fn editor() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+// No NUL bytes and valid UTF-8 in the first few KiB, the usual guess.
+pub fn is_text(path: &Path) -> bool {
+    let mut head = [0u8; 8192];
+    let Ok(n) = File::open(path).and_then(|mut f| f.read(&mut head)) else {
+        return false;
+    };
+    let head = &head[..n];
+    // A character cut off at the end of the buffer still counts as text.
+    !head.contains(&0) && std::str::from_utf8(head).map_or_else(|e| e.error_len().is_none(), |_| true)
+}
+
+// An interactive $SHELL (sh without one) in `dir`, in the foreground.
+pub fn shell(dir: &Path) -> Result<(), String> {
+    let shell = env::var("SHELL").ok().filter(|s| !s.is_empty()).unwrap_or_else(|| "/bin/sh".to_string());
+    // A shell exits with the status of its last command, which says nothing
+    // about whether it worked.
+    Command::new(&shell)
+        .current_dir(dir)
+        .status()
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", shell, e))
+}
+
+// Runs the editor in the foreground; the caller hands it the terminal.
+pub fn edit(editor: &str, path: &Path) -> Result<(), String> {
+    // Through sh, since the variable may carry arguments, like "code -w".
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|e| format!("{}: {}", editor, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", editor, status))
+    }
+}
@@ -1,10 +1,12 @@
 use ratatui::layout::Rect;
 
+#[derive(Debug, Clone, Copy)]
 pub struct BlockRect {
     pub index: usize,
     pub rect: Rect,
 }
 
+// Blocks are laid out in the order given, largest first for the squarest ones.
 pub fn treemap(sizes: &[(usize, u64)], area: Rect) -> Vec<BlockRect> {
     if sizes.is_empty() || area.width == 0 || area.height == 0 {
         return Vec::new();
@@ -13,7 +15,7 @@ pub fn treemap(sizes: &[(usize, u64)], area: Rect) -> Vec<BlockRect> {
     let total: u64 = sizes.iter().map(|(_, s)| *s).sum();
     let area_f = (area.width as f64) * (area.height as f64);
 
-    let mut items: Vec<(usize, f64)> = sizes
+    let items: Vec<(usize, f64)> = sizes
         .iter()
         .map(|(idx, s)| {
             let v = if total == 0 { 1.0 } else { (*s as f64).max(1.0) };
@@ -21,8 +23,6 @@ pub fn treemap(sizes: &[(usize, u64)], area: Rect) -> Vec<BlockRect> {
         })
         .collect();
 
-    items.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
     let total_f: f64 = items.iter().map(|(_, v)| *v).sum();
     let normalized: Vec<(usize, f64)> = items
         .into_iter()
@@ -89,7 +89,7 @@ pub fn grid_layout(sizes: &[(usize, u64)], area: Rect) -> Vec<BlockRect> {
     let total: u64 = sizes.iter().map(|(_, s)| *s).sum();
     let total_f = if total == 0 { sizes.len() as f64 } else { total as f64 };
 
-    let mut items: Vec<(usize, f64)> = sizes
+    let items: Vec<(usize, f64)> = sizes
         .iter()
         .map(|(idx, s)| {
             let v = if total == 0 { 1.0 } else { (*s as f64).max(1.0) };
@@ -97,8 +97,6 @@ pub fn grid_layout(sizes: &[(usize, u64)], area: Rect) -> Vec<BlockRect> {
         })
         .collect();
 
-    items.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
     let n = items.len();
     let mut rows = (f64::from(n as u32).sqrt().ceil() as u16).max(1);
     if rows > area.height {
@@ -0,0 +1,142 @@
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::collections::HashMap;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ffi::CString;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+// Reports changes in the folder on screen and its subfolders through
+// inotify, so the view can refresh itself. Deeper changes would need a watch
+// on every folder below, which runs into the kernel's per-user limit.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct Watcher {
+    fd: i32,
+    watches: Arc<Mutex<HashMap<i32, PathBuf>>>,
+    // Tells the reading thread to close the descriptor and stop.
+    stop: Arc<AtomicBool>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Watcher {
+    // `changed` gets the watched folder something happened in, once per batch
+    // of events. None when inotify is not available.
+    pub fn spawn(changed: impl Fn(PathBuf) + Send + 'static) -> Option<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        let watches: Arc<Mutex<HashMap<i32, PathBuf>>> = Arc::default();
+        let stop: Arc<AtomicBool> = Arc::default();
+        let shared = Arc::clone(&watches);
+        let stopped = Arc::clone(&stop);
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; 64 * 1024];
+            let header = std::mem::size_of::<libc::inotify_event>();
+            // The descriptor is only closed here, once nothing reads from it
+            // any more, so it cannot be reused for another file under the read.
+            let mut poll = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            loop {
+                if stopped.load(Ordering::Relaxed) {
+                    unsafe { libc::close(fd) };
+                    return;
+                }
+                if unsafe { libc::poll(&mut poll, 1, 250) } <= 0 {
+                    continue;
+                }
+                let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+                if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                if n <= 0 {
+                    return;
+                }
+                let mut dirs: Vec<PathBuf> = Vec::new();
+                let mut at = 0;
+                while at + header <= n as usize {
+                    let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf[at..].as_ptr().cast()) };
+                    at += header + event.len as usize;
+                    // The kernel dropped events, so any folder may have changed.
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        for dir in shared.lock().unwrap().values() {
+                            if !dirs.contains(dir) {
+                                dirs.push(dir.clone());
+                            }
+                        }
+                        continue;
+                    }
+                    // Removing a watch reports it too; that folder is gone from
+                    // the map by then.
+                    let Some(dir) = shared.lock().unwrap().get(&event.wd).cloned() else {
+                        continue;
+                    };
+                    if !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                }
+                for dir in dirs {
+                    changed(dir);
+                }
+            }
+        });
+        Some(Watcher { fd, watches, stop })
+    }
+
+    // Watches exactly these folders from now on.
+    pub fn watch(&self, dirs: &[PathBuf]) {
+        let mut watches = self.watches.lock().unwrap();
+        watches.retain(|&wd, path| {
+            let keep = dirs.contains(path);
+            if !keep {
+                unsafe { libc::inotify_rm_watch(self.fd, wd) };
+            }
+            keep
+        });
+        for dir in dirs {
+            if watches.values().any(|p| p == dir) {
+                continue;
+            }
+            let Ok(c) = CString::new(dir.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let wd = unsafe { libc::inotify_add_watch(self.fd, c.as_ptr(), MASK) };
+            if wd >= 0 {
+                watches.insert(wd, dir.clone());
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub struct Watcher;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Watcher {
+    pub fn spawn(_changed: impl Fn(PathBuf) + Send + 'static) -> Option<Watcher> {
+        None
+    }
+
+    pub fn watch(&self, _dirs: &[PathBuf]) {}
+}
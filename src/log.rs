@@ -0,0 +1,161 @@
+use crate::export;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+const LEVELS: [Level; 6] = [Level::Off, Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Level> {
+        LEVELS.into_iter().find(|l| l.name() == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    // One step more verbose, wrapping from trace back to off.
+    pub fn next(self) -> Level {
+        LEVELS[(self as usize + 1) % LEVELS.len()]
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Off as u8);
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+// Opened on the first message, so quiet runs never create the file.
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn init(path: Option<PathBuf>, level: Level) {
+    match path {
+        Some(path) => {
+            let _ = PATH.set(path);
+            set_level(level);
+        }
+        None => set_level(Level::Off),
+    }
+}
+
+pub fn level() -> Level {
+    LEVELS[LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn path() -> Option<&'static Path> {
+    PATH.get().map(PathBuf::as_path)
+}
+
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level <= self::level()
+}
+
+pub fn write(level: Level, target: &str, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let Some(path) = path() else {
+        return;
+    };
+    let mut file = FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if file.is_none() {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(f) => *file = Some(f),
+            // Nowhere to write to, so stop formatting messages for nothing.
+            Err(_) => return set_level(Level::Off),
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    if let Some(f) = file.as_mut() {
+        let _ = writeln!(
+            f,
+            "{}.{:03} {:<5} {}: {}",
+            export::timestamp(now.as_secs()),
+            now.subsec_millis(),
+            level.name(),
+            target,
+            args
+        );
+    }
+}
+
+// Logs how long a piece of work took once it is dropped. The label is only
+// built when the level is enabled.
+pub struct Span {
+    level: Level,
+    target: &'static str,
+    label: String,
+    start: Instant,
+}
+
+pub fn span(level: Level, target: &'static str, label: impl FnOnce() -> String) -> Option<Span> {
+    if !enabled(level) {
+        return None;
+    }
+    let label = label();
+    write(Level::Trace, target, format_args!("{} started", label));
+    Some(Span {
+        level,
+        target,
+        label,
+        start: Instant::now(),
+    })
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let ms = self.start.elapsed().as_secs_f64() * 1000.0;
+        write(self.level, self.target, format_args!("{} took {:.1} ms", self.label, ms));
+    }
+}
+
+macro_rules! log_at {
+    ($level:expr, $target:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, $target, format_args!($($arg)+));
+        }
+    };
+}
+
+macro_rules! warning {
+    ($target:expr, $($arg:tt)+) => { $crate::log::log_at!($crate::log::Level::Warn, $target, $($arg)+) };
+}
+
+macro_rules! info {
+    ($target:expr, $($arg:tt)+) => { $crate::log::log_at!($crate::log::Level::Info, $target, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => { $crate::log::log_at!($crate::log::Level::Debug, $target, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($target:expr, $($arg:tt)+) => { $crate::log::log_at!($crate::log::Level::Trace, $target, $($arg)+) };
+}
+
+pub(crate) use {debug, info, log_at, trace, warning};
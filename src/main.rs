@@ -1,66 +1,565 @@
+mod audit;
+mod bookmarks;
+mod cli;
+mod collate;
+mod config;
+mod diskcache;
+mod export;
+mod fsinfo;
+mod glob;
+mod graphics;
+mod hook;
+mod import;
+mod json;
+mod keys;
+mod launch;
+mod live;
 mod layout;
+mod log;
+mod ncdu;
+mod palette;
+mod png;
+mod regex;
 mod scan;
+mod serve;
+mod snapshot;
+mod trash;
+mod units;
+mod vfs;
+mod watch;
 
+use crate::cli::{Command, ReportFormat};
+use crate::config::{Config, HookEvent};
+use crate::hook::Field;
+use crate::keys::Action;
 use crate::layout::{grid_layout, treemap, BlockRect};
-use crate::scan::{start_scan, Item, ItemKind, ScanHandle, ScanMsg, ViewMode};
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseEventKind};
-use crossterm::execute;
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crate::palette::{Theme, Ui};
+use crate::export::SvgBlock;
+use crate::units::{format_count, format_size, UnitMode};
+use crate::vfs::{FileSystem, Kind};
+use crate::scan::{
+    Exclude, Item, ItemKind, Node, Priority, ScanError, ScanLimits, ScanMsg, ScanRequest, Scanner, SizeHints,
+    TreeOptions, ViewMode,
+};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton,
+    MouseEventKind,
+};
+use crossterm::cursor::{MoveTo, Show};
+use crossterm::style::{Attribute, Colors, Print, ResetColor, SetAttribute, SetColors};
+use crossterm::{execute, queue};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Clear, Paragraph};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
 use ratatui::Terminal;
-use std::collections::HashMap;
-use std::ffi::CString;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::io::{self, Stdout};
+use std::io::{self, Stdout, Write};
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::os::unix::ffi::OsStrExt;
-use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const VERSION_LABEL: &str = concat!("v", env!("CARGO_PKG_VERSION"));
+const HTML_TREE: TreeOptions = TreeOptions {
+    max_depth: 4,
+    max_children: 200,
+    keep_files: true,
+    follow_links: false,
+    allocated: false,
+    count_links: false,
+    one_fs: false,
+    mounts: false,
+    du: false,
+};
+const SNAPSHOT_TREE: TreeOptions = TreeOptions {
+    max_depth: 6,
+    max_children: usize::MAX,
+    keep_files: false,
+    follow_links: false,
+    allocated: false,
+    count_links: false,
+    one_fs: false,
+    mounts: false,
+    du: false,
+};
 
 #[derive(Default)]
 struct ScanState {
     scanning: bool,
     scanned: u64,
     errors: u64,
+    // A cached result is shown while it is refreshed, so partial results
+    // would only make it shrink and grow back.
+    stale: bool,
+    // Bytes in the entries sized so far, against what the scan should come
+    // to, for the percentage and the time left.
+    bytes: u64,
+    expected: Option<u64>,
 }
 
+// What the items are ordered by, in the treemap and with Tab. Cycled with s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Size,
+    Name,
+    Count,
+    Modified,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Size => SortKey::Name,
+            SortKey::Name => SortKey::Count,
+            SortKey::Count => SortKey::Modified,
+            SortKey::Modified => SortKey::Size,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Size => "size",
+            SortKey::Name => "name",
+            SortKey::Count => "item count",
+            SortKey::Modified => "last modified",
+        }
+    }
+}
+
+// What follows the name in a block's label. Cycled with %.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LabelMode {
+    Size,
+    // Share of the folder shown.
+    Share,
+    Both,
+    // Share of the whole filesystem.
+    Disk,
+}
+
+impl LabelMode {
+    fn next(self) -> Self {
+        match self {
+            LabelMode::Size => LabelMode::Share,
+            LabelMode::Share => LabelMode::Both,
+            LabelMode::Both => LabelMode::Disk,
+            LabelMode::Disk => LabelMode::Size,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LabelMode::Size => "size",
+            LabelMode::Share => "share of this folder",
+            LabelMode::Both => "size and share of this folder",
+            LabelMode::Disk => "share of the filesystem",
+        }
+    }
+}
+
+enum AppEvent {
+    Input(Event),
+    Scan(u64, ScanMsg),
+    ScanEnded(u64),
+    // Something changed in this watched folder.
+    Changed(PathBuf),
+    Deleted(Deleted),
+    Tick,
+}
+
+// What a confirmed batch of deletions came to, for each target whether it
+// is gone and the first thing that went wrong with it.
+struct Deleted {
+    outcomes: Vec<(PathBuf, bool, Result<(), String>)>,
+}
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+// Frame interval while a scan streams in and nobody is typing.
+const BUSY_FRAME_INTERVAL: Duration = Duration::from_millis(100);
+// Unhandled scan messages beyond which progress updates are dropped. They
+// are snapshots, so the next one or the final result supersedes them.
+const SCAN_BACKLOG_LIMIT: usize = 16;
+const SPINNER_INTERVAL: Duration = Duration::from_millis(200);
+// How long the input thread waits for input before checking whether it
+// should stand by.
+const INPUT_POLL: Duration = Duration::from_millis(50);
+const NAV_DEBOUNCE: Duration = Duration::from_millis(150);
+const PREFETCH_COUNT: usize = 3;
+// Places kept to go back to, and totals of earlier scans kept as hints, so a
+// long session does not grow them without end.
+const HISTORY_LIMIT: usize = 200;
+const TOTALS_LIMIT: usize = 10_000;
+// Folder changes are collected this long before the view is rescanned, and
+// at most this many subfolders are watched. A folder that keeps changing, a
+// log being written say, waits twice as long each time, up to the maximum.
+const LIVE_DELAY: Duration = Duration::from_secs(2);
+const LIVE_MAX_DELAY: Duration = Duration::from_secs(60);
+const LIVE_WATCH_LIMIT: usize = 512;
+// Nested levels are drawn inside directory blocks at least this big, for at
+// most this many of their largest children.
+const NESTED_MIN_WIDTH: u16 = 16;
+const NESTED_MIN_HEIGHT: u16 = 5;
+const NESTED_MAX: usize = 24;
+// Files drawn in the expanded files strip of the folders view; the rest is
+// one "(N more)" block that opens the files view.
+const STRIP_FILES: usize = 24;
+// Layout index of the block that stands for the items folded away as too small.
+const OTHER_BLOCK: usize = usize::MAX;
+// In front of the name of a folder that is a mount point.
+const MOUNT_PREFIX: &str = "▤ ";
+
+// Counters behind the hidden F12 overlay, kept cheap enough to collect always.
+#[derive(Default)]
+struct DebugStats {
+    visible: bool,
+    draws: u64,
+    last_frame: Duration,
+    slowest_frame: Duration,
+    cache_hits: u64,
+    cache_misses: u64,
+    // Scan messages sent by the scanner but not yet handled by the UI.
+    backlog: Arc<AtomicUsize>,
+}
+
+// Per-item drawing inputs, computed once when the items change instead of
+// for every block on every frame.
+struct ItemLook {
+    style: Style,
+    size_text: String,
+    // A folder with another filesystem mounted on it.
+    mount: bool,
+}
+
+impl ItemLook {
+    fn new(theme: &Theme, now: Option<i64>, index: usize, item: &Item, mount: bool) -> Self {
+        let color = block_color(theme, now, index, item.kind, item.mtime);
+        let size_text = if item.kind == ItemKind::Mount {
+            "-".to_string()
+        } else if item.partial {
+            format!("≥{}", format_size(item.size))
+        } else {
+            format_size(item.size)
+        };
+        Self {
+            style: Style::default().bg(color).fg(palette::text_color(color)),
+            size_text,
+            mount,
+        }
+    }
+}
+
+// Blocks are colored by rank and kind, or by how long ago anything in them
+// changed when `now` is given.
+fn block_color(theme: &Theme, now: Option<i64>, index: usize, kind: ItemKind, mtime: i64) -> Color {
+    match now {
+        Some(now) => palette::age_color(theme, (mtime > 0).then(|| now - mtime)),
+        None => palette::color_for_item(theme, index, kind),
+    }
+}
+
+// A child drawn inside a directory's block when the tree knows its contents.
+struct InnerBlock {
+    rect: Rect,
+    label: Option<String>,
+    style: Style,
+}
+
+// A block, or a row of the list, that a click selects. A block laid out
+// finer than cells keeps its `fine` rect, since the cells at its edges are
+// shared with its neighbours.
+#[derive(Clone, Copy)]
 struct ClickTarget {
     rect: Rect,
+    fine: Option<Rect>,
     index: usize,
 }
 
+// Something to delete or trash; marked entries are kept the same way.
+#[derive(Clone)]
+struct Target {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    size: u64,
+    // Files that go with it, shown next to the size before confirming.
+    files: u64,
+}
+
+impl Target {
+    fn of(item: &Item) -> Self {
+        Target {
+            path: item.path.to_path_buf(),
+            name: item.name.to_string(),
+            is_dir: item.kind != ItemKind::File,
+            size: item.size,
+            files: if item.kind == ItemKind::File { 1 } else { item.count },
+        }
+    }
+}
+
 struct ConfirmAction {
-    target_path: PathBuf,
-    target_name: String,
+    targets: Vec<Target>,
+    trash: bool,
+    // The targets are the marked entries, wherever they are, rather than
+    // what is selected.
+    marked: bool,
+}
+
+enum Handover {
+    // A text file for $EDITOR.
+    Edit(PathBuf),
+    // $SHELL in this folder.
+    Shell(PathBuf),
+}
+
+// The search box of the current view. Items whose names don't match are
+// dimmed, or left out of the layout while `hide` is on.
+#[derive(Default)]
+struct Search {
+    query: String,
+    // Keys go to the query until Enter or Esc.
+    typing: bool,
+    hide: bool,
+}
+
+impl Search {
+    // Case-insensitive unless the query has a capital in it, like smartcase
+    // in vim and less.
+    fn matches(&self, name: &str) -> bool {
+        self.rank(name).is_some()
+    }
+
+    // How well a name matches: 0 for all of it, 1 for its start, 2 for
+    // anywhere in it.
+    fn rank(&self, name: &str) -> Option<u8> {
+        let lower;
+        let name = if self.query.chars().any(char::is_uppercase) {
+            name
+        } else {
+            lower = name.to_lowercase();
+            &lower
+        };
+        if name == self.query {
+            Some(0)
+        } else if name.starts_with(&self.query) {
+            Some(1)
+        } else {
+            name.contains(&self.query).then_some(2)
+        }
+    }
+}
+
+// The path typed after g, relative to the current folder unless it starts
+// with / or ~.
+#[derive(Default)]
+struct PathPrompt {
+    text: String,
+    // The folder is compared with the current one rather than opened.
+    compare: bool,
+    // When Tab finds several folders that share no more of their names, the
+    // next Tab goes through them, each after `base`.
+    choices: Vec<String>,
+    choice: Option<usize>,
+    base: String,
+}
+
+// Two folders side by side, drawn on the same scale, with what they have
+// in common listed by how much it differs.
+struct Compare {
+    sides: [CompareSide; 2],
+    // The row of the list, whose blocks are highlighted on both sides.
+    selected: usize,
+}
+
+struct CompareSide {
+    path: PathBuf,
+    // None until its scan is done.
+    items: Option<Arc<[Item]>>,
+}
+
+// One name in either folder or both, with its size on each side.
+struct CompareRow {
+    name: Arc<str>,
+    kind: ItemKind,
+    sizes: [Option<u64>; 2],
+    paths: [Option<Arc<Path>>; 2],
+}
+
+impl CompareRow {
+    fn difference(&self) -> i128 {
+        self.sizes[1].unwrap_or(0) as i128 - self.sizes[0].unwrap_or(0) as i128
+    }
+
+    // The name an item is matched by on the other side. The loose files of
+    // the two folders are one row, however many each has.
+    fn name_of(item: &Item) -> Arc<str> {
+        if item.kind == ItemKind::FilesAggregate {
+            Arc::from("(Files)")
+        } else {
+            Arc::clone(&item.name)
+        }
+    }
+}
+
+// Names found anywhere below the current folder, in the tree of a deep scan.
+#[derive(Default)]
+struct Find {
+    query: String,
+    // The query is a regular expression rather than part of a name.
+    regex: bool,
+    // Keys go to the query until Enter runs it.
+    typing: bool,
+    results: Vec<Found>,
+    // How many matched, of which the largest FIND_LIMIT are listed.
+    matched: usize,
+    selected: usize,
+}
+
+struct Found {
+    path: PathBuf,
+    size: u64,
     is_dir: bool,
-    return_path: Option<PathBuf>,
+}
+
+const FIND_LIMIT: usize = 1000;
+
+struct VolumeScreen {
+    volumes: Vec<fsinfo::Volume>,
+    selected: usize,
+    rows: Vec<Rect>,
+    // Shown instead of a first scan; leaving it scans the start path after all.
+    start: bool,
 }
 
 struct App {
     current_path: PathBuf,
-    items: Vec<Item>,
+    // The scan result as cached, and the items shown in the current size mode.
+    scanned: Arc<[Item]>,
+    items: Arc<[Item]>,
     total: u64,
-    layout_sizes: Vec<(usize, u64)>,
-    layout_has_zero: bool,
+    allocated: bool,
+    sort: SortKey,
+    labels: LabelMode,
+    age_colors: bool,
+    free_space: bool,
+    // The files strip of the folders view shows the largest files instead of
+    // one "(Files: N)" block.
+    files_open: bool,
     scan_state: ScanState,
-    scan_handle: Option<ScanHandle>,
+    scan_errors: Arc<[ScanError]>,
+    scan_cancel: Option<Arc<AtomicBool>>,
+    scan_generation: u64,
+    generations: u64,
+    // Scans that only fill the cache: prefetches and the files strip.
+    prefetches: HashMap<u64, (CacheKey, Arc<AtomicBool>)>,
+    watcher: Option<live::Watcher>,
+    // Watched folders that changed since the last rescan, and when the first
+    // of them did.
+    changes: Vec<PathBuf>,
+    changed_since: Option<Instant>,
+    // How long changes are collected now, and when the last rescan for them
+    // started.
+    live_delay: Duration,
+    live_refreshed: Option<Instant>,
+    scan_pending: bool,
+    last_launch: Option<Instant>,
+    events: Sender<AppEvent>,
+    fs: Arc<dyn FileSystem>,
+    // Where other filesystems are mounted, read again on every refresh.
+    mount_points: HashSet<PathBuf>,
+    scanner: Scanner,
     view_mode: ViewMode,
+    // The place last shown and the places before and after it, for back and
+    // forward.
+    visited: Option<(PathBuf, ViewMode)>,
+    back: Vec<(PathBuf, ViewMode)>,
+    forward: Vec<(PathBuf, ViewMode)>,
+    // Rows with bars instead of the treemap, and the first row shown.
+    list: bool,
+    list_top: usize,
+    debug: DebugStats,
+    looks: Vec<ItemLook>,
+    blocks: Vec<BlockRect>,
+    // The blocks as laid out in finer units than cells, `scale` of them to a
+    // cell across and down: half rows with half_blocks, pixels when the
+    // treemap is shown as a picture. The rects in `blocks` then cover every
+    // cell a block has any part of.
+    fine: Vec<Rect>,
+    scale: (u16, u16),
+    // How the terminal shows pictures, if it does, and a digest of the one
+    // on the screen.
+    graphics: Option<Box<dyn graphics::ImageProtocol>>,
+    image: Option<u64>,
+    blocks_area: Option<Rect>,
     click_map: Vec<ClickTarget>,
+    // Items too small for a block of their own, shown together as "Other".
+    folded: Vec<usize>,
+    other_target: Option<ClickTarget>,
+    // Where the mouse last moved to; the block under it is described in the
+    // bottom bar.
+    hover: Option<(u16, u16)>,
     up_rect: Option<Rect>,
+    // The ancestors in the bottom bar path, each with the folder it opens.
+    crumbs: Vec<(Rect, PathBuf)>,
+    selected: Option<usize>,
     spinner: usize,
     last_error: Option<String>,
+    notice: Option<String>,
+    // A batch of deletions is running on its own thread.
+    deleting: bool,
+    treemap_area: Rect,
     fs_used: u64,
     fs_total: u64,
     fs_last: Instant,
     fs_device: Option<String>,
-    scan_cache: HashMap<CacheKey, CachedScan>,
+    // Of the last folder scanned or checked; see volumes().
+    volumes: scan::Volumes,
+    scan_cache: ScanCache,
+    // Apparent and allocated totals of earlier scans, kept when the cache
+    // entry goes, so a rescan knows roughly how far it is.
+    totals: HashMap<CacheKey, (u64, u64)>,
+    // What is shown came from the cache file rather than a scan.
+    cached: bool,
     confirm: Option<ConfirmAction>,
+    // A program that needs the terminal to itself; run_app hands it over.
+    handover: Option<Handover>,
+    volume_screen: Option<VolumeScreen>,
+    search: Option<Search>,
+    find: Option<Find>,
+    path_prompt: Option<PathPrompt>,
+    compare: Option<Compare>,
+    bookmarks: Vec<PathBuf>,
+    // The row selected in the bookmark list while it is open.
+    bookmark_list: Option<usize>,
+    // Scroll offset of the help overlay while it is open.
+    help: Option<usize>,
+    // Likewise for the list of scan errors.
+    error_list: Option<usize>,
+    marked: Vec<Target>,
+    chooser: bool,
+    // An imported listing or a deep scan, navigated without scanning. Nested
+    // levels are drawn from it too.
+    tree: Option<Node>,
+    tree_errors: Arc<[ScanError]>,
+    imported: bool,
+    inner: Vec<InnerBlock>,
+    windows_drives: Vec<(PathBuf, String)>,
+    exclude: Arc<Exclude>,
+    depth: Option<usize>,
+    theme: Theme,
+    config: Config,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -69,395 +568,3794 @@ struct CacheKey {
     view: ViewMode,
 }
 
+// Results are immutable once a scan is done, so the cache and the App share
+// them instead of copying directories with huge numbers of entries.
 #[derive(Debug, Clone)]
 struct CachedScan {
-    items: Vec<Item>,
-    total: u64,
-    layout_sizes: Vec<(usize, u64)>,
-    layout_has_zero: bool,
-    errors: u64,
+    items: Arc<[Item]>,
+    errors: Arc<[ScanError]>,
+    stamp: u64,
+    bytes: usize,
+    used: u64,
+    // Loaded from the cache file, i.e. scanned in an earlier session.
+    from_disk: bool,
+}
+
+// Results of visited directories. Once their estimated size passes the
+// memory limit (0 means none), the least recently used ones are dropped.
+struct ScanCache {
+    entries: HashMap<CacheKey, CachedScan>,
+    bytes: usize,
+    // The tree of a deep scan or an import. It cannot be dropped, but it
+    // counts toward the limit, so a big one leaves less room for the rest.
+    tree_bytes: usize,
+    limit: usize,
+    clock: u64,
+}
+
+impl ScanCache {
+    fn new(limit: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            bytes: 0,
+            tree_bytes: 0,
+            limit,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<CachedScan> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.used = self.clock;
+        Some(entry.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, mut scan: CachedScan) {
+        self.clock += 1;
+        scan.used = self.clock;
+        scan.bytes = estimate_bytes(&scan.items, &scan.errors);
+        self.bytes += scan.bytes;
+        if let Some(old) = self.entries.insert(key.clone(), scan) {
+            self.bytes -= old.bytes;
+        }
+        // The newest entry is kept even when it alone is over the limit.
+        self.evict(Some(&key));
+    }
+
+    fn set_tree_bytes(&mut self, bytes: usize) {
+        self.tree_bytes = bytes;
+        self.evict(None);
+    }
+
+    fn evict(&mut self, keep: Option<&CacheKey>) {
+        while self.limit > 0 && self.bytes + self.tree_bytes > self.limit {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(k, _)| Some(*k) != keep)
+                .min_by_key(|(_, e)| e.used)
+                .map(|(k, _)| k.clone());
+            let Some(oldest) = oldest else { break };
+            if let Some(old) = self.entries.remove(&oldest) {
+                log::debug!("cache", "evicted {} ({} bytes)", oldest.path.display(), old.bytes);
+                self.bytes -= old.bytes;
+            }
+        }
+    }
+
+    fn contains(&self, key: &CacheKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&CacheKey) -> bool) {
+        self.entries.retain(|k, _| keep(k));
+        self.bytes = self.entries.values().map(|e| e.bytes).sum();
+    }
+}
+
+// Rough heap footprint of a scan result: the structs plus their strings.
+fn estimate_bytes(items: &[Item], errors: &[ScanError]) -> usize {
+    let items: usize = items
+        .iter()
+        .map(|i| std::mem::size_of::<Item>() + i.name.len() + i.path.as_os_str().len())
+        .sum();
+    let errors: usize = errors
+        .iter()
+        .map(|e| std::mem::size_of::<ScanError>() + e.path.as_os_str().len() + e.message.len())
+        .sum();
+    items + errors
+}
+
+// Likewise for a tree, whose nodes keep only their own names.
+fn estimate_tree_bytes(node: &Node) -> usize {
+    let own = std::mem::size_of::<Node>() + node.name.len();
+    own + node.children.iter().map(estimate_tree_bytes).sum::<usize>()
 }
 
 impl App {
-    fn new(path: PathBuf) -> Self {
+    fn new(path: PathBuf, config: Config, theme: Theme, chooser: bool, events: Sender<AppEvent>) -> Self {
+        let scan_events = events.clone();
+        let debug = DebugStats::default();
+        let backlog = Arc::clone(&debug.backlog);
+        let fs: Arc<dyn FileSystem> = Arc::new(vfs::Os);
+        let windows_drives = fsinfo::windows_drives();
+        let mut excluded = fsinfo::android_duplicate_views();
+        if !config.wsl_windows_drives {
+            excluded.extend(windows_drives.iter().map(|(path, _)| path.clone()));
+        }
+        let scanner = Scanner::spawn(Arc::clone(&fs), config.hooks.clone(), move |generation, msg| {
+            // Later progress and partial results supersede dropped ones.
+            let progress = matches!(msg, Some(ScanMsg::Progress { .. } | ScanMsg::Partial { .. }));
+            if progress && backlog.load(Ordering::Relaxed) >= SCAN_BACKLOG_LIMIT {
+                return;
+            }
+            backlog.fetch_add(1, Ordering::Relaxed);
+            let _ = scan_events.send(match msg {
+                Some(msg) => AppEvent::Scan(generation, msg),
+                None => AppEvent::ScanEnded(generation),
+            });
+        });
+        let live_events = events.clone();
+        let watcher = if config.live_refresh {
+            live::Watcher::spawn(move |dir| {
+                let _ = live_events.send(AppEvent::Changed(dir));
+            })
+        } else {
+            None
+        };
         Self {
             current_path: path,
-            items: Vec::new(),
+            scanned: Arc::from([]),
+            items: Arc::from([]),
             total: 0,
-            layout_sizes: Vec::new(),
-            layout_has_zero: false,
+            allocated: config.allocated,
+            sort: SortKey::Size,
+            labels: LabelMode::Size,
+            age_colors: config.age_colors,
+            free_space: config.free_space,
+            files_open: false,
             scan_state: ScanState::default(),
-            scan_handle: None,
+            scan_errors: Arc::from([]),
+            scan_cancel: None,
+            scan_generation: 0,
+            generations: 0,
+            prefetches: HashMap::new(),
+            watcher,
+            changes: Vec::new(),
+            changed_since: None,
+            live_delay: LIVE_DELAY,
+            live_refreshed: None,
+            scan_pending: false,
+            last_launch: None,
+            events,
+            fs,
+            mount_points: mount_points(&config.pseudo_filesystems),
+            scanner,
             view_mode: ViewMode::Dirs,
+            visited: None,
+            back: Vec::new(),
+            forward: Vec::new(),
+            list: false,
+            list_top: 0,
+            debug,
+            looks: Vec::new(),
+            blocks: Vec::new(),
+            fine: Vec::new(),
+            scale: (1, 1),
+            graphics: None,
+            image: None,
+            blocks_area: None,
             click_map: Vec::new(),
+            folded: Vec::new(),
+            other_target: None,
+            hover: None,
             up_rect: None,
+            crumbs: Vec::new(),
+            selected: None,
             spinner: 0,
             last_error: None,
+            notice: None,
+            deleting: false,
+            treemap_area: Rect::default(),
             fs_used: 0,
             fs_total: 0,
             fs_last: Instant::now() - Duration::from_secs(10),
             fs_device: None,
-            scan_cache: HashMap::new(),
+            volumes: scan::Volumes::default(),
+            totals: HashMap::new(),
+            scan_cache: ScanCache::new(config.memory_limit as usize * 1024 * 1024),
+            cached: false,
             confirm: None,
+            handover: None,
+            volume_screen: None,
+            search: None,
+            find: None,
+            path_prompt: None,
+            compare: None,
+            bookmarks: config.bookmarks_file.as_deref().map(bookmarks::load).unwrap_or_default(),
+            bookmark_list: None,
+            help: None,
+            error_list: None,
+            marked: Vec::new(),
+            chooser,
+            tree: None,
+            tree_errors: Arc::from([]),
+            imported: false,
+            inner: Vec::new(),
+            exclude: Arc::new(Exclude {
+                paths: excluded,
+                pseudo: fsinfo::pseudo_mounts(&config.pseudo_filesystems),
+                patterns: config.exclude.clone(),
+            }),
+            depth: None,
+            windows_drives,
+            theme,
+            config,
         }
     }
 
     fn start_scan(&mut self) {
-        if let Some(handle) = &self.scan_handle {
-            handle.cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(cancel) = self.scan_cancel.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        for (_, (_, cancel)) in self.prefetches.drain() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        self.generations += 1;
+        self.scan_generation = self.generations;
+        let here = (self.current_path.clone(), self.view_mode);
+        if let Some(left) = self.visited.replace(here.clone()).filter(|v| *v != here) {
+            if self.back.len() >= HISTORY_LIMIT {
+                self.back.remove(0);
+            }
+            self.back.push(left);
+            self.forward.clear();
+        }
+        // Without a mouse there is always a selection to act on.
+        self.selected = (!self.config.mouse).then_some(0);
+        self.list_top = 0;
+        self.search = None;
+        self.changes.clear();
+        self.changed_since = None;
+        self.live_delay = LIVE_DELAY;
+        if self.show_tree() {
+            self.watch_current();
+            return;
         }
         let key = CacheKey {
             path: self.current_path.clone(),
             view: self.view_mode,
         };
-        if let Some(cached) = self.scan_cache.get(&key).cloned() {
-            self.items = cached.items;
-            self.total = cached.total;
-            self.layout_sizes = cached.layout_sizes;
-            self.layout_has_zero = cached.layout_has_zero;
+        if let Some(cached) = self.scan_cache.get(&key) {
+            self.debug.cache_hits += 1;
+            log::debug!("cache", "hit {} ({:?})", key.path.display(), key.view);
+            self.set_items(Arc::clone(&cached.items));
+            self.fetch_strip_files();
             self.scan_state = ScanState {
                 scanning: false,
                 scanned: self.items.len() as u64,
-                errors: cached.errors,
+                errors: cached.errors.len() as u64,
+                ..ScanState::default()
             };
+            self.scan_errors = cached.errors;
+            self.cached = cached.from_disk;
             self.last_error = None;
-            self.scan_handle = None;
+            self.notice = None;
+            self.scan_pending = false;
+            // Whether it changed on disk is up to the scanner thread to find
+            // out; it has subfolders to stat.
+            if !self.imported {
+                let path = self.current_path.clone();
+                self.scan_cancel = Some(self.scanner.check(self.scan_generation, path, cached.items, cached.stamp));
+            }
+            self.watch_current();
             return;
         }
 
-        self.items.clear();
-        self.total = 0;
-        self.layout_sizes.clear();
-        self.layout_has_zero = false;
+        self.debug.cache_misses += 1;
+        log::debug!("cache", "miss {} ({:?})", key.path.display(), key.view);
+        self.set_items(Arc::from([]));
         self.scan_state = ScanState {
             scanning: true,
-            scanned: 0,
-            errors: 0,
+            expected: self.expected_total(),
+            ..ScanState::default()
         };
+        self.scan_errors = Arc::from([]);
+        self.cached = false;
         self.last_error = None;
-        self.scan_handle = Some(start_scan(self.current_path.clone(), self.view_mode));
+        self.notice = None;
+        self.watch_current();
+        self.schedule_scan();
     }
 
-    fn invalidate_cache_for(&mut self, path: &Path) {
-        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-        self.scan_cache
-            .retain(|k, _| !k.path.starts_with(&target) && !target.starts_with(&k.path));
+    // The folder on screen and its subfolders; nothing while browsing a tree,
+    // which is not rescanned.
+    fn watch_current(&self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        if self.imported || self.inside_tree() {
+            watcher.watch(&[]);
+            return;
+        }
+        let subdirs = self.scanned.iter().filter(|i| i.kind == ItemKind::Dir).map(|i| i.path.to_path_buf());
+        let dirs: Vec<PathBuf> =
+            std::iter::once(self.current_path.clone()).chain(subdirs.take(LIVE_WATCH_LIMIT)).collect();
+        watcher.watch(&dirs);
     }
 
-    fn go_up(&mut self) {
-        if self.view_mode == ViewMode::Files {
-            self.view_mode = ViewMode::Dirs;
-            self.start_scan();
+    fn inside_tree(&self) -> bool {
+        self.tree.as_ref().is_some_and(|t| self.current_path.starts_with(t.path()))
+    }
+
+    fn folder_changed(&mut self, dir: PathBuf) {
+        // Left over from a folder that is no longer on screen.
+        if dir != self.current_path && dir.parent() != Some(self.current_path.as_path()) {
             return;
         }
-        if let Some(parent) = self.current_path.parent().map(Path::to_path_buf) {
-            self.current_path = parent;
-            self.start_scan();
+        log::trace!("live", "{} changed", dir.display());
+        if !self.changes.contains(&dir) {
+            self.changes.push(dir);
         }
+        self.changed_since.get_or_insert_with(Instant::now);
     }
 
-    fn update_scan(&mut self) -> bool {
-        let mut changed = false;
-        if let Some(handle) = &self.scan_handle {
-            loop {
-                match handle.rx.try_recv() {
-                    Ok(msg) => match msg {
-                        ScanMsg::Progress { scanned, errors } => {
-                            self.scan_state.scanned = scanned;
-                            self.scan_state.errors = errors;
-                            changed = true;
-                        }
-                        ScanMsg::Done { items, total, errors } => {
-                            self.items = items;
-                            self.total = total;
-                            self.layout_sizes = self
-                                .items
-                                .iter()
-                                .enumerate()
-                                .map(|(i, item)| (i, item.size))
-                                .collect();
-                            self.layout_has_zero = self
-                                .items
-                                .iter()
-                                .any(|i| i.size == 0 && i.kind == ItemKind::Dir);
-                            let key = CacheKey {
-                                path: self.current_path.clone(),
-                                view: self.view_mode,
-                            };
-                            let cached = CachedScan {
-                                items: self.items.clone(),
-                                total: self.total,
-                                layout_sizes: self.layout_sizes.clone(),
-                                layout_has_zero: self.layout_has_zero,
-                                errors,
-                            };
-                            self.scan_cache.insert(key, cached);
-                            self.scan_state.scanned = self.items.len() as u64;
-                            self.scan_state.errors = errors;
-                            self.scan_state.scanning = false;
-                            changed = true;
-                        }
-                        ScanMsg::Error(err) => {
-                            self.last_error = Some(err);
-                            self.scan_state.scanning = false;
-                            changed = true;
-                        }
-                    },
-                    Err(std::sync::mpsc::TryRecvError::Empty) => break,
-                    Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                        self.scan_state.scanning = false;
-                        changed = true;
-                        break;
-                    }
-                }
+    // Rescans the view once changes have settled, keeping the old result on
+    // screen meanwhile like a stale cache entry. Called from the tick handler.
+    fn flush_changes(&mut self) -> bool {
+        let settled = self.changed_since.is_some_and(|t| t.elapsed() >= self.live_delay);
+        if !settled || self.scan_state.scanning || self.scan_pending {
+            return false;
+        }
+        self.changed_since = None;
+        let busy = self.live_refreshed.is_some_and(|t| t.elapsed() < self.live_delay * 2);
+        self.live_delay = if busy { (self.live_delay * 2).min(LIVE_MAX_DELAY) } else { LIVE_DELAY };
+        self.live_refreshed = Some(Instant::now());
+        for dir in std::mem::take(&mut self.changes) {
+            self.invalidate_cache_for(&dir);
+        }
+        log::debug!("live", "refreshing {}", self.current_path.display());
+        self.generations += 1;
+        self.scan_generation = self.generations;
+        self.scan_state.scanning = true;
+        self.scan_state.stale = true;
+        self.scan_state.bytes = 0;
+        self.scan_state.expected = self.expected_total();
+        self.launch_scan();
+        true
+    }
+
+    // What a scan of this place should come to: the last scan of it, or at
+    // the root of a filesystem what the filesystem has in use.
+    fn expected_total(&self) -> Option<u64> {
+        let key = CacheKey {
+            path: self.current_path.clone(),
+            view: self.view_mode,
+        };
+        if let Some(&(apparent, allocated)) = self.totals.get(&key) {
+            return Some(if self.allocated { allocated } else { apparent });
+        }
+        if self.view_mode != ViewMode::Dirs || self.imported || !is_mount_root(&self.current_path) {
+            return None;
+        }
+        fsinfo::fs_usage(&self.current_path).map(|(used, _)| used)
+    }
+
+    // What is known of the sizes of the folders in `dir`: the stale listing
+    // on screen while it is rescanned, and the totals of earlier scans.
+    fn size_hints(&self, dir: &Path) -> Arc<SizeHints> {
+        let mut hints: SizeHints = self
+            .totals
+            .iter()
+            .filter(|(key, _)| key.view == ViewMode::Dirs && key.path.parent() == Some(dir))
+            .map(|(key, &(apparent, _))| (key.path.clone(), apparent))
+            .collect();
+        let shown = self.scanned.iter().filter(|i| i.kind == ItemKind::Dir && i.path.parent() == Some(dir));
+        hints.extend(shown.map(|i| (i.path.to_path_buf(), i.size)));
+        Arc::new(hints)
+    }
+
+    fn remember_total(&mut self, key: &CacheKey, items: &[Item]) {
+        let apparent = items.iter().map(|i| i.size).sum();
+        let allocated = items.iter().map(|i| i.allocated).sum();
+        // They are only hints, so any one of them can go to make room.
+        if self.totals.len() >= TOTALS_LIMIT && !self.totals.contains_key(key) {
+            if let Some(old) = self.totals.keys().next().cloned() {
+                self.totals.remove(&old);
             }
         }
-        changed
+        self.totals.insert(key.clone(), (apparent, allocated));
     }
 
-    fn update_fs_cache(&mut self) {
-        if self.fs_last.elapsed() < Duration::from_secs(1) {
-            return;
+    // The first navigation scans right away; ones following quickly after it
+    // are coalesced so clicking through several levels only scans the last
+    // one. Flushed from the tick handler.
+    fn schedule_scan(&mut self) {
+        if self.last_launch.is_some_and(|t| t.elapsed() < NAV_DEBOUNCE) {
+            self.scan_pending = true;
+        } else {
+            self.launch_scan();
         }
-        if let Some((used, total)) = fs_usage(&self.current_path) {
-            self.fs_used = used;
-            self.fs_total = total;
+    }
+
+    fn flush_pending_scan(&mut self) {
+        if self.scan_pending && self.last_launch.is_none_or(|t| t.elapsed() >= NAV_DEBOUNCE) {
+            self.launch_scan();
         }
-        self.fs_device = current_device(&self.current_path);
-        self.fs_last = Instant::now();
     }
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let start_path = env::args().nth(1).unwrap_or_else(|| ".".to_string());
-    let start_path = PathBuf::from(start_path);
+    fn launch_scan(&mut self) {
+        self.scan_pending = false;
+        self.last_launch = Some(Instant::now());
+        if self.imported {
+            let msg = ScanMsg::Error(format!("{} is not in the listing", self.current_path.display()));
+            self.debug.backlog.fetch_add(1, Ordering::Relaxed);
+            let _ = self.events.send(AppEvent::Scan(self.scan_generation, msg));
+            return;
+        }
+        if let Some((_, drive)) = self.windows_drives.iter().find(|(p, _)| self.current_path.starts_with(p)) {
+            self.notice = Some(format!("{} is a Windows drive; scanning it through WSL is very slow", drive));
+        }
+        let (exclude, limits) = self.scan_settings();
+        // Folders below the depth limit of a deep scan get an ordinary one.
+        let cancel = if self.config.deep_scan && !self.inside_tree() {
+            self.scanner.submit_tree(self.scan_generation, self.current_path.clone(), exclude, limits)
+        } else {
+            let request = ScanRequest {
+                path: self.current_path.clone(),
+                view: self.view_mode,
+                exclude,
+                limits,
+                hints: self.size_hints(&self.current_path),
+                priority: Priority::Foreground,
+            };
+            self.scanner.submit(self.scan_generation, request)
+        };
+        self.scan_cancel = Some(cancel);
+    }
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // Shows the current directory straight from the tree, if it has it.
+    // Going above the root of a deep scan needs a new one.
+    fn show_tree(&mut self) -> bool {
+        let Some(node) = self.tree.as_ref().and_then(|root| root.find(&self.current_path)) else {
+            return false;
+        };
+        // Past the depth limit the tree has totals but no entries.
+        if node.children.is_empty() && node.count > 0 {
+            return false;
+        }
+        let limit = self.config.files_limit as usize;
+        let items = match self.view_mode {
+            ViewMode::Types => scan::types_from_node(node, &self.current_path, limit),
+            ViewMode::Largest => scan::largest_from_node(node, &self.current_path, limit),
+            _ => Some(scan::items_from_node(node, &self.current_path, self.view_mode).0),
+        };
+        let Some(items) = items else {
+            return false;
+        };
+        let items = self.finish_items(items);
+        self.set_items(items);
+        let errors: Vec<ScanError> = self
+            .tree_errors
+            .iter()
+            .filter(|e| e.path.starts_with(&self.current_path))
+            .cloned()
+            .collect();
+        self.scan_errors = Arc::from(errors);
+        self.scan_state = ScanState {
+            scanning: false,
+            scanned: self.items.len() as u64,
+            errors: self.scan_errors.len() as u64,
+            ..ScanState::default()
+        };
+        self.last_error = None;
+        self.notice = None;
+        self.scan_pending = false;
+        true
+    }
 
-    let res = run_app(&mut terminal, start_path);
+    fn scan_settings(&self) -> (Arc<Exclude>, ScanLimits) {
+        let limits = ScanLimits {
+            workers: self.config.size_workers as usize,
+            timeout: Some(Duration::from_secs(self.config.size_timeout as u64)).filter(|t| !t.is_zero()),
+            files: self.config.files_limit as usize,
+            count_links: self.config.count_links,
+            depth: self.depth,
+            one_fs: self.config.one_file_system,
+        };
+        (Arc::clone(&self.exclude), limits)
+    }
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
-    terminal.show_cursor()?;
+    // Opening the biggest block is the most common next step, so the largest
+    // subdirectories are scanned into the cache while the user looks at this
+    // one. They run at background priority, so any real scan goes first and
+    // stops the one that is running; navigating cancels them all.
+    fn prefetch(&mut self) {
+        let (exclude, limits) = self.scan_settings();
+        let mut dirs: Vec<&Item> = self
+            .items
+            .iter()
+            .filter(|i| i.kind == ItemKind::Dir && !i.partial && i.size > 0)
+            .collect();
+        dirs.sort_by_key(|i| std::cmp::Reverse(i.size));
+        let targets: Vec<PathBuf> = dirs
+            .into_iter()
+            .take(PREFETCH_COUNT)
+            .map(|i| i.path.to_path_buf())
+            .filter(|path| {
+                !self.scan_cache.contains(&CacheKey {
+                    path: path.clone(),
+                    view: ViewMode::Dirs,
+                })
+            })
+            .collect();
+        for path in targets {
+            log::debug!("cache", "prefetching {}", path.display());
+            self.generations += 1;
+            let generation = self.generations;
+            let request = ScanRequest {
+                path: path.clone(),
+                view: ViewMode::Dirs,
+                exclude: exclude.clone(),
+                limits,
+                hints: self.size_hints(&path),
+                priority: Priority::Background,
+            };
+            let cancel = self.scanner.submit(generation, request);
+            self.prefetches.insert(generation, (CacheKey { path, view: ViewMode::Dirs }, cancel));
+        }
+    }
 
-    Ok(res?)
-}
+    fn cache_scan(
+        &mut self,
+        path: PathBuf,
+        view: ViewMode,
+        items: &Arc<[Item]>,
+        errors: &Arc<[ScanError]>,
+        stamp: u64,
+    ) {
+        let cached = CachedScan {
+            items: Arc::clone(items),
+            errors: Arc::clone(errors),
+            stamp,
+            bytes: 0,
+            used: 0,
+            from_disk: false,
+        };
+        let key = CacheKey { path, view };
+        self.remember_total(&key, items);
+        self.scan_cache.insert(key, cached);
+    }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<Stdout>>, start_path: PathBuf) -> io::Result<()> {
-    let start_path = fs::canonicalize(&start_path).unwrap_or(start_path);
-    let mut app = App::new(start_path);
-    app.start_scan();
-    app.update_fs_cache();
-    terminal.draw(|f| ui(f, &mut app))?;
-
-    let mut last_frame = Instant::now();
-    loop {
-        let mut dirty = app.update_scan();
-
-        if app.scan_state.scanning && last_frame.elapsed() >= Duration::from_millis(200) {
-            app.spinner = (app.spinner + 1) % 4;
-            dirty = true;
-        }
-
-        if event::poll(Duration::from_millis(200))? {
-            dirty = true;
-            match event::read()? {
-                Event::Key(key) => {
-                    if key.kind == KeyEventKind::Press {
-                        if app.confirm.is_some() {
-                            match key.code {
-                                KeyCode::Char('y') | KeyCode::Enter => {
-                                    let action = app.confirm.take().unwrap();
-                                    if let Err(err) = perform_delete(&action) {
-                                        app.last_error = Some(err);
-                                    }
-                                    app.invalidate_cache_for(&action.target_path);
-                                    if let Some(parent) = action.return_path {
-                                        app.current_path = parent;
-                                        app.view_mode = ViewMode::Dirs;
-                                    }
-                                    app.start_scan();
-                                }
-                                KeyCode::Char('n') | KeyCode::Esc => {
-                                    app.confirm = None;
-                                }
-                                _ => {}
-                            }
-                            continue;
-                        }
-                        match key.code {
-                            KeyCode::Char('q') => break,
-                            KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Up | KeyCode::Left | KeyCode::Esc => {
-                                app.go_up()
-                            }
-                            KeyCode::Char('f') => {
-                                app.view_mode = if app.view_mode == ViewMode::Dirs {
-                                    ViewMode::Files
-                                } else {
-                                    ViewMode::Dirs
-                                };
-                                app.start_scan();
-                            }
-                            KeyCode::Delete => {
-                                if let Some(parent) = app.current_path.parent().map(Path::to_path_buf) {
-                                    let name = app
-                                        .current_path
-                                        .file_name()
-                                        .unwrap_or_default()
-                                        .to_string_lossy()
-                                        .to_string();
-                                    app.confirm = Some(ConfirmAction {
-                                        target_path: app.current_path.clone(),
-                                        target_name: name,
-                                        is_dir: true,
-                                        return_path: Some(parent),
-                                    });
-                                } else {
-                                    app.last_error = Some("Refusing to delete root directory".to_string());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                Event::Mouse(mouse) => {
-                    if let MouseEventKind::Down(_) = mouse.kind {
-                        let x = mouse.column;
-                        let y = mouse.row;
+    // A digest of the settings that change what a folder scan finds. The
+    // cache in memory is emptied when one of them is toggled, so everything
+    // in it was scanned with the current ones.
+    fn cache_settings(&self) -> u64 {
+        let mut digest = diskcache::Digest::new();
+        digest.list(&self.exclude.paths);
+        digest.list(&self.exclude.patterns);
+        digest.list(&self.config.pseudo_filesystems);
+        digest.number(self.config.count_links as u64);
+        digest.number(self.config.one_file_system as u64);
+        digest.number(self.config.files_limit as u64);
+        digest.finish()
+    }
 
-                        if app.confirm.is_some() {
-                            continue;
-                        }
+    fn load_disk_cache(&mut self) {
+        let Some(file) = self.config.cache_file.clone().filter(|_| !self.imported) else {
+            return;
+        };
+        let entries = diskcache::load(&file, self.cache_settings());
+        log::debug!("cache", "loaded {} folders from {}", entries.len(), file.display());
+        for entry in entries {
+            let cached = CachedScan {
+                items: Arc::from(entry.items),
+                errors: Arc::from(entry.errors),
+                stamp: entry.stamp,
+                bytes: 0,
+                used: 0,
+                from_disk: true,
+            };
+            let key = CacheKey { path: entry.path, view: ViewMode::Dirs };
+            self.remember_total(&key, &cached.items);
+            self.scan_cache.insert(key, cached);
+        }
+    }
 
-                        if let Some(up_rect) = app.up_rect {
-                            if contains(up_rect, x, y) {
-                                app.go_up();
-                                continue;
-                            }
-                        }
+    // Only complete folder scans are kept, least recently used first so they
+    // are loaded back in the same order.
+    fn save_disk_cache(&self) {
+        let Some(file) = self.config.cache_file.as_ref().filter(|_| !self.imported) else {
+            return;
+        };
+        let mut scans: Vec<(&CacheKey, &CachedScan)> = self
+            .scan_cache
+            .entries
+            .iter()
+            .filter(|(k, e)| k.view == ViewMode::Dirs && !e.items.iter().any(|i| i.partial))
+            .collect();
+        scans.sort_by_key(|(_, e)| e.used);
+        let entries: Vec<diskcache::Entry> = scans
+            .into_iter()
+            .map(|(k, e)| diskcache::Entry {
+                path: k.path.clone(),
+                stamp: e.stamp,
+                items: e.items.to_vec(),
+                errors: e.errors.to_vec(),
+            })
+            .collect();
+        match diskcache::save(file, self.cache_settings(), &entries) {
+            Ok(()) => log::debug!("cache", "saved {} folders to {}", entries.len(), file.display()),
+            Err(e) => log::warning!("cache", "could not save the scan cache: {}", e),
+        }
+    }
 
-                        if let Some(target) = app.click_map.iter().find(|t| contains(t.rect, x, y)) {
-                            if let Some(item) = app.items.get(target.index) {
-                                if let MouseEventKind::Down(crossterm::event::MouseButton::Right) = mouse.kind {
-                                    app.confirm = Some(ConfirmAction {
-                                        target_path: item.path.clone(),
-                                        target_name: item.name.clone(),
-                                        is_dir: item.kind != ItemKind::File,
-                                        return_path: None,
-                                    });
-                                } else {
-                                    match item.kind {
-                                        ItemKind::Dir => {
-                                            app.current_path = item.path.clone();
-                                            app.view_mode = ViewMode::Dirs;
-                                            app.start_scan();
-                                        }
-                                        ItemKind::FilesAggregate => {
-                                            app.view_mode = ViewMode::Files;
-                                            app.start_scan();
-                                        }
-                                        ItemKind::File => {}
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Event::Resize(_, _) => {}
-                _ => {}
+    fn finish_items(&self, mut items: Vec<Item>) -> Arc<[Item]> {
+        for item in items.iter_mut().filter(|i| i.kind == ItemKind::Dir) {
+            if let Some((_, drive)) = self.windows_drives.iter().find(|(p, _)| **p == *item.path) {
+                item.name = format!("{} ({})", item.name, drive).into();
             }
         }
-        if dirty {
-            app.update_fs_cache();
-            terminal.draw(|f| ui(f, &mut app))?;
-            last_frame = Instant::now();
-        }
+        Arc::from(items)
     }
 
-    Ok(())
-}
+    fn invalidate_cache_for(&mut self, path: &Path) {
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.scan_cache
+            .retain(|k| !k.path.starts_with(&target) && !target.starts_with(&k.path));
+    }
 
-fn ui(f: &mut ratatui::Frame, app: &mut App) {
-    let size = f.size();
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Min(1), Constraint::Length(1)])
-        .split(size);
+    fn set_tree(&mut self, tree: Option<Node>) {
+        self.scan_cache.set_tree_bytes(tree.as_ref().map_or(0, estimate_tree_bytes));
+        self.tree = tree;
+    }
 
-    let main = chunks[0];
-    let bottom = chunks[1];
+    // Drops what is known about the current folder and scans it again.
+    fn refresh(&mut self) {
+        if self.imported {
+            self.last_error = Some("An imported listing cannot be rescanned".to_string());
+            return;
+        }
+        self.set_tree(None);
+        self.mount_points = mount_points(&self.config.pseudo_filesystems);
+        let path = self.current_path.clone();
+        self.invalidate_cache_for(&path);
+        self.start_scan();
+    }
 
-    render_treemap(f, app, main);
-    render_bottom(f, app, bottom);
-}
+    // The free space block, for the folders view of a filesystem's root.
+    fn free_item(&self) -> Option<Item> {
+        if !self.free_space || self.imported || self.view_mode != ViewMode::Dirs {
+            return None;
+        }
+        let (used, total) = self.volumes()?.root?;
+        let free = total.saturating_sub(used);
+        Some(Item {
+            name: "Free space".into(),
+            path: self.current_path.as_path().into(),
+            size: free,
+            allocated: free,
+            kind: ItemKind::FreeSpace,
+            count: 0,
+            partial: false,
+            mtime: 0,
+        })
+    }
 
-fn render_treemap(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
-    app.click_map.clear();
+    fn toggle_free_space(&mut self) {
+        self.free_space = !self.free_space;
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(if self.free_space {
+            "Free space shown at the root of a filesystem".to_string()
+        } else {
+            "Free space hidden".to_string()
+        });
+    }
+
+    // A key typed into the path prompt.
+    fn path_prompt_key(&mut self, key: &KeyEvent) {
+        let Some(prompt) = self.path_prompt.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.path_prompt = None,
+            KeyCode::Enter => {
+                let prompt = self.path_prompt.take().unwrap_or_default();
+                match self.typed_folder(&prompt.text) {
+                    Some(path) if prompt.compare => self.open_compare(self.current_path.clone(), path),
+                    Some(path) => self.go_to(path),
+                    None => {}
+                }
+            }
+            KeyCode::Tab => self.complete_path(),
+            KeyCode::Backspace => {
+                prompt.text.pop();
+                prompt.choices.clear();
+            }
+            KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                prompt.text.push(c);
+                prompt.choices.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Like a shell: the one folder that fits, or as much as all of them have
+    // in common; after that, one of them per Tab.
+    fn complete_path(&mut self) {
+        let Some(prompt) = self.path_prompt.as_ref() else {
+            return;
+        };
+        if !prompt.choices.is_empty() {
+            let next = prompt.choice.map_or(0, |c| (c + 1) % prompt.choices.len());
+            let prompt = self.path_prompt.as_mut().unwrap();
+            prompt.text = format!("{}{}/", prompt.base, prompt.choices[next]);
+            prompt.choice = Some(next);
+            return;
+        }
+        let (base, prefix) = match prompt.text.rfind('/') {
+            Some(i) => prompt.text.split_at(i + 1),
+            None if prompt.text == "~" => ("~/", ""),
+            None => ("", prompt.text.as_str()),
+        };
+        let (base, prefix) = (base.to_string(), prefix.to_string());
+        let mut names: Vec<String> = self
+            .subfolders(&self.typed_path(&base))
+            .into_iter()
+            .filter(|name| name.starts_with(&prefix) && (prefix.starts_with('.') || !name.starts_with('.')))
+            .collect();
+        if self.config.natural_sort {
+            names.sort_by(|a, b| collate::natural_cmp(a, b));
+        } else {
+            names.sort();
+        }
+        let prompt = self.path_prompt.as_mut().unwrap();
+        match names.as_slice() {
+            [] => {}
+            [name] => prompt.text = format!("{}{}/", base, name),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.chars().count(), |len, name| {
+                    first.chars().zip(name.chars()).take_while(|(a, b)| a == b).count().min(len)
+                });
+                let common: String = first.chars().take(common).collect();
+                if common.len() > prefix.len() {
+                    prompt.text = format!("{}{}", base, common);
+                } else {
+                    prompt.choices = names;
+                    prompt.choice = None;
+                    prompt.base = base;
+                }
+            }
+        }
+    }
+
+    // The folders in `dir`, from the tree of an import since there is no
+    // filesystem behind it.
+    fn subfolders(&self, dir: &Path) -> Vec<String> {
+        if self.imported {
+            let Some(node) = self.tree.as_ref().and_then(|root| root.find(dir)) else {
+                return Vec::new();
+            };
+            let names = node.children.iter().filter(|c| c.is_dir).filter_map(|c| c.name.to_str());
+            return names.map(String::from).collect();
+        }
+        let Ok(entries) = self.fs.read_dir(dir) else {
+            return Vec::new();
+        };
+        // Only links need a stat of their own to tell where they lead.
+        let is_dir = |e: &vfs::Entry| match e.kind {
+            Kind::Dir => true,
+            Kind::Symlink => self.fs.metadata(&e.path).is_ok_and(|m| m.kind == Kind::Dir),
+            _ => false,
+        };
+        entries
+            .flatten()
+            .filter(is_dir)
+            .filter_map(|e| e.name.into_string().ok())
+            .collect()
+    }
+
+    // What the prompt text stands for, with ~ and a relative path resolved
+    // and . and .. taken out.
+    fn typed_path(&self, text: &str) -> PathBuf {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let path = match (text.strip_prefix('~'), home) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                home.join(rest.trim_start_matches('/'))
+            }
+            _ => self.current_path.join(text),
+        };
+        let mut clean = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    clean.pop();
+                }
+                other => clean.push(other),
+            }
+        }
+        clean
+    }
+
+    // The typed folder once it is known to be one that can be read.
+    fn typed_folder(&mut self, text: &str) -> Option<PathBuf> {
+        if text.is_empty() {
+            return None;
+        }
+        let path = self.typed_path(text);
+        if self.imported {
+            return match self.tree.as_ref().and_then(|root| root.find(&path)) {
+                Some(node) if node.is_dir => Some(path),
+                _ => {
+                    self.last_error = Some(format!("{} is not a folder in this listing", path.display()));
+                    None
+                }
+            };
+        }
+        let path = match self.fs.canonicalize(&path) {
+            Ok(path) => path,
+            Err(err) => {
+                self.last_error = Some(format!("{}: {}", path.display(), err));
+                return None;
+            }
+        };
+        if !self.fs.metadata(&path).is_ok_and(|m| m.kind == Kind::Dir) {
+            self.last_error = Some(format!("{} is not a folder", path.display()));
+            return None;
+        }
+        if let Err(err) = self.fs.read_dir(&path) {
+            self.last_error = Some(format!("Cannot read {}: {}", path.display(), err));
+            return None;
+        }
+        Some(path)
+    }
+
+    fn open_compare(&mut self, left: PathBuf, right: PathBuf) {
+        if left == right {
+            self.last_error = Some("Pick another folder to compare this one with".to_string());
+            return;
+        }
+        let mut compare = Compare {
+            sides: [CompareSide { path: left, items: None }, CompareSide { path: right, items: None }],
+            selected: 0,
+        };
+        for side in &mut compare.sides {
+            side.items = self.compare_items(&side.path);
+        }
+        self.compare = Some(compare);
+    }
+
+    // The items of a folder to compare, from the tree or the cache; failing
+    // that, a scan is started that fills them in when it is done.
+    fn compare_items(&mut self, path: &Path) -> Option<Arc<[Item]>> {
+        let node = self.tree.as_ref().and_then(|root| root.find(path));
+        if let Some(node) = node.filter(|n| !(n.children.is_empty() && n.count > 0)) {
+            return Some(self.finish_items(scan::items_from_node(node, path, ViewMode::Dirs).0));
+        }
+        let key = CacheKey { path: path.to_path_buf(), view: ViewMode::Dirs };
+        if let Some(cached) = self.scan_cache.get(&key) {
+            return Some(cached.items);
+        }
+        if self.imported {
+            return Some(Arc::from([]));
+        }
+        // A prefetch of the folder would be dropped by the next scan that
+        // comes along, so the comparison gets a scan of its own.
+        self.prefetches.retain(|_, (k, cancel)| {
+            if *k == key {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            *k != key
+        });
+        let (exclude, limits) = self.scan_settings();
+        self.generations += 1;
+        let generation = self.generations;
+        let request = ScanRequest {
+            path: key.path.clone(),
+            view: ViewMode::Dirs,
+            exclude,
+            limits,
+            hints: self.size_hints(path),
+            priority: Priority::Foreground,
+        };
+        let cancel = self.scanner.submit(generation, request);
+        self.prefetches.insert(generation, (key, cancel));
+        None
+    }
+
+    // Gives the waiting sides of the comparison their items.
+    fn fill_compare(&mut self, path: &Path, items: &Arc<[Item]>) -> bool {
+        let Some(compare) = self.compare.as_mut() else {
+            return false;
+        };
+        let mut filled = false;
+        for side in compare.sides.iter_mut().filter(|s| s.items.is_none() && s.path == path) {
+            side.items = Some(Arc::clone(items));
+            filled = true;
+        }
+        filled
+    }
+
+    // A side whose scan was cancelled, by navigating or by a newer scan,
+    // would wait forever; it gets another one.
+    fn refetch_compare(&mut self) {
+        let Some(compare) = &self.compare else {
+            return;
+        };
+        let waiting: Vec<PathBuf> = compare
+            .sides
+            .iter()
+            .filter(|s| s.items.is_none())
+            .filter(|s| !self.prefetches.values().any(|(k, _)| k.path == s.path && k.view == ViewMode::Dirs))
+            .map(|s| s.path.clone())
+            .collect();
+        for path in waiting {
+            if let Some(items) = self.compare_items(&path) {
+                self.fill_compare(&path, &items);
+            }
+        }
+    }
+
+    // What the two folders hold, the biggest differences first.
+    fn compare_rows(&self) -> Vec<CompareRow> {
+        let Some(compare) = &self.compare else {
+            return Vec::new();
+        };
+        let mut rows: Vec<CompareRow> = Vec::new();
+        let mut index: HashMap<(Arc<str>, ItemKind), usize> = HashMap::new();
+        for (side, items) in compare.sides.iter().enumerate() {
+            for item in items.items.iter().flat_map(|items| items.iter()) {
+                let files = item.kind == ItemKind::FilesAggregate && item.count > 0;
+                if !item.kind.is_entry() && !files {
+                    continue;
+                }
+                let size = if self.allocated { item.allocated } else { item.size };
+                let name = CompareRow::name_of(item);
+                let i = *index.entry((Arc::clone(&name), item.kind)).or_insert_with(|| {
+                    rows.push(CompareRow {
+                        name,
+                        kind: item.kind,
+                        sizes: [None; 2],
+                        paths: [None, None],
+                    });
+                    rows.len() - 1
+                });
+                let row = &mut rows[i];
+                row.sizes[side] = Some(size);
+                row.paths[side] = Some(Arc::clone(&item.path));
+            }
+        }
+        rows.sort_by(|a, b| {
+            let biggest = |r: &CompareRow| r.sizes[0].max(r.sizes[1]);
+            b.difference().abs().cmp(&a.difference().abs()).then_with(|| biggest(b).cmp(&biggest(a)))
+        });
+        rows
+    }
+
+    // A key while comparing. Enter compares the selected folder on both
+    // sides, Backspace the folders both are in.
+    fn compare_key(&mut self, key: &KeyEvent) {
+        let rows = self.compare_rows();
+        let Some(compare) = self.compare.as_mut() else {
+            return;
+        };
+        let last = rows.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab => compare.selected = (compare.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab => {
+                compare.selected = compare.selected.saturating_sub(1)
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => compare.selected = (compare.selected + HELP_PAGE).min(last),
+            KeyCode::PageUp => compare.selected = compare.selected.saturating_sub(HELP_PAGE),
+            KeyCode::Home | KeyCode::Char('g') => compare.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => compare.selected = last,
+            KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => {
+                let Some(row) = rows.get(compare.selected) else {
+                    return;
+                };
+                match (&row.paths, row.kind) {
+                    ([Some(left), Some(right)], ItemKind::Dir) => {
+                        let (left, right) = (left.to_path_buf(), right.to_path_buf());
+                        self.open_compare(left, right);
+                    }
+                    _ => self.last_error = Some(format!("{} is not a folder on both sides", row.name)),
+                }
+            }
+            KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Left => {
+                let [left, right] = &compare.sides;
+                if let (Some(left), Some(right)) = (left.path.parent(), right.path.parent()) {
+                    let (left, right) = (left.to_path_buf(), right.to_path_buf());
+                    self.open_compare(left, right);
+                }
+            }
+            KeyCode::Esc => self.compare = None,
+            _ if self.config.keys.is(Action::Compare, key) => self.compare = None,
+            _ => {}
+        }
+    }
+
+    fn open_search(&mut self) {
+        let mut search = self.search.take().unwrap_or_default();
+        search.typing = true;
+        self.search = Some(search);
+    }
+
+    // A key typed into the search box.
+    fn search_key(&mut self, key: &KeyEvent) {
+        let hid = self.search.as_ref().is_some_and(|s| s.hide);
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.search = None,
+            KeyCode::Enter => {
+                search.typing = false;
+                self.select_best_match();
+            }
+            KeyCode::Tab => search.hide = !search.hide,
+            KeyCode::Backspace => {
+                search.query.pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                search.query.push(c)
+            }
+            _ => return,
+        }
+        // Hiding changes the layout; dimming is only a matter of drawing.
+        if hid || self.search.as_ref().is_some_and(|s| s.hide) {
+            self.replace_items(Arc::clone(&self.scanned));
+        }
+    }
+
+    fn close_search(&mut self) {
+        let hid = self.search.take().is_some_and(|s| s.hide);
+        if hid {
+            self.replace_items(Arc::clone(&self.scanned));
+        }
+    }
+
+    // The closest match, the biggest of equally close ones.
+    fn select_best_match(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let best = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| search.rank(&item.name).map(|rank| (rank, std::cmp::Reverse(item.size), i)))
+            .min();
+        match best {
+            Some((_, _, index)) => self.selected = Some(index),
+            None => self.last_error = Some(format!("Nothing here matches '{}'", search.query)),
+        }
+    }
+
+    fn open_find(&mut self) {
+        let mut find = self.find.take().unwrap_or_default();
+        find.typing = true;
+        self.find = Some(find);
+    }
+
+    // A key typed into the find prompt, or used in its results.
+    fn find_key(&mut self, key: &KeyEvent) {
+        let Some(find) = self.find.as_mut() else {
+            return;
+        };
+        if find.typing {
+            match key.code {
+                KeyCode::Esc if find.results.is_empty() => self.find = None,
+                KeyCode::Esc => find.typing = false,
+                KeyCode::Enter => self.run_find(),
+                KeyCode::Tab => find.regex = !find.regex,
+                KeyCode::Backspace => {
+                    find.query.pop();
+                }
+                KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                    find.query.push(c)
+                }
+                _ => {}
+            }
+            return;
+        }
+        let last = find.results.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab => find.selected = (find.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab => find.selected = find.selected.saturating_sub(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => find.selected = (find.selected + HELP_PAGE).min(last),
+            KeyCode::PageUp => find.selected = find.selected.saturating_sub(HELP_PAGE),
+            KeyCode::Home | KeyCode::Char('g') => find.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => find.selected = last,
+            KeyCode::Char('/') => find.typing = true,
+            KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => self.go_to_found(),
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('h') => self.find = None,
+            _ => {}
+        }
+    }
+
+    // Every name below the current folder that matches, the largest first.
+    // Only the tree of a deep scan or an import has them all at hand.
+    fn run_find(&mut self) {
+        let Some(find) = self.find.as_mut() else {
+            return;
+        };
+        let Some(node) = self.tree.as_ref().and_then(|root| root.find(&self.current_path)) else {
+            self.last_error = Some("Finding below this folder needs a deep scan (--deep or deep_scan = true)".to_string());
+            return;
+        };
+        let pattern = if find.regex {
+            match regex::Regex::new(&find.query) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    self.last_error = Some(format!("Bad regular expression: {}", err));
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let query = &find.query;
+        let ignore_case = !query.chars().any(char::is_uppercase);
+        let matches = |name: &str| match &pattern {
+            Some(regex) => regex.is_match(name),
+            None if ignore_case => name.to_lowercase().contains(query.as_str()),
+            None => name.contains(query.as_str()),
+        };
+        let mut results = Vec::new();
+        let mut stack: Vec<(PathBuf, &Node)> = vec![(self.current_path.clone(), node)];
+        while let Some((dir, node)) = stack.pop() {
+            for child in &node.children {
+                let path = dir.join(&child.name);
+                if matches(&scan::display_name(&child.name)) {
+                    let size = if self.allocated { child.allocated } else { child.size };
+                    results.push(Found { path: path.clone(), size, is_dir: child.is_dir });
+                }
+                stack.push((path, child));
+            }
+        }
+        results.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        find.matched = results.len();
+        results.truncate(FIND_LIMIT);
+        find.results = results;
+        find.selected = 0;
+        find.typing = find.results.is_empty();
+        if find.results.is_empty() {
+            self.last_error = Some(format!("Nothing below this folder matches '{}'", find.query));
+        }
+    }
+
+    // Opens the folder a result is in, with the result selected; a file in
+    // the files view so that it has a block of its own.
+    fn go_to_found(&mut self) {
+        let Some(mut find) = self.find.take().filter(|f| f.selected < f.results.len()) else {
+            return;
+        };
+        let found = find.results.swap_remove(find.selected);
+        let Some(parent) = found.path.parent() else {
+            return;
+        };
+        self.current_path = parent.to_path_buf();
+        self.view_mode = if found.is_dir { ViewMode::Dirs } else { ViewMode::Files };
+        self.start_scan();
+        // From the tree the items are there at once.
+        if let Some(index) = self.items.iter().position(|i| *i.path == *found.path) {
+            self.selected = Some(index);
+        }
+    }
+
+    // Where a block is drawn in full: when laid out finer than cells, the
+    // cells it has to itself; the cells it shares are drawn with half blocks.
+    // Whether the cell at `x`, `y` goes to `target`. A cell that blocks
+    // share goes to the one that has its middle.
+    fn hits(&self, target: &ClickTarget, x: u16, y: u16) -> bool {
+        let (sx, sy) = (self.scale.0 as u32, self.scale.1 as u32);
+        match target.fine {
+            Some(fine) => covers(fine, x as u32 * sx + sx / 2, y as u32 * sy + sy / 2),
+            None => contains(target.rect, x, y),
+        }
+    }
+
+    fn drawn_rect(&self, index: usize) -> Rect {
+        match self.fine.get(index) {
+            Some(fine) => cells_owned(*fine, self.scale),
+            None => self.blocks[index].rect,
+        }
+    }
+
+    fn dimmed(&self, item: &Item) -> bool {
+        self.search.as_ref().is_some_and(|s| !s.matches(&item.name))
+    }
+
+    // Bookmarks the current folder, or forgets it if it already is one.
+    fn toggle_bookmark(&mut self) {
+        let path = self.current_path.clone();
+        if let Some(index) = self.bookmarks.iter().position(|b| *b == path) {
+            self.bookmarks.remove(index);
+            self.notice = Some(format!("Bookmark removed: {}", path.display()));
+        } else {
+            self.bookmarks.push(path.clone());
+            self.notice = Some(format!("Bookmarked {}", path.display()));
+        }
+        self.save_bookmarks();
+    }
+
+    fn save_bookmarks(&mut self) {
+        let Some(file) = &self.config.bookmarks_file else {
+            return;
+        };
+        if let Err(err) = bookmarks::save(file, &self.bookmarks) {
+            self.last_error = Some(format!("Could not save the bookmarks: {}", err));
+        }
+    }
+
+    fn open_bookmarks(&mut self) {
+        if self.bookmarks.is_empty() {
+            let key = self.config.keys.first(Action::Bookmark);
+            self.last_error = Some(format!("No bookmarks yet; {} bookmarks the current folder", key));
+            return;
+        }
+        let here = self.bookmarks.iter().position(|b| *b == self.current_path);
+        self.bookmark_list = Some(here.unwrap_or(0));
+    }
+
+    // A key in the bookmark list: 1 to 9 go straight to that bookmark.
+    fn bookmark_key(&mut self, key: &KeyEvent) {
+        let Some(selected) = self.bookmark_list else {
+            return;
+        };
+        let len = self.bookmarks.len();
+        match key.code {
+            KeyCode::Tab | KeyCode::Char('j') | KeyCode::Down => self.bookmark_list = Some((selected + 1) % len),
+            KeyCode::BackTab | KeyCode::Char('k') | KeyCode::Up => {
+                self.bookmark_list = Some((selected + len - 1) % len)
+            }
+            KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => self.go_to_bookmark(selected),
+            KeyCode::Char(c @ '1'..='9') => {
+                let index = c as usize - '1' as usize;
+                if index < len {
+                    self.go_to_bookmark(index);
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                self.bookmarks.remove(selected);
+                self.save_bookmarks();
+                self.bookmark_list = match self.bookmarks.len() {
+                    0 => None,
+                    len => Some(selected.min(len - 1)),
+                };
+            }
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('h') => self.bookmark_list = None,
+            _ if self.config.keys.is(Action::Bookmarks, key) => self.bookmark_list = None,
+            _ => {}
+        }
+    }
+
+    fn go_to_bookmark(&mut self, index: usize) {
+        self.bookmark_list = None;
+        let path = self.bookmarks[index].clone();
+        if !path.is_dir() && self.tree.as_ref().and_then(|root| root.find(&path)).is_none() {
+            self.last_error = Some(format!("{} is not a folder (any more)", path.display()));
+            return;
+        }
+        self.go_to(path);
+    }
+
+    fn toggle_files_strip(&mut self) {
+        self.files_open = !self.files_open;
+        self.fetch_strip_files();
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(if self.files_open {
+            "Files strip: the largest files".to_string()
+        } else {
+            "Files strip: collapsed".to_string()
+        });
+    }
+
+    // The largest files of the folder on screen for the expanded files
+    // strip, if they are known: from the tree, or a files view scan in the
+    // cache. Past STRIP_FILES they are folded into one block.
+    fn strip_files(&mut self) -> Option<Vec<Item>> {
+        if !self.files_open || self.view_mode != ViewMode::Dirs {
+            return None;
+        }
+        let mut files: Vec<Item> = match self.tree.as_ref().and_then(|root| root.find(&self.current_path)) {
+            Some(node) => scan::items_from_node(node, &self.current_path, ViewMode::Files).0,
+            None => {
+                let key = CacheKey { path: self.current_path.clone(), view: ViewMode::Files };
+                self.scan_cache.get(&key)?.items.to_vec()
+            }
+        };
+        let by_size = |i: &Item| std::cmp::Reverse(if self.allocated { i.allocated } else { i.size });
+        files.sort_by_key(|i| (i.kind != ItemKind::File, by_size(i)));
+        let kept = files.iter().take(STRIP_FILES).filter(|i| i.kind == ItemKind::File).count();
+        let rest = files.split_off(kept);
+        if !rest.is_empty() {
+            let count = rest.iter().map(|i| if i.kind == ItemKind::File { 1 } else { i.count }).sum::<u64>();
+            files.push(Item {
+                name: format!("({} more)", count).into(),
+                path: self.current_path.as_path().into(),
+                size: rest.iter().map(|i| i.size).sum(),
+                allocated: rest.iter().map(|i| i.allocated).sum(),
+                kind: ItemKind::Others,
+                count,
+                partial: false,
+                mtime: rest.iter().map(|i| i.mtime).max().unwrap_or(0),
+            });
+        }
+        Some(files)
+    }
+
+    // Lists the files of the folder on screen into the cache when the strip
+    // is expanded and has nothing to show yet.
+    fn fetch_strip_files(&mut self) {
+        if !self.files_open || self.view_mode != ViewMode::Dirs || self.imported || self.inside_tree() {
+            return;
+        }
+        let has_files = self.scanned.iter().any(|i| i.kind == ItemKind::FilesAggregate && i.count > 0);
+        let key = CacheKey { path: self.current_path.clone(), view: ViewMode::Files };
+        if !has_files || self.scan_cache.contains(&key) || self.prefetches.values().any(|(k, _)| *k == key) {
+            return;
+        }
+        let (exclude, limits) = self.scan_settings();
+        self.generations += 1;
+        let generation = self.generations;
+        let request = ScanRequest {
+            path: key.path.clone(),
+            view: ViewMode::Files,
+            exclude,
+            limits,
+            hints: Arc::default(),
+            priority: Priority::Background,
+        };
+        let cancel = self.scanner.submit(generation, request);
+        self.prefetches.insert(generation, (key, cancel));
+    }
+
+    fn is_mount_point(&self, item: &Item) -> bool {
+        item.kind == ItemKind::Dir && !self.imported && self.mount_points.contains(&*item.path)
+    }
+
+    fn go_up(&mut self) {
+        if self.view_mode != ViewMode::Dirs {
+            self.view_mode = ViewMode::Dirs;
+            self.start_scan();
+            return;
+        }
+        if let Some(parent) = self.current_path.parent().map(Path::to_path_buf) {
+            self.current_path = parent;
+            self.start_scan();
+        } else if !self.imported {
+            // There is nothing above /, so offer the other filesystems instead.
+            self.open_volumes();
+        }
+    }
+
+    // One step back or forward through the places shown, like a browser.
+    fn travel(&mut self, back: bool) {
+        let (from, to) = if back { (&mut self.back, &mut self.forward) } else { (&mut self.forward, &mut self.back) };
+        let Some((path, view)) = from.pop() else {
+            return;
+        };
+        if let Some(here) = self.visited.take() {
+            to.push(here);
+        }
+        self.visited = Some((path.clone(), view));
+        self.current_path = path;
+        self.view_mode = view;
+        self.start_scan();
+    }
+
+    // The list shows what the "Other" block stands for.
+    fn open_other(&mut self) {
+        let Some(&first) = self.folded.first() else {
+            return;
+        };
+        self.list = true;
+        self.list_top = first;
+        self.selected = self.selected.map(|_| first);
+    }
+
+    fn go_to(&mut self, path: PathBuf) {
+        self.current_path = path;
+        self.view_mode = ViewMode::Dirs;
+        self.start_scan();
+    }
+
+    fn open_volumes(&mut self) {
+        let mut volumes = fsinfo::volumes();
+        if self.config.natural_sort {
+            volumes.sort_by(|a, b| {
+                collate::natural_cmp(&a.path.to_string_lossy(), &b.path.to_string_lossy())
+            });
+        }
+        if volumes.is_empty() {
+            self.last_error = Some("No mounted filesystems found".to_string());
+            return;
+        }
+        let selected = volumes
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| self.current_path.starts_with(&v.path))
+            .max_by_key(|(_, v)| v.path.as_os_str().len())
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        self.volume_screen = Some(VolumeScreen {
+            volumes,
+            selected,
+            rows: Vec::new(),
+            start: false,
+        });
+    }
+
+    fn choose_volume(&mut self, index: usize) {
+        let Some(screen) = self.volume_screen.take() else {
+            return;
+        };
+        if let Some(volume) = screen.volumes.get(index) {
+            self.current_path = volume.path.clone();
+            self.view_mode = ViewMode::Dirs;
+            self.start_scan();
+        }
+    }
+
+    fn update_scan(&mut self, generation: u64, msg: ScanMsg) -> bool {
+        if let Some((key, _)) = self.prefetches.get(&generation) {
+            let key = key.clone();
+            let (items, errors, stamp) = match msg {
+                ScanMsg::Done { items, errors, stamp, .. } => (items, errors, stamp),
+                // A folder that cannot be read is compared as empty.
+                ScanMsg::Error(err) if key.view == ViewMode::Dirs && self.fill_compare(&key.path, &Arc::from([])) => {
+                    self.last_error = Some(err);
+                    return true;
+                }
+                _ => return false,
+            };
+            let items = self.finish_items(items);
+            self.cache_scan(key.path.clone(), key.view, &items, &Arc::from(errors), stamp);
+            if key.view == ViewMode::Dirs && self.fill_compare(&key.path, &items) {
+                return true;
+            }
+            let strip = key.view == ViewMode::Files && self.view_mode == ViewMode::Dirs;
+            if strip && key.path == self.current_path {
+                self.replace_items(Arc::clone(&self.scanned));
+                return true;
+            }
+            return false;
+        }
+        if generation != self.scan_generation {
+            return false;
+        }
+        match msg {
+            ScanMsg::Progress { scanned, errors } => {
+                self.scan_state.scanned = scanned;
+                self.scan_state.errors = errors;
+                true
+            }
+            ScanMsg::Partial { items } => {
+                self.scan_state.bytes = items.iter().map(|i| if self.allocated { i.allocated } else { i.size }).sum();
+                if self.scan_state.stale {
+                    return false;
+                }
+                let items = self.finish_items(items);
+                self.replace_items(items);
+                true
+            }
+            ScanMsg::Done { items, errors, stamp, .. } => {
+                let items = self.finish_items(items);
+                self.replace_items(items);
+                self.scan_errors = Arc::from(errors);
+                self.cached = false;
+                let (items, errors) = (Arc::clone(&self.scanned), Arc::clone(&self.scan_errors));
+                self.cache_scan(self.current_path.clone(), self.view_mode, &items, &errors, stamp);
+                self.watch_current();
+                self.scan_state.scanned = self.items.len() as u64;
+                self.scan_state.errors = self.scan_errors.len() as u64;
+                self.scan_state.scanning = false;
+                self.fetch_strip_files();
+                if self.config.prefetch && !self.imported && self.view_mode == ViewMode::Dirs {
+                    self.prefetch();
+                }
+                true
+            }
+            ScanMsg::Tree { root, errors } => {
+                self.set_tree(Some(root));
+                self.tree_errors = Arc::from(errors);
+                if !self.show_tree() {
+                    self.scan_state.scanning = false;
+                }
+                true
+            }
+            // Show the cached result meanwhile and refresh it in the background.
+            ScanMsg::Stale => {
+                log::debug!("cache", "{} changed on disk, refreshing", self.current_path.display());
+                self.scan_state.scanning = true;
+                self.scan_state.stale = true;
+                self.scan_state.expected = self.expected_total();
+                self.schedule_scan();
+                true
+            }
+            ScanMsg::Volumes(volumes) => {
+                self.volumes = volumes;
+                self.replace_items(Arc::clone(&self.scanned));
+                true
+            }
+            ScanMsg::Error(err) => {
+                self.last_error = Some(err);
+                self.scan_state.scanning = false;
+                true
+            }
+        }
+    }
+
+    // The filesystems behind the folder on screen, once they are known.
+    fn volumes(&self) -> Option<&scan::Volumes> {
+        Some(&self.volumes).filter(|v| v.path == self.current_path)
+    }
+
+    // Items carry both sizes, so switching needs no rescan.
+    fn toggle_size_mode(&mut self) {
+        self.allocated = !self.allocated;
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(if self.allocated {
+            "Sizes: blocks allocated on disk".to_string()
+        } else {
+            "Sizes: apparent (file lengths)".to_string()
+        });
+    }
+
+    fn age_now(&self) -> Option<i64> {
+        self.age_colors
+            .then(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64))
+    }
+
+    fn toggle_age_colors(&mut self) {
+        self.age_colors = !self.age_colors;
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(if self.age_colors {
+            "Colors: age of the newest change (green this week, red two years or more)".to_string()
+        } else {
+            "Colors: by size rank".to_string()
+        });
+    }
+
+    fn cycle_palette(&mut self) {
+        self.theme.palette = self.theme.palette.next();
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(format!("Palette: {}", self.theme.palette.name()));
+    }
+
+    fn toggle_one_fs(&mut self) {
+        if self.imported {
+            self.last_error = Some("An imported listing cannot be rescanned".to_string());
+            return;
+        }
+        self.config.one_file_system = !self.config.one_file_system;
+        self.set_tree(None);
+        self.scan_cache.retain(|_| false);
+        self.start_scan();
+        self.notice = Some(if self.config.one_file_system {
+            "Staying on this filesystem; other mounts are shown dimmed".to_string()
+        } else {
+            "Crossing into mounted filesystems".to_string()
+        });
+    }
+
+    fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(format!("Sorted by {}", self.sort.name()));
+    }
+
+    fn cycle_labels(&mut self) {
+        self.labels = self.labels.next();
+        self.notice = Some(if self.labels == LabelMode::Disk && self.fs_total == 0 {
+            "Labels: share of the filesystem (unknown here, sizes shown)".to_string()
+        } else {
+            format!("Labels: {}", self.labels.name())
+        });
+    }
+
+    // The texts that may follow a block's name, the longest first, so a
+    // narrow block can fall back to a shorter one.
+    fn size_labels(&self, size_text: &str, size: u64) -> Vec<String> {
+        let at_least = if size_text.starts_with('≥') { "≥" } else { "" };
+        let share = |whole| format!("{}{}", at_least, share_text(size, whole));
+        match self.labels {
+            LabelMode::Size => vec![size_text.to_string()],
+            LabelMode::Share => vec![share(self.total)],
+            LabelMode::Both => vec![format!("{} {}", size_text, share(self.total)), share(self.total)],
+            LabelMode::Disk if self.fs_total > 0 => {
+                vec![format!("{} of disk", share(self.fs_total)), share(self.fs_total)]
+            }
+            LabelMode::Disk => vec![size_text.to_string()],
+        }
+    }
+
+    fn cycle_units(&mut self) {
+        let mode = units::mode().next();
+        units::set_mode(mode);
+        self.replace_items(Arc::clone(&self.scanned));
+        self.notice = Some(match mode {
+            UnitMode::Binary => "Units: binary (1 KiB = 1024 bytes)".to_string(),
+            UnitMode::Si => "Units: SI (1 kB = 1000 bytes)".to_string(),
+            UnitMode::Bytes => "Units: exact bytes".to_string(),
+        });
+    }
+
+    fn cycle_log_level(&mut self) {
+        let Some(path) = log::path() else {
+            self.last_error = Some("Logging is disabled (log_file is empty)".to_string());
+            return;
+        };
+        let level = log::level().next();
+        log::set_level(level);
+        self.notice = Some(format!("Log level {} ({})", level.name(), path.display()));
+    }
+
+    // The scan result is kept as it came; what is shown is sized and ordered
+    // here, so switching either needs no rescan.
+    fn set_items(&mut self, items: Arc<[Item]>) {
+        self.scanned = Arc::clone(&items);
+        let mut shown: Vec<Item> = items.to_vec();
+        if let Some(files) = self.strip_files() {
+            shown.retain(|i| i.kind != ItemKind::FilesAggregate);
+            shown.extend(files);
+        }
+        if let Some(search) = self.search.as_ref().filter(|s| s.hide) {
+            shown.retain(|i| search.matches(&i.name));
+        }
+        if self.allocated {
+            for item in &mut shown {
+                item.size = item.allocated;
+            }
+        }
+        if let Some(free) = self.free_item() {
+            shown.push(free);
+        }
+        if self.config.mount_usage {
+            let mounts = self.volumes().map(|v| &v.mounts);
+            for item in shown.iter_mut().filter(|i| self.is_mount_point(i)) {
+                if let Some(&(Some(used), _)) = mounts.and_then(|m| m.get(&*item.path)) {
+                    item.size = used;
+                    item.allocated = used;
+                }
+            }
+        }
+        sort_items(&mut shown, self.sort, self.config.natural_sort);
+        let items: Arc<[Item]> = Arc::from(shown);
+        self.total = items.iter().map(|i| i.size).sum();
+        self.looks = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| ItemLook::new(&self.theme, self.age_now(), i, item, self.is_mount_point(item)))
+            .collect();
+        self.items = items;
+        self.blocks_area = None;
+    }
+
+    // Swaps in new items for the same directory, keeping the selection on the
+    // same entry while it is still there.
+    fn replace_items(&mut self, items: Arc<[Item]>) {
+        let selected = self.selected_item().map(|i| i.path);
+        self.set_items(items);
+        if let Some(path) = selected {
+            self.selected = self.items.iter().position(|i| i.path == path).or(Some(0));
+        }
+    }
+
+    fn end_scan(&mut self, generation: u64) -> bool {
+        let prefetch = self.prefetches.remove(&generation).is_some();
+        self.refetch_compare();
+        if prefetch {
+            return false;
+        }
+        if generation != self.scan_generation || !self.scan_state.scanning {
+            return false;
+        }
+        self.scan_state.scanning = false;
+        self.scan_cancel = None;
+        true
+    }
+
+    fn toggle_mark(&mut self, item: &Item) {
+        if let Some(pos) = self.marked.iter().position(|m| *m.path == *item.path) {
+            self.marked.remove(pos);
+        } else {
+            self.marked.push(Target::of(item));
+        }
+    }
+
+    fn is_marked(&self, path: &Path) -> bool {
+        self.marked.iter().any(|m| m.path == path)
+    }
+
+    fn move_selection(&mut self, forward: bool) {
+        let len = self.items.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = Some(match self.selected {
+            None => 0,
+            Some(i) if forward => (i + 1) % len,
+            Some(i) => (i + len - 1) % len,
+        });
+    }
+
+    // The largest files have long paths for names, so they are always a list.
+    fn shows_list(&self) -> bool {
+        self.list || self.view_mode == ViewMode::Largest
+    }
+
+    // Moves the selection by whole rows of the list, stopping at either end.
+    fn scroll_list(&mut self, rows: isize) {
+        let last = self.items.len().saturating_sub(1);
+        let current = self.selected.unwrap_or(self.list_top) as isize;
+        self.selected = Some((current + rows).clamp(0, last as isize) as usize);
+    }
+
+    fn selected_item(&self) -> Option<Item> {
+        self.selected.and_then(|i| self.items.get(i)).cloned()
+    }
+
+    fn open(&mut self, item: &Item) {
+        match item.kind {
+            ItemKind::Dir | ItemKind::Mount => {
+                self.current_path = item.path.to_path_buf();
+                self.view_mode = ViewMode::Dirs;
+                self.start_scan();
+            }
+            ItemKind::FilesAggregate if !self.files_open => self.toggle_files_strip(),
+            // The files strip shows only the largest; the rest are in the
+            // files view.
+            ItemKind::FilesAggregate | ItemKind::Others if self.view_mode == ViewMode::Dirs => {
+                self.view_mode = ViewMode::Files;
+                self.start_scan();
+            }
+            ItemKind::File if self.chooser => self.toggle_mark(item),
+            // A file found somewhere below: show it where it lives.
+            ItemKind::File if self.view_mode == ViewMode::Largest => {
+                if let Some(parent) = item.path.parent() {
+                    self.current_path = parent.to_path_buf();
+                    self.view_mode = ViewMode::Files;
+                    self.start_scan();
+                }
+            }
+            ItemKind::File
+            | ItemKind::FilesAggregate
+            | ItemKind::Others
+            | ItemKind::Extension
+            | ItemKind::FreeSpace => {}
+        }
+    }
+
+    // Text files go to $EDITOR when one is set; anything else, and folders,
+    // to whatever the desktop opens them with.
+    fn launch(&mut self, item: &Item) {
+        if !matches!(item.kind, ItemKind::Dir | ItemKind::File | ItemKind::Mount) {
+            return;
+        }
+        if item.kind == ItemKind::File && launch::editor().is_some() && launch::is_text(&item.path) {
+            self.handover = Some(Handover::Edit(item.path.to_path_buf()));
+            return;
+        }
+        match launch::open(&item.path) {
+            Ok(()) => self.notice = Some(format!("Opened {}", item.path.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    // The folder the item is in, in the file manager.
+    fn reveal(&mut self, item: Option<&Item>) {
+        let dir = match item {
+            Some(item) if item.kind.is_entry() => item.path.parent().unwrap_or(&item.path),
+            _ => &self.current_path,
+        };
+        match launch::open(dir) {
+            Ok(()) => self.notice = Some(format!("Opened {}", dir.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    fn confirm_delete(&mut self, item: &Item, trash: bool) {
+        // Aggregates stand for many paths or none; "(Files: N)" has the
+        // folder itself as its path.
+        if !item.kind.is_entry() {
+            return;
+        }
+        if self.config.read_only {
+            self.last_error = Some("Read-only mode: delete disabled".to_string());
+            return;
+        }
+        self.confirm = Some(ConfirmAction {
+            targets: vec![Target::of(item)],
+            trash,
+            marked: false,
+        });
+    }
+
+    // Everything marked, with one confirmation.
+    fn confirm_delete_marked(&mut self, trash: bool) {
+        if self.config.read_only {
+            self.last_error = Some("Read-only mode: delete disabled".to_string());
+            return;
+        }
+        // Whatever lies inside another marked folder goes with it.
+        let targets = self
+            .marked
+            .iter()
+            .filter(|t| !self.marked.iter().any(|o| o.path != t.path && t.path.starts_with(&o.path)))
+            .cloned()
+            .collect();
+        self.confirm = Some(ConfirmAction {
+            targets,
+            trash,
+            marked: true,
+        });
+    }
+
+    // Hooks and removing a big folder take their time, so a confirmed batch
+    // runs on its own thread and the app catches up when it is done.
+    fn start_delete(&mut self, action: ConfirmAction) {
+        let (config, events, fs) = (self.config.clone(), self.events.clone(), Arc::clone(&self.fs));
+        let verb = if action.trash { "Trashing" } else { "Deleting" };
+        self.notice = Some(match action.targets.as_slice() {
+            [one] => format!("{} {}...", verb, one.name),
+            many => format!("{} {} items...", verb, many.len()),
+        });
+        self.deleting = true;
+        thread::spawn(move || {
+            let outcomes = action
+                .targets
+                .iter()
+                .map(|target| {
+                    let (gone, result) = delete_target(fs.as_ref(), &config, target, action.trash);
+                    (target.path.clone(), gone, result)
+                })
+                .collect();
+            let _ = events.send(AppEvent::Deleted(Deleted { outcomes }));
+        });
+    }
+
+    fn finish_delete(&mut self, deleted: Deleted) {
+        self.deleting = false;
+        self.notice = None;
+        let mut failed = None;
+        for (path, gone, result) in deleted.outcomes {
+            self.invalidate_cache_for(&path);
+            if gone {
+                if let Some(tree) = &mut self.tree {
+                    tree.remove(&path);
+                }
+                self.marked.retain(|m| !m.path.starts_with(&path));
+                // The folder on screen went with it.
+                if self.current_path.starts_with(&path) {
+                    if let Some(parent) = path.parent() {
+                        self.current_path = parent.to_path_buf();
+                        self.view_mode = ViewMode::Dirs;
+                    }
+                }
+            }
+            if let Err(err) = result {
+                failed.get_or_insert(err);
+            }
+        }
+        self.start_scan();
+        if let Some(err) = failed {
+            self.last_error = Some(err);
+        }
+    }
+
+    fn export_delete_script(&mut self) {
+        if self.marked.is_empty() {
+            self.last_error = Some("Nothing marked (Ctrl+click to mark)".to_string());
+            return;
+        }
+        let entries: Vec<(PathBuf, u64)> = self.marked.iter().map(|m| (m.path.clone(), m.size)).collect();
+        let out = export::timestamped_name("duviz-cleanup", "sh");
+        match export::write_delete_script(&out, &entries) {
+            Ok(()) => self.notice = Some(format!("Saved {}", out.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    fn export_svg(&mut self) {
+        // The treemap is laid out even while the list is shown.
+        let blocks: Vec<SvgBlock> = self
+            .blocks
+            .iter()
+            .filter(|b| b.rect.width > 0 && b.rect.height > 0)
+            .map(|target| {
+                let (bg, name, size) = match self.items.get(target.index) {
+                    Some(item) => (
+                        block_color(&self.theme, self.age_now(), target.index, item.kind, item.mtime),
+                        item.name.to_string(),
+                        item.size,
+                    ),
+                    None => other_look(self, &self.folded),
+                };
+                SvgBlock {
+                    rect: target.rect,
+                    bg,
+                    fg: palette::text_color(bg),
+                    label: label_for_rect(&name, &self.size_labels(&format_size(size), size), target.rect),
+                }
+            })
+            .collect();
+        if blocks.is_empty() {
+            self.last_error = Some("Nothing to export".to_string());
+            return;
+        }
+        let out = export::timestamped_name("duviz", "svg");
+        match export::write_svg(&out, self.treemap_area, &blocks) {
+            Ok(()) => self.notice = Some(format!("Saved {}", out.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    fn show_errors(&mut self) {
+        if self.scan_errors.is_empty() {
+            self.notice = Some("No scan errors in this view".to_string());
+            return;
+        }
+        self.error_list = Some(0);
+    }
+
+    fn export_errors(&mut self) {
+        if self.scan_errors.is_empty() {
+            self.last_error = Some("No scan errors to export".to_string());
+            return;
+        }
+        let out = export::timestamped_name("duviz-errors", "tsv");
+        match export::write_errors(&out, &self.scan_errors) {
+            Ok(()) => self.notice = Some(format!("Saved {} errors to {}", self.scan_errors.len(), out.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    fn export_png(&mut self) {
+        if self.items.is_empty() {
+            self.last_error = Some("Nothing to export".to_string());
+            return;
+        }
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: self.config.png_width,
+            height: self.config.png_height,
+        };
+        let (blocks, folded) = compute_blocks(self, area, (1, 1));
+        let blocks: Vec<(Rect, Color)> = blocks
+            .into_iter()
+            .map(|b| match self.items.get(b.index) {
+                Some(item) => (b.rect, block_color(&self.theme, self.age_now(), b.index, item.kind, item.mtime)),
+                None => (b.rect, other_look(self, &folded).0),
+            })
+            .collect();
+        let out = export::timestamped_name("duviz", "png");
+        match export::write_png(&out, area.width, area.height, &blocks) {
+            Ok(()) => self.notice = Some(format!("Saved {}", out.display())),
+            Err(err) => self.last_error = Some(err),
+        }
+    }
+
+    fn update_fs_cache(&mut self) {
+        if self.imported || self.fs_last.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        if let Some((used, total)) = self.fs.usage(&self.current_path) {
+            self.fs_used = used;
+            self.fs_total = total;
+        }
+        self.fs_device = self.fs.device(&self.current_path);
+        self.fs_last = Instant::now();
+    }
+}
+
+// For the commands that need nothing else from the config, which a broken
+// one should not stop.
+fn config_units(profile: Option<&str>) -> UnitMode {
+    config::load(profile).map_or(UnitMode::Binary, |config| config.units)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = cli::parse(env::args_os().skip(1)).unwrap_or_else(|e| exit_with_error(&e));
+    match &options.command {
+        Command::Run => {}
+        Command::Version => {
+            println!("duviz {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Command::ConfigInit { path, force } => {
+            let path = config_file_arg(path.as_deref());
+            config::init(&path, *force).unwrap_or_else(|e| exit_with_error(&e));
+            println!("Wrote {}", path.display());
+            return Ok(());
+        }
+        Command::ConfigCheck { path } => {
+            let path = config_file_arg(path.as_deref());
+            let errors = config::check(&path).unwrap_or_else(|e| exit_with_error(&e));
+            if errors.is_empty() {
+                println!("{}: OK", path.display());
+                return Ok(());
+            }
+            for err in &errors {
+                eprintln!("{}:{}", path.display(), err);
+            }
+            std::process::exit(1);
+        }
+        Command::Diff { old, new, limit } => {
+            units::set_mode(config_units(options.profile.as_deref()));
+            let old = snapshot::read(old).unwrap_or_else(|e| exit_with_error(&e));
+            let new = snapshot::read(new).unwrap_or_else(|e| exit_with_error(&e));
+            let rows = snapshot::diff(&old.root, &new.root);
+            print!("{}", snapshot::format_diff(&old, &new, &rows, *limit));
+            return Ok(());
+        }
+        Command::Serve { bind, port } => {
+            units::set_mode(config_units(options.profile.as_deref()));
+            let config = config::load(options.profile.as_deref()).ok();
+            let natural = config.as_ref().is_none_or(|config| config.natural_sort);
+            let opts = TreeOptions { one_fs: config.is_some_and(|config| config.one_file_system), ..HTML_TREE };
+            serve::serve(&options.path, bind, *port, opts, natural).unwrap_or_else(|e| exit_with_error(&e));
+            return Ok(());
+        }
+        Command::Watch { .. } => {}
+        Command::Snapshot { dir, keep } => {
+            let one_fs = config::load(options.profile.as_deref()).is_ok_and(|config| config.one_file_system);
+            let opts = TreeOptions { one_fs, ..SNAPSHOT_TREE };
+            let (root, errors) = scan::scan_tree(&vfs::Os, &options.path, opts, &Exclude::default())
+                .unwrap_or_else(|e| exit_with_error(&e));
+            let path = snapshot::write_rotated(dir, &root, errors.len() as u64, *keep)
+                .unwrap_or_else(|e| exit_with_error(&e));
+            println!("{}", path.display());
+            return Ok(());
+        }
+    }
+    let mut config = config::load(options.profile.as_deref()).unwrap_or_else(|e| exit_with_error(&e));
+    // The flags switch on what the config may already have switched on.
+    options.count_links |= config.count_links;
+    options.allocated = (options.allocated || config.allocated) && !options.apparent;
+    options.one_fs |= config.one_file_system;
+    options.exclude.splice(0..0, config.exclude.iter().cloned());
+    config.count_links = options.count_links;
+    config.allocated = options.allocated;
+    config.one_file_system = options.one_fs;
+    config.exclude = options.exclude.clone();
+    config.deep_scan |= options.deep;
+    log::init(config.log_file.clone(), config.log_level);
+    units::set_mode(config.units);
+    log::info!("main", "duviz {} started on {}", env!("CARGO_PKG_VERSION"), options.path.display());
+    if let Command::Watch { interval } = options.command {
+        watch::run(&options.path, Duration::from_secs(interval), &config).unwrap_or_else(|e| exit_with_error(&e));
+        return Ok(());
+    }
+
+    if let Some(format) = options.report {
+        if options.import.is_some() {
+            exit_with_error("--import cannot be combined with report output");
+        }
+        run_report(&vfs::Os, format, &options, &config).unwrap_or_else(|e| exit_with_error(&e));
+        return Ok(());
+    }
+    let imported = options
+        .import
+        .as_deref()
+        .map(|path| import::read_listing(path).unwrap_or_else(|e| exit_with_error(&e)));
+    if imported.is_some() {
+        // Nothing on disk corresponds to the listing, so never offer to delete.
+        config.read_only = true;
+    }
+    let scheme = config
+        .color_scheme
+        .as_deref()
+        .map(|path| palette::load_scheme(path).unwrap_or_else(|e| exit_with_error(&e)));
+
+    enable_raw_mode()?;
+    // Before the alternate screen and the input thread, so the terminal's
+    // answer is read here instead of showing up as key presses.
+    let theme = Theme {
+        depth: config.colors.unwrap_or_else(palette::detect),
+        background: config
+            .theme
+            .or(scheme.map(|s| s.background()))
+            .unwrap_or_else(palette::detect_background),
+        scheme,
+        palette: config.palette,
+    };
+    let graphics = graphics::detect(config.graphics);
+    let mut stdout = io::stdout();
+    if config.set_title {
+        push_title(&mut stdout)?;
+    }
+    execute!(stdout, EnterAlternateScreen)?;
+    if options.no_mouse || !mouse_supported() {
+        config.mouse = false;
+    }
+    if config.mouse && execute!(stdout, EnableMouseCapture).is_err() {
+        config.mouse = false;
+    }
+    let set_title = config.set_title;
+    let mouse = config.mouse;
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal(mouse, set_title);
+        default_hook(info);
+        // The UI would carry on over a terminal no longer set up for it, with
+        // the work of the panicked thread silently undone.
+        if thread::current().name() != Some("main") {
+            std::process::abort();
+        }
+    }));
+    handle_signals(mouse, set_title);
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let res = run_app(&mut terminal, &options, config, theme, imported, graphics);
+
+    restore_terminal(mouse, set_title);
+
+    let marked = res?;
+    if let Some(out) = &options.choose_files {
+        write_chosen(out, &marked).unwrap_or_else(|e| exit_with_error(&e));
+    }
+    Ok(())
+}
+
+// Leaves raw mode, the alternate screen and mouse reporting. Runs on normal
+// exit as well as from the panic hook and on fatal signals.
+fn restore_terminal(mouse: bool, set_title: bool) {
+    let mut stdout = io::stdout();
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout, LeaveAlternateScreen);
+    if mouse {
+        let _ = execute!(stdout, DisableMouseCapture);
+    }
+    if set_title {
+        let _ = pop_title(&mut stdout);
+    }
+    let _ = execute!(stdout, Show);
+}
+
+// Termination signals are blocked in every thread (threads inherit the mask,
+// so this runs before any are spawned) and collected by one thread that can
+// safely restore the terminal, then exit with the usual 128+N status.
+fn handle_signals(mouse: bool, set_title: bool) {
+    let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut set);
+        for sig in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT, libc::SIGQUIT] {
+            libc::sigaddset(&mut set, sig);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+    }
+    thread::spawn(move || {
+        let mut sig = 0;
+        if unsafe { libc::sigwait(&set, &mut sig) } == 0 {
+            restore_terminal(mouse, set_title);
+            std::process::exit(128 + sig);
+        }
+    });
+}
+
+// The Linux console and dumb terminals never report mouse events.
+fn mouse_supported() -> bool {
+    !matches!(env::var("TERM").as_deref(), Ok("linux") | Ok("dumb") | Ok("") | Err(_))
+}
+
+fn write_chosen(out: &Path, paths: &[PathBuf]) -> Result<(), String> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let mut buf = Vec::new();
+    for path in paths {
+        buf.extend_from_slice(path.as_os_str().as_bytes());
+        buf.push(b'\n');
+    }
+    fs::write(out, buf).map_err(|e| format!("{}: {}", out.display(), e))
+}
+
+fn run_report(
+    fs: &dyn FileSystem,
+    format: ReportFormat,
+    options: &cli::Options,
+    config: &Config,
+) -> Result<(), String> {
+    let started = Instant::now();
+    // Reports cross into other filesystems unless -x or the config says not to.
+    let one_fs = options.one_fs || config.one_file_system;
+    let exclude = Exclude {
+        paths: Vec::new(),
+        pseudo: fsinfo::pseudo_mounts(&config.pseudo_filesystems),
+        patterns: options.exclude.clone(),
+    };
+    let base = fs.canonicalize(&options.path).unwrap_or_else(|_| options.path.clone());
+    // Streamed reports walk everything but only print the first levels.
+    let shown = |path: &Path| {
+        options
+            .depth
+            .is_none_or(|depth| path.strip_prefix(&base).map_or(0, |rel| rel.iter().count()) <= depth)
+    };
+    let errors = match format {
+        ReportFormat::Html => {
+            let out = options.out.as_deref().ok_or("--html requires an output file")?;
+            let opts = TreeOptions {
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                max_depth: options.depth.unwrap_or(HTML_TREE.max_depth),
+                ..HTML_TREE
+            };
+            let (root, errors) = scan::scan_tree(fs, &options.path, opts, &exclude)?;
+            export::write_html(out, &root, errors.len() as u64, config.natural_sort)?;
+            errors
+        }
+        ReportFormat::Prometheus => {
+            let opts = TreeOptions {
+                max_depth: 1,
+                max_children: usize::MAX,
+                keep_files: false,
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                mounts: false,
+                du: false,
+            };
+            let (root, errors) = scan::scan_tree(fs, &options.path, opts, &exclude)?;
+            let text = export::prometheus(&root, errors.len() as u64, started.elapsed());
+            export::write_output(options.out.as_deref(), &text)?;
+            errors
+        }
+        ReportFormat::Snapshot => {
+            let opts = TreeOptions {
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                max_depth: options.depth.unwrap_or(SNAPSHOT_TREE.max_depth),
+                ..SNAPSHOT_TREE
+            };
+            let (root, errors) = scan::scan_tree(fs, &options.path, opts, &exclude)?;
+            let text = snapshot::to_json(&root, errors.len() as u64);
+            export::write_output(options.out.as_deref(), &text)?;
+            errors
+        }
+        ReportFormat::Ncdu => {
+            let opts = TreeOptions {
+                max_depth: options.depth.unwrap_or(usize::MAX),
+                max_children: usize::MAX,
+                keep_files: true,
+                follow_links: options.follow_links,
+                // Both sizes are exported; nodes keep the allocated one anyway.
+                allocated: false,
+                count_links: options.count_links,
+                one_fs,
+                mounts: false,
+                // ncdu lists the size of each folder itself, as du counts it.
+                du: true,
+            };
+            let (root, errors) = scan::scan_tree(fs, &options.path, opts, &exclude)?;
+            export::write_output(options.out.as_deref(), &ncdu::to_json(&root))?;
+            errors
+        }
+        ReportFormat::Markdown => {
+            let opts = TreeOptions {
+                max_depth: 1,
+                max_children: usize::MAX,
+                keep_files: false,
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                mounts: false,
+                du: false,
+            };
+            let mut largest: Vec<(u64, PathBuf)> = Vec::new();
+            let (root, errors) = scan::scan_tree_with(fs, &options.path, opts, &exclude, &mut |path, node| {
+                if !node.is_dir {
+                    largest.push((node.size, path.to_path_buf()));
+                    if largest.len() >= export::MARKDOWN_ROWS * 8 {
+                        largest.sort_by_key(|f| std::cmp::Reverse(f.0));
+                        largest.truncate(export::MARKDOWN_ROWS);
+                    }
+                }
+                true
+            })?;
+            largest.sort_by_key(|f| std::cmp::Reverse(f.0));
+            largest.truncate(export::MARKDOWN_ROWS);
+            let text = export::markdown(&root, errors.len() as u64, &largest, fs.usage(root.path()));
+            export::write_output(options.out.as_deref(), &text)?;
+            errors
+        }
+        ReportFormat::Du => {
+            let (block, suffix) = match &options.block_size {
+                Some(b) => (b.bytes, b.suffix.as_str()),
+                None => (1024, ""),
+            };
+            let mut out = io::BufWriter::new(io::stdout().lock());
+            let mut failed = None;
+            let opts = TreeOptions {
+                max_depth: 0,
+                max_children: 0,
+                keep_files: false,
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                mounts: false,
+                du: true,
+            };
+            // du prints directories after their contents, under the path as it was given.
+            let (_, errors) = scan::scan_tree_with(fs, &options.path, opts, &exclude, &mut |path, node| {
+                if !node.is_dir || !shown(path) {
+                    return true;
+                }
+                let shown = match path.strip_prefix(&base) {
+                    Ok(rel) if rel.as_os_str().is_empty() => options.path.clone(),
+                    Ok(rel) => options.path.join(rel),
+                    Err(_) => path.to_path_buf(),
+                };
+                let line = write!(out, "{}{}\t", node.size.div_ceil(block), suffix)
+                    .and_then(|_| out.write_all(shown.as_os_str().as_bytes()))
+                    .and_then(|_| out.write_all(b"\n"));
+                if let Err(e) = line {
+                    failed = Some(e);
+                    return false;
+                }
+                true
+            })?;
+            if let Err(e) = out.flush() {
+                failed.get_or_insert(e);
+            }
+            match failed {
+                Some(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                Some(e) => return Err(format!("write failed: {}", e)),
+                None => {}
+            }
+            errors
+        }
+        ReportFormat::JsonLines => {
+            let mut sink: Box<dyn Write> = match &options.out {
+                Some(path) => Box::new(io::LineWriter::new(
+                    fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+                )),
+                None => Box::new(io::stdout().lock()),
+            };
+            let mut failed = None;
+            let opts = TreeOptions {
+                max_depth: 0,
+                max_children: 0,
+                keep_files: false,
+                follow_links: options.follow_links,
+                allocated: options.allocated,
+                count_links: options.count_links,
+                one_fs,
+                mounts: false,
+                du: false,
+            };
+            let (_, errors) = scan::scan_tree_with(fs, &options.path, opts, &exclude, &mut |path, node| {
+                if !shown(path) {
+                    return true;
+                }
+                if let Err(e) = writeln!(sink, "{}", export::json_line(path, node)) {
+                    failed = Some(e);
+                    return false;
+                }
+                true
+            })?;
+            match failed {
+                Some(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                Some(e) => return Err(format!("write failed: {}", e)),
+                None if !errors.is_empty() && options.errors_out.is_none() => {
+                    eprintln!("duviz: {} entries could not be read", errors.len());
+                }
+                None => {}
+            }
+            errors
+        }
+    };
+    match &options.errors_out {
+        Some(path) => export::write_errors(path, &errors),
+        None => Ok(()),
+    }
+}
+
+fn config_file_arg(path: Option<&Path>) -> PathBuf {
+    match path {
+        Some(p) => p.to_path_buf(),
+        None => config::config_path()
+            .unwrap_or_else(|| exit_with_error("cannot locate config directory (HOME is not set)")),
+    }
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!("duviz: {}", msg);
+    std::process::exit(2);
+}
+
+// Applies one terminal event to the app. Returns false when the user quits.
+// Runs one action of the main screen. None when it does not apply here, so
+// the next action bound to the same key gets its turn.
+fn run_action(app: &mut App, action: Action) -> Option<bool> {
+    let page = app.treemap_area.height as isize;
+    match action {
+        Action::RowUp | Action::RowDown | Action::PageUp | Action::PageDown | Action::First | Action::Last
+            if !app.shows_list() =>
+        {
+            return None
+        }
+        Action::RowUp => app.scroll_list(-1),
+        Action::RowDown => app.scroll_list(1),
+        Action::PageUp => app.scroll_list(-page),
+        Action::PageDown => app.scroll_list(page),
+        Action::First => app.scroll_list(isize::MIN / 2),
+        Action::Last => app.scroll_list(isize::MAX / 2),
+        Action::Quit => return Some(false),
+        Action::Help => app.help = Some(0),
+        Action::Debug => app.debug.visible = !app.debug.visible,
+        Action::LogLevel => app.cycle_log_level(),
+        Action::Units => app.cycle_units(),
+        Action::Volumes => app.open_volumes(),
+        Action::Up => app.go_up(),
+        Action::Back => app.travel(true),
+        Action::Forward => app.travel(false),
+        Action::Next => app.move_selection(true),
+        Action::Previous => app.move_selection(false),
+        Action::List => app.list = !app.list,
+        Action::Open => {
+            if let Some(item) = app.selected_item() {
+                app.open(&item);
+            }
+        }
+        Action::Shell => app.handover = Some(Handover::Shell(app.current_path.clone())),
+        Action::Launch => {
+            if let Some(item) = app.selected_item() {
+                app.launch(&item);
+            }
+        }
+        Action::Reveal => {
+            let item = app.selected_item();
+            app.reveal(item.as_ref());
+        }
+        Action::Mark => {
+            if let Some(item) = app.selected_item().filter(|i| i.kind.is_entry()) {
+                app.toggle_mark(&item);
+            }
+        }
+        Action::Delete | Action::Trash => {
+            let trash = action == Action::Trash || !app.config.permanent_delete;
+            if !app.marked.is_empty() {
+                app.confirm_delete_marked(trash);
+            } else if let Some(item) = app.selected_item().filter(|i| i.kind.is_entry()) {
+                app.confirm_delete(&item, trash);
+            }
+        }
+        Action::Refresh => app.refresh(),
+        Action::ExportSvg => app.export_svg(),
+        Action::ExportPng => app.export_png(),
+        Action::ExportErrors => app.export_errors(),
+        Action::Errors => app.show_errors(),
+        Action::DeleteScript => app.export_delete_script(),
+        Action::SizeMode => app.toggle_size_mode(),
+        Action::Sort => app.cycle_sort(),
+        Action::Labels => app.cycle_labels(),
+        Action::OneFileSystem => app.toggle_one_fs(),
+        Action::AgeColors => app.toggle_age_colors(),
+        Action::FreeSpace => app.toggle_free_space(),
+        Action::Palette => app.cycle_palette(),
+        Action::Search => app.open_search(),
+        Action::Find => app.open_find(),
+        Action::GoTo => app.path_prompt = Some(PathPrompt::default()),
+        Action::Compare => app.path_prompt = Some(PathPrompt { compare: true, ..PathPrompt::default() }),
+        Action::Bookmark => app.toggle_bookmark(),
+        Action::Bookmarks => app.open_bookmarks(),
+        Action::Files if app.view_mode == ViewMode::Dirs => app.toggle_files_strip(),
+        Action::Files | Action::Types | Action::Largest => {
+            let view = match action {
+                Action::Files => ViewMode::Files,
+                Action::Types => ViewMode::Types,
+                _ => ViewMode::Largest,
+            };
+            app.view_mode = if app.view_mode == view { ViewMode::Dirs } else { view };
+            app.start_scan();
+        }
+        Action::DeleteFolder | Action::DeleteFolderPermanently if app.config.read_only => {
+            app.last_error = Some("Read-only mode: delete disabled".to_string());
+        }
+        Action::DeleteFolder | Action::DeleteFolderPermanently => {
+            if app.current_path.parent().is_some() {
+                let name = scan::display_name(app.current_path.file_name().unwrap_or_default());
+                let target = Target {
+                    path: app.current_path.clone(),
+                    name,
+                    is_dir: true,
+                    size: app.items.iter().filter(|i| i.kind != ItemKind::FreeSpace).map(|i| i.size).sum(),
+                    files: app.items.iter().map(|i| if i.kind == ItemKind::File { 1 } else { i.count }).sum(),
+                };
+                app.confirm = Some(ConfirmAction {
+                    targets: vec![target],
+                    trash: action == Action::DeleteFolder && !app.config.permanent_delete,
+                    marked: false,
+                });
+            } else {
+                app.last_error = Some("Refusing to delete root directory".to_string());
+            }
+        }
+    }
+    Some(true)
+}
+
+fn handle_event(app: &mut App, event: Event) -> bool {
+    match event {
+        Event::Key(key) if key.kind == KeyEventKind::Press => {
+            if app.confirm.is_some() {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter if app.deleting => {
+                        app.last_error = Some("Still deleting the last batch; try again when it is done".to_string());
+                    }
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        let action = app.confirm.take().unwrap();
+                        app.start_delete(action);
+                    }
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        app.confirm = None;
+                    }
+                    _ => {}
+                }
+                return true;
+            }
+            let quit = app.config.keys.is(Action::Quit, &key);
+            let help = app.config.keys.is(Action::Help, &key);
+            if let Some(scroll) = &mut app.help {
+                if quit {
+                    return false;
+                }
+                if help {
+                    app.help = None;
+                    return true;
+                }
+                match key.code {
+                    KeyCode::Char('j') | KeyCode::Down => *scroll = scroll.saturating_add(1),
+                    KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                    KeyCode::PageDown | KeyCode::Char(' ') => *scroll = scroll.saturating_add(HELP_PAGE),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(HELP_PAGE),
+                    KeyCode::Home | KeyCode::Char('g') => *scroll = 0,
+                    // Clamped to the last page when drawn.
+                    KeyCode::End | KeyCode::Char('G') => *scroll = usize::MAX,
+                    KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('h') => app.help = None,
+                    _ => {}
+                }
+                return true;
+            }
+            if let Some(scroll) = &mut app.error_list {
+                if quit {
+                    return false;
+                }
+                if app.config.keys.is(Action::Errors, &key) {
+                    app.error_list = None;
+                    return true;
+                }
+                if app.config.keys.is(Action::ExportErrors, &key) {
+                    app.export_errors();
+                    return true;
+                }
+                match key.code {
+                    KeyCode::Char('j') | KeyCode::Down => *scroll = scroll.saturating_add(1),
+                    KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                    KeyCode::PageDown | KeyCode::Char(' ') => *scroll = scroll.saturating_add(HELP_PAGE),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(HELP_PAGE),
+                    KeyCode::Home | KeyCode::Char('g') => *scroll = 0,
+                    KeyCode::End | KeyCode::Char('G') => *scroll = usize::MAX,
+                    KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('h') => app.error_list = None,
+                    _ => {}
+                }
+                return true;
+            }
+            if let Some(screen) = &mut app.volume_screen {
+                if quit {
+                    return false;
+                }
+                let len = screen.volumes.len();
+                match key.code {
+                    KeyCode::Tab | KeyCode::Char('j') | KeyCode::Down => screen.selected = (screen.selected + 1) % len,
+                    KeyCode::BackTab | KeyCode::Char('k') | KeyCode::Up => {
+                        screen.selected = (screen.selected + len - 1) % len
+                    }
+                    KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => {
+                        let selected = screen.selected;
+                        app.choose_volume(selected);
+                    }
+                    KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Char('V') => {
+                        let start = screen.start;
+                        app.volume_screen = None;
+                        if start {
+                            app.start_scan();
+                        }
+                    }
+                    _ => {}
+                }
+                return true;
+            }
+            if app.bookmark_list.is_some() {
+                if quit {
+                    return false;
+                }
+                app.bookmark_key(&key);
+                return true;
+            }
+            if app.find.is_some() {
+                if quit && !app.find.as_ref().is_some_and(|f| f.typing) {
+                    return false;
+                }
+                app.find_key(&key);
+                return true;
+            }
+            if app.path_prompt.is_some() {
+                app.path_prompt_key(&key);
+                return true;
+            }
+            if app.compare.is_some() {
+                // What sizes mean and how they read carry over.
+                for action in [Action::Quit, Action::Help, Action::SizeMode, Action::Units] {
+                    if app.config.keys.is(action, &key) {
+                        return run_action(app, action).unwrap_or(true);
+                    }
+                }
+                app.compare_key(&key);
+                return true;
+            }
+            if app.search.as_ref().is_some_and(|s| s.typing) {
+                app.search_key(&key);
+                return true;
+            }
+            // Esc drops a search before it goes up a level.
+            if app.search.is_some() && key.code == KeyCode::Esc {
+                app.close_search();
+                return true;
+            }
+            for action in app.config.keys.actions(&key) {
+                if let Some(keep_running) = run_action(app, action) {
+                    return keep_running;
+                }
+            }
+        }
+        Event::Mouse(mouse) => {
+            if let Some(scroll) = app.help.as_mut().or(app.error_list.as_mut()) {
+                match mouse.kind {
+                    MouseEventKind::ScrollDown => *scroll = scroll.saturating_add(3),
+                    MouseEventKind::ScrollUp => *scroll = scroll.saturating_sub(3),
+                    _ => {}
+                }
+                return true;
+            }
+            if mouse.kind == MouseEventKind::Moved {
+                app.hover = Some((mouse.column, mouse.row));
+                return true;
+            }
+            // The blocks under these are not what the click map knows.
+            if app.compare.is_some() || app.find.is_some() || app.bookmark_list.is_some() {
+                return true;
+            }
+            if app.shows_list() && app.confirm.is_none() && app.volume_screen.is_none() {
+                match mouse.kind {
+                    MouseEventKind::ScrollDown => app.scroll_list(3),
+                    MouseEventKind::ScrollUp => app.scroll_list(-3),
+                    _ => {}
+                }
+            }
+            if let MouseEventKind::Down(_) = mouse.kind {
+                let x = mouse.column;
+                let y = mouse.row;
+
+                if app.confirm.is_some() {
+                    return true;
+                }
+
+                if let Some(screen) = &app.volume_screen {
+                    if let Some(index) = screen.rows.iter().position(|r| contains(*r, x, y)) {
+                        app.choose_volume(index);
+                    }
+                    return true;
+                }
+
+                if let Some(up_rect) = app.up_rect {
+                    if contains(up_rect, x, y) {
+                        app.go_up();
+                        return true;
+                    }
+                }
+
+                if app.other_target.is_some_and(|t| app.hits(&t, x, y)) {
+                    app.open_other();
+                    return true;
+                }
+
+                if let Some((_, path)) = app.crumbs.iter().find(|(r, _)| contains(*r, x, y)) {
+                    let path = path.clone();
+                    app.go_to(path);
+                    return true;
+                }
+
+                if let Some(target) = app.click_map.iter().find(|t| app.hits(t, x, y)) {
+                    if let Some(item) = app.items.get(target.index) {
+                        let left = mouse.kind == MouseEventKind::Down(MouseButton::Left);
+                        let ctrl = mouse.modifiers.contains(KeyModifiers::CONTROL);
+                        let markable = item.kind.is_entry();
+                        if left && markable && (ctrl || (app.chooser && item.kind == ItemKind::File)) {
+                            let item = item.clone();
+                            app.toggle_mark(&item);
+                            return true;
+                        }
+                        let item = item.clone();
+                        if mouse.kind == MouseEventKind::Down(MouseButton::Middle) {
+                            app.launch(&item);
+                        } else if let MouseEventKind::Down(crossterm::event::MouseButton::Right) = mouse.kind {
+                            // Shift is the way to skip the trash.
+                            let shift = mouse.modifiers.contains(KeyModifiers::SHIFT);
+                            app.confirm_delete(&item, !shift && !app.config.permanent_delete);
+                        } else {
+                            app.open(&item);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    true
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    options: &cli::Options,
+    config: Config,
+    theme: Theme,
+    imported: Option<Node>,
+    graphics: Option<Box<dyn graphics::ImageProtocol>>,
+) -> io::Result<Vec<PathBuf>> {
+    let start_path = match &imported {
+        Some(root) => root.path().to_path_buf(),
+        None => fs::canonicalize(&options.path).unwrap_or_else(|_| options.path.clone()),
+    };
+    let chooser = options.choose_files.is_some();
+    let (events_tx, events) = mpsc::channel();
+    let input_paused = Arc::new(AtomicBool::new(false));
+    spawn_input_thread(events_tx.clone(), Arc::clone(&input_paused));
+    spawn_ticker(events_tx.clone());
+    let mut app = App::new(start_path, config, theme, chooser, events_tx);
+    app.imported = imported.is_some();
+    app.set_tree(imported);
+    app.graphics = graphics;
+    app.depth = options.depth;
+    if options.files {
+        app.view_mode = ViewMode::Files;
+    }
+    app.load_disk_cache();
+    // Like gdu without an argument it can read: pick a filesystem first.
+    if !app.imported && (options.devices || fs::read_dir(&app.current_path).is_err()) {
+        app.open_volumes();
+    }
+    match &mut app.volume_screen {
+        Some(screen) => screen.start = true,
+        None => app.start_scan(),
+    }
+    app.update_fs_cache();
+    let mut title_path: Option<PathBuf> = None;
+    if app.config.set_title {
+        update_title(terminal, &app.current_path, &mut title_path)?;
+    }
+    draw(terminal, &mut app)?;
+
+    let mut dirty = false;
+    let mut typed = false;
+    let mut last_spin = Instant::now();
+    let mut last_draw = Instant::now();
+    while let Ok(event) = events.recv() {
+        match event {
+            // Drawn at once instead of on the next tick, so the layout and the
+            // click targets never lag behind the new size.
+            AppEvent::Input(Event::Resize(_, _)) => {
+                app.blocks_area = None;
+                app.image = None;
+                terminal.autoresize()?;
+                draw(terminal, &mut app)?;
+                last_draw = Instant::now();
+                dirty = false;
+            }
+            AppEvent::Input(event) => {
+                dirty = true;
+                typed = true;
+                if !handle_event(&mut app, event) {
+                    break;
+                }
+                if let Some(handover) = app.handover.take() {
+                    // The picture would stay over whatever runs.
+                    show_picture(terminal, &mut app, None)?;
+                    let outcome = hand_over(terminal, app.config.mouse, &input_paused, || match &handover {
+                        Handover::Edit(path) => launch::edit(&launch::editor().unwrap_or_default(), path),
+                        Handover::Shell(dir) => launch::shell(dir),
+                    })?;
+                    // Whatever ran may well have changed the files on screen.
+                    if !app.imported {
+                        app.refresh();
+                    }
+                    if let Err(err) = outcome {
+                        app.last_error = Some(err);
+                    }
+                }
+            }
+            AppEvent::Scan(generation, msg) => {
+                app.debug.backlog.fetch_sub(1, Ordering::Relaxed);
+                dirty |= app.update_scan(generation, msg);
+            }
+            AppEvent::ScanEnded(generation) => {
+                app.debug.backlog.fetch_sub(1, Ordering::Relaxed);
+                dirty |= app.end_scan(generation);
+            }
+            // Frames are only drawn on ticks, so bursts of input or scan
+            // messages are coalesced into one redraw.
+            AppEvent::Changed(dir) => app.folder_changed(dir),
+            AppEvent::Deleted(deleted) => {
+                app.finish_delete(deleted);
+                dirty = true;
+            }
+            AppEvent::Tick => {
+                app.flush_pending_scan();
+                dirty |= app.flush_changes();
+                let animated = app.scan_state.scanning || app.debug.visible;
+                if animated && last_spin.elapsed() >= SPINNER_INTERVAL {
+                    app.spinner = (app.spinner + 1) % 4;
+                    last_spin = Instant::now();
+                    dirty = true;
+                }
+                if dirty && last_draw.elapsed() >= frame_interval(&app, typed) {
+                    app.update_fs_cache();
+                    if app.config.set_title {
+                        update_title(terminal, &app.current_path, &mut title_path)?;
+                    }
+                    draw(terminal, &mut app)?;
+                    last_draw = Instant::now();
+                    dirty = false;
+                    typed = false;
+                }
+            }
+        }
+    }
+
+    show_picture(terminal, &mut app, None)?;
+    app.save_disk_cache();
+    Ok(app.marked.into_iter().map(|m| m.path).collect())
+}
+
+// crossterm's read blocks, so input gets its own thread feeding the event
+// channel; it ends with the process. It polls rather than waits, so it can
+// stand by while an editor has the terminal.
+fn spawn_input_thread(events: Sender<AppEvent>, paused: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        if paused.load(Ordering::Relaxed) {
+            thread::sleep(INPUT_POLL);
+            continue;
+        }
+        match event::poll(INPUT_POLL) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => return,
+        }
+        let Ok(event) = event::read() else {
+            return;
+        };
+        if events.send(AppEvent::Input(event)).is_err() {
+            return;
+        }
+    });
+}
+
+// Leaves the screen to an editor or a shell and puts it back afterwards. The
+// outer result is the terminal, the inner one the program.
+fn hand_over(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    mouse: bool,
+    input_paused: &AtomicBool,
+    run: impl FnOnce() -> Result<(), String>,
+) -> io::Result<Result<(), String>> {
+    input_paused.store(true, Ordering::Relaxed);
+    // A poll already under way runs out before the editor starts reading.
+    thread::sleep(INPUT_POLL * 2);
+    let mut stdout = io::stdout();
+    if mouse {
+        execute!(stdout, DisableMouseCapture)?;
+    }
+    execute!(stdout, LeaveAlternateScreen, Show)?;
+    disable_raw_mode()?;
+    let result = run();
+    enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+    terminal.clear()?;
+    input_paused.store(false, Ordering::Relaxed);
+    Ok(result)
+}
+
+fn spawn_ticker(events: Sender<AppEvent>) {
+    thread::spawn(move || {
+        while events.send(AppEvent::Tick).is_ok() {
+            thread::sleep(FRAME_INTERVAL);
+        }
+    });
+}
+
+fn update_title(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    path: &Path,
+    last: &mut Option<PathBuf>,
+) -> io::Result<()> {
+    if last.as_deref() == Some(path) {
+        return Ok(());
+    }
+    execute!(terminal.backend_mut(), SetTitle(format!("duviz — {}", scan::display_name(path.as_os_str()))))?;
+    *last = Some(path.to_path_buf());
+    Ok(())
+}
+
+fn push_title(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(b"\x1b[22;0t")?;
+    out.flush()
+}
+
+fn pop_title(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(b"\x1b[23;0t")?;
+    out.flush()
+}
+
+// Input is answered at the full frame rate; a running scan alone only gets a
+// few frames per second. Frames never take more than a third of the time, so
+// a slow terminal does not starve event handling.
+fn frame_interval(app: &App, typed: bool) -> Duration {
+    let base = if app.scan_state.scanning && !typed {
+        BUSY_FRAME_INTERVAL
+    } else {
+        FRAME_INTERVAL
+    };
+    base.max(app.debug.last_frame * 3)
+}
+
+fn draw(terminal: &mut Terminal<CrosstermBackend<Stdout>>, app: &mut App) -> io::Result<()> {
+    let start = Instant::now();
+    let frame = terminal.draw(|f| ui(f, app))?;
+    // Everything that goes into the picture, so it is only sent again when
+    // it changed.
+    let wanted = match &app.graphics {
+        Some(protocol) if picture_wanted(app) => {
+            let text = if protocol.text_on_top() { Vec::new() } else { picture_text(frame.buffer, app.treemap_area)? };
+            let mut hasher = DefaultHasher::new();
+            (app.treemap_area, app.scale, &app.fine, &text).hash(&mut hasher);
+            for i in 0..app.fine.len() {
+                block_fill(app, i).hash(&mut hasher);
+            }
+            for inner in &app.inner {
+                (inner.rect, inner.style.bg).hash(&mut hasher);
+            }
+            Some((hasher.finish(), text))
+        }
+        _ => None,
+    };
+    show_picture(terminal, app, wanted)?;
+    let elapsed = start.elapsed();
+    app.debug.draws += 1;
+    app.debug.last_frame = elapsed;
+    app.debug.slowest_frame = app.debug.slowest_frame.max(elapsed);
+    Ok(())
+}
+
+fn ui(f: &mut ratatui::Frame, app: &mut App) {
+    let size = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(size);
+
+    let main = chunks[0];
+    let bottom = chunks[1];
+
+    if app.volume_screen.is_some() {
+        render_volumes(f, app, main);
+    } else if app.compare.is_some() {
+        render_compare(f, app, main);
+    } else {
+        render_treemap(f, app, main);
+    }
+    render_bottom(f, app, bottom);
+    if app.debug.visible {
+        render_debug(f, app, main);
+    }
+    if app.error_list.is_some() {
+        render_errors(f, app, main);
+    }
+    if app.find.is_some() {
+        render_find(f, app, main);
+    }
+    if app.bookmark_list.is_some() {
+        render_bookmarks(f, app, main);
+    }
+    if app.help.is_some() {
+        render_help(f, app, main);
+    }
+}
+
+// Key, what it does; an empty key starts a section, an empty action is a note.
+// One row of the help: what the mouse (or the status line) does, the actions
+// whose keys are listed after it, and what they do. A row with neither starts
+// a section.
+const HELP: &[(&str, &[Action], &str)] = &[
+    ("", &[], "Moving around"),
+    ("Click", &[Action::Open], "open the selected folder; the files block opens the files view"),
+    ("", &[Action::Up], "go up; above / pick another filesystem"),
+    ("[Up] in the bottom bar", &[], "go up"),
+    ("A folder in the bottom bar path", &[], "go straight to that folder"),
+    ("Hover", &[], "full name, exact size, share, item count and last change in the bottom bar"),
+    ("", &[Action::Next], "select the next block"),
+    ("", &[Action::Previous], "select the previous block"),
+    ("", &[Action::Back], "go back to the previous place, like a browser"),
+    ("", &[Action::Forward], "go forward again"),
+    ("", &[Action::Volumes], "pick another mounted filesystem"),
+    ("", &[Action::Compare], "compare this folder with another: both treemaps on one scale, and what differs"),
+    ("", &[Action::GoTo], "type the path of a folder to go to, Tab completes it; relative unless it starts with / or ~"),
+    ("", &[Action::Bookmark], "bookmark this folder, or remove its bookmark"),
+    ("", &[Action::Bookmarks], "the bookmarks: Enter or 1-9 goes to one, d removes it"),
+    ("Middle-click", &[Action::Launch], "open with $EDITOR (text) or the default application"),
+    ("", &[Action::Reveal], "show the folder it is in, in the file manager"),
+    ("", &[Action::Shell], "a shell ($SHELL) in this folder; exit it to come back and rescan"),
+    ("", &[], "Views"),
+    ("", &[Action::Files], "show the largest files in the files strip, or hide them; back to the folders view"),
+    ("", &[Action::Types], "file types: everything below this folder by extension"),
+    ("", &[Action::Largest], "the largest files anywhere below this folder; open one to go to it"),
+    ("", &[Action::List], "switch between the treemap and a list with bars, shares and item counts"),
+    ("", &[Action::Search], "search by name: others are dimmed, Tab hides them, Enter selects the best match"),
+    ("", &[Action::Find], "with a deep scan: find names or (Tab) regexes anywhere below; Enter goes to one"),
+    (
+        "",
+        &[Action::RowUp, Action::RowDown, Action::PageUp, Action::PageDown, Action::First, Action::Last],
+        "in the list: move along the rows (the wheel too)",
+    ),
+    ("", &[Action::SizeMode], "switch between apparent sizes and blocks allocated on disk"),
+    ("", &[Action::Sort], "sort by size, name, item count or last modified (newest first)"),
+    ("", &[Action::Labels], "label blocks with their size, share of this folder, both, or share of the disk"),
+    ("", &[Action::AgeColors], "color by age of the newest change: green this week, red years ago"),
+    ("", &[Action::Palette], "cycle the palette: default, deuteranopia, high contrast, monochrome"),
+    ("", &[Action::Refresh], "scan this folder again, dropping what was cached"),
+    ("", &[Action::OneFileSystem], "stay on this filesystem (other mounts dimmed) or cross into mounts"),
+    ("", &[Action::FreeSpace], "show or hide the free space block at the root of a filesystem"),
+    ("", &[Action::Debug], "debug overlay: frame times, scan throughput, cache"),
+    ("", &[Action::LogLevel], "cycle the log level"),
+    ("", &[], "Marking and deleting"),
+    ("Ctrl+click", &[Action::Mark], "mark or unmark the block"),
+    ("", &[Action::DeleteScript], "write a cleanup script for the marked paths"),
+    ("Right-click", &[Action::Delete], "move to the trash (delete if permanent_delete is set)"),
+    ("", &[Action::Delete, Action::Trash], "with blocks marked: all of them, after one confirmation"),
+    ("Shift+right-click", &[], "delete permanently"),
+    ("", &[Action::Trash], "move to the trash, even with permanent_delete"),
+    ("", &[Action::DeleteFolder], "remove the folder you are in (to the trash unless permanent_delete)"),
+    ("", &[Action::DeleteFolderPermanently], "remove the folder you are in permanently"),
+    ("", &[], "Exports"),
+    ("", &[Action::ExportSvg], "save the treemap as SVG in the working directory"),
+    ("", &[Action::ExportPng], "save the treemap as PNG (png_width x png_height)"),
+    ("", &[Action::ExportErrors], "save the scan errors of this view as TSV"),
+    ("", &[Action::Errors], "list the scan errors of this view: the folders that were under-counted"),
+    ("", &[], "Sizes"),
+    ("", &[Action::Units], "binary units (1 KiB is 1024 bytes), SI units (1 kB is 1000) or exact bytes"),
+    ("≥ size", &[], "a folder that took longer than size_timeout; what was counted"),
+    ("(N more)", &[], "the files beyond the files_limit largest, as one block"),
+    ("Other (N items)", &[], "items too small for a block of their own; click it to list them"),
+    ("", &[], "Other"),
+    ("", &[Action::Help], "show or hide this help"),
+    ("", &[Action::Quit], "quit"),
+];
+const HELP_PAGE: usize = 10;
+
+fn render_help(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    let mut lines: Vec<Line> = Vec::new();
+    let size_mode = if app.allocated { "blocks allocated on disk" } else { "apparent sizes" };
+    let view = match app.view_mode {
+        ViewMode::Dirs => "folders",
+        ViewMode::Files => "files",
+        ViewMode::Types => "file types",
+        ViewMode::Largest => "largest files",
+    };
+    lines.push(Line::from(format!("Showing the {} view with {}, sorted by {}.", view, size_mode, app.sort.name())));
+    if app.config.read_only {
+        lines.push(Line::styled("Read-only mode: deleting is disabled.", Style::default().fg(app.theme.ui(Ui::Warn))));
+    }
+    // The key column follows the [keys] remapping.
+    let rows: Vec<(String, &str)> = HELP
+        .iter()
+        .map(|(fixed, actions, text)| {
+            let keys = std::iter::once(fixed.to_string())
+                .chain(actions.iter().map(|a| app.config.keys.label(*a)))
+                .filter(|k| !k.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ");
+            (keys, *text)
+        })
+        .collect();
+    let key_width = rows.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+    for ((fixed, actions, _), (key, text)) in HELP.iter().zip(&rows) {
+        if fixed.is_empty() && actions.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(*text, Style::default().fg(app.theme.ui(Ui::Accent)).add_modifier(Modifier::BOLD)));
+        } else {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<1$}  ", key, key_width), Style::default().fg(app.theme.ui(Ui::Key))),
+                Span::raw(*text),
+            ]));
+        }
+    }
+    let visible = area.height.saturating_sub(2) as usize;
+    let last = lines.len().saturating_sub(visible);
+    let scroll = app.help.map_or(0, |s| s.min(last));
+    app.help = Some(scroll);
+    let title = if last > 0 {
+        format!(" help {}/{}  j/k scroll, Esc close ", scroll + 1, last + 1)
+    } else {
+        " help  Esc close ".to_string()
+    };
+    let p = Paragraph::new(lines)
+        .scroll((scroll as u16, 0))
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(p, area);
+}
+
+// The scan errors as "path  message" rows, in the help's frame.
+fn render_errors(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    let lines: Vec<Line> = app
+        .scan_errors
+        .iter()
+        .map(|e| {
+            Line::from(vec![
+                Span::styled(format!("{}  ", e.path.display()), Style::default().fg(app.theme.ui(Ui::Bad))),
+                Span::raw(e.message.as_str()),
+            ])
+        })
+        .collect();
+    let visible = area.height.saturating_sub(2) as usize;
+    let last = lines.len().saturating_sub(visible);
+    let scroll = app.error_list.map_or(0, |s| s.min(last));
+    app.error_list = Some(scroll);
+    let plural = if lines.len() == 1 { "" } else { "s" };
+    let save = app.config.keys.first(Action::ExportErrors);
+    let title = if last > 0 {
+        format!(
+            " {} scan error{} {}/{}  j/k scroll, {} save, Esc close ",
+            lines.len(),
+            plural,
+            scroll + 1,
+            last + 1,
+            save
+        )
+    } else {
+        format!(" {} scan error{}  {} save, Esc close ", lines.len(), plural, save)
+    };
+    let p = Paragraph::new(lines)
+        .scroll((scroll as u16, 0))
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(p, area);
+}
+
+// The find prompt and its results as "size  path" rows, in the help's frame.
+fn render_find(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let Some(find) = &app.find else {
+        return;
+    };
+    let kind = if find.regex { "regex" } else { "name" };
+    let mut lines = vec![if find.typing {
+        let other = if find.regex { "name" } else { "regex" };
+        Line::from(format!("Find {}: {}█  Enter search, Tab {}, Esc close", kind, find.query, other))
+    } else {
+        Line::from(format!("Find {}: {}  / edit, Enter go there, Esc close", kind, find.query))
+    }];
+    lines.push(Line::from(""));
+    let visible = area.height.saturating_sub(4) as usize;
+    let scroll = (find.selected + 1).saturating_sub(visible);
+    for (i, found) in find.results.iter().enumerate().skip(scroll).take(visible) {
+        let path = found.path.strip_prefix(&app.current_path).unwrap_or(&found.path);
+        let suffix = if found.is_dir { "/" } else { "" };
+        let style = if i == find.selected && !find.typing {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:>10}  ", format_size(found.size)), Style::default().fg(app.theme.ui(Ui::Accent))),
+            Span::styled(format!("{}{}", path.display(), suffix), style),
+        ]));
+    }
+    let title = match find.results.len() {
+        0 => " Find below this folder ".to_string(),
+        shown if shown < find.matched => format!(" {} matches, the largest {} shown ", find.matched, shown),
+        1 => " 1 match ".to_string(),
+        shown => format!(" {} matches ", shown),
+    };
+    let p = Paragraph::new(lines)
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(p, area);
+}
+
+// Both folders' treemaps over the list of differences. The smaller folder
+// gets a smaller treemap, so equal areas mean equal sizes on both sides, and
+// a name has the same color on both.
+fn render_compare(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let Some(compare) = &app.compare else {
+        return;
+    };
+    let rows = app.compare_rows();
+    let selected = rows.get(compare.selected);
+    let ranks: HashMap<(&str, ItemKind), usize> =
+        rows.iter().enumerate().map(|(i, row)| ((&*row.name, row.kind), i)).collect();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Min(4)])
+        .split(area);
+    let halves = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+    let size = |item: &Item| if app.allocated { item.allocated } else { item.size };
+    let totals = compare
+        .sides
+        .iter()
+        .map(|side| side.items.as_ref().map_or(0, |items| items.iter().map(size).sum()))
+        .collect::<Vec<u64>>();
+    let largest = totals.iter().copied().max().unwrap_or(0);
+    f.render_widget(Clear, area);
+    for (side, half) in compare.sides.iter().zip(halves.iter()) {
+        let total = side.items.as_ref().map(|items| items.iter().map(size).sum::<u64>());
+        let heading = match total {
+            Some(total) => format!("{}  {}", side.path.display(), format_size(total)),
+            None => format!("{}  scanning...", side.path.display()),
+        };
+        // A column between the two sides.
+        let gap = if half.x > area.x { 1 } else { 0 };
+        let heading_area = Rect { x: half.x + gap, width: half.width.saturating_sub(gap), height: 1, ..*half };
+        f.render_widget(
+            Paragraph::new(heading).style(Style::default().fg(app.theme.ui(Ui::Accent)).add_modifier(Modifier::BOLD)),
+            heading_area,
+        );
+        let (Some(items), Some(total)) = (&side.items, total) else {
+            continue;
+        };
+        let full = Rect { y: half.y + 1, height: half.height.saturating_sub(1), ..heading_area };
+        // Height in proportion to the size keeps the width of both sides.
+        let height = match largest {
+            0 => 0,
+            largest => ((full.height as f64 * total as f64 / largest as f64).ceil() as u16).min(full.height),
+        };
+        let map_area = Rect { height, ..full };
+        let sizes: Vec<(usize, u64)> = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| size(item) > 0)
+            .map(|(i, item)| (i, size(item)))
+            .collect();
+        let buf = f.buffer_mut();
+        for block in treemap(&sizes, map_area) {
+            let item = &items[block.index];
+            let rank = ranks.get(&(&*CompareRow::name_of(item), item.kind)).copied().unwrap_or(block.index);
+            let color = palette::color_for_item(&app.theme, rank, item.kind);
+            let mut style = Style::default().bg(color).fg(palette::text_color(color));
+            if selected.is_some_and(|r| r.name == item.name && r.kind == item.kind) {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            buf.set_style(block.rect, style);
+            if let Some(label) = label_for_rect(&item.name, &[format_size(size(item))], block.rect) {
+                buf.set_stringn(block.rect.x, block.rect.y, label, block.rect.width as usize, style);
+            }
+        }
+    }
+    let list = chunks[1];
+    let difference = |d: i128| match d {
+        0 => "same".to_string(),
+        d if d > 0 => format!("+{}", format_size(d as u64)),
+        d => format!("-{}", format_size(d.unsigned_abs() as u64)),
+    };
+    let side_size = |s: Option<u64>| s.map_or_else(|| "-".to_string(), format_size);
+    let mut lines = vec![Line::styled(
+        format!("{:>10}  {:>10}  {:>11}  {}", "left", "right", "difference", "name"),
+        Style::default().fg(app.theme.ui(Ui::Muted)),
+    )];
+    let visible = list.height.saturating_sub(3) as usize;
+    let scroll = (compare.selected + 1).saturating_sub(visible);
+    for (i, row) in rows.iter().enumerate().skip(scroll).take(visible) {
+        let suffix = if row.kind == ItemKind::Dir { "/" } else { "" };
+        let color = match row.difference() {
+            0 => app.theme.ui(Ui::Muted),
+            d if d > 0 => app.theme.ui(Ui::Warn),
+            _ => app.theme.ui(Ui::Good),
+        };
+        let style = if i == compare.selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:>10}  {:>10}  ", side_size(row.sizes[0]), side_size(row.sizes[1])), style),
+            Span::styled(format!("{:>11}  ", difference(row.difference())), style.fg(color)),
+            Span::styled(format!("{}{}", row.name, suffix), style),
+        ]));
+    }
+    let title = match totals.as_slice() {
+        [left, right] if compare.sides.iter().all(|s| s.items.is_some()) => {
+            format!(" {} entries, right minus left {} ", rows.len(), difference(*right as i128 - *left as i128))
+        }
+        _ => " Comparing ".to_string(),
+    };
+    let p = Paragraph::new(lines)
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(p, list);
+}
+
+// The bookmarks, numbered for the keys that go straight to them.
+fn render_bookmarks(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let Some(selected) = app.bookmark_list else {
+        return;
+    };
+    let lines: Vec<Line> = app
+        .bookmarks
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let number = if i < 9 { format!("{} ", i + 1) } else { "  ".to_string() };
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(number, Style::default().fg(app.theme.ui(Ui::Key))),
+                Span::styled(path.display().to_string(), style),
+            ])
+        })
+        .collect();
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let visible = height.saturating_sub(2) as usize;
+    let scroll = (selected + 1).saturating_sub(visible);
+    let title = " Bookmarks  Enter go, 1-9 go to that one, d remove, Esc close ";
+    let p = Paragraph::new(lines)
+        .scroll((scroll as u16, 0))
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    let overlay_area = centered_rect(70, height, area);
+    f.render_widget(Clear, overlay_area);
+    f.render_widget(p, overlay_area);
+}
+
+fn render_debug(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let stats = &app.debug;
+    let throughput = match app.last_launch {
+        Some(t) if app.scan_state.scanning => {
+            format!("{:.0} entries/s", app.scan_state.scanned as f64 / t.elapsed().as_secs_f64().max(0.001))
+        }
+        _ => "idle".to_string(),
+    };
+    let (busy, workers) = scan::sizing_load();
+    let lookups = stats.cache_hits + stats.cache_misses;
+    let hit_rate = (stats.cache_hits * 100).checked_div(lookups).unwrap_or(0);
+    let lines = [
+        format!("frame  {:.1} ms (max {:.1} ms)", ms(stats.last_frame), ms(stats.slowest_frame)),
+        format!("draws  {}", stats.draws),
+        format!("scan   {}", throughput),
+        format!("sizing {}/{} workers busy", busy, workers),
+        format!("queue  {} scan messages", stats.backlog.load(Ordering::Relaxed)),
+        format!("cache  {} hits, {} misses ({}%)", stats.cache_hits, stats.cache_misses, hit_rate),
+        format!(
+            "memory ~{} in {} scans{}{}",
+            format_size(app.scan_cache.bytes as u64),
+            app.scan_cache.entries.len(),
+            match app.scan_cache.tree_bytes {
+                0 => String::new(),
+                bytes => format!(" + ~{} tree", format_size(bytes as u64)),
+            },
+            match app.scan_cache.limit {
+                0 => String::new(),
+                limit => format!(" (max {})", format_size(limit as u64)),
+            }
+        ),
+    ];
+    let width = 48.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let rect = Rect {
+        x: area.x + area.width - width,
+        y: area.y,
+        width,
+        height,
+    };
+    let text: Vec<Line> = lines.into_iter().map(Line::from).collect();
+    let p = Paragraph::new(text)
+        .style(overlay_style(&app.theme))
+        .block(Block::default().borders(Borders::ALL).title("debug"));
+    f.render_widget(Clear, rect);
+    f.render_widget(p, rect);
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn render_treemap(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    app.click_map.clear();
+    app.other_target = None;
+    app.treemap_area = area;
+
+    if area.width < 2 || area.height < 2 {
+        return;
+    }
+
+    f.render_widget(Clear, area);
+
+    if app.scan_state.scanning && app.items.is_empty() {
+        let p = Paragraph::new(scan_status(app, area.width)).style(Style::default().fg(app.theme.ui(Ui::Warn)));
+        f.render_widget(Clear, area);
+        f.render_widget(p, area);
+        return;
+    }
+
+    if app.items.is_empty() {
+        let msg = if let Some(err) = &app.last_error {
+            format!("Error: {}", err)
+        } else {
+            "Empty directory".to_string()
+        };
+        let p = Paragraph::new(msg).style(Style::default().fg(app.theme.ui(Ui::Warn)));
+        f.render_widget(Clear, area);
+        f.render_widget(p, area);
+        return;
+    }
+
+    // The layout only changes with the items or the terminal size.
+    if app.blocks_area != Some(area) {
+        let _span = log::span(log::Level::Trace, "layout", || format!("{} blocks", app.items.len()));
+        app.scale = match app.graphics.as_ref().and(graphics::cell_size()) {
+            Some(cell) => cell,
+            None if app.config.half_blocks => (1, 2),
+            None => (1, 1),
+        };
+        if app.scale != (1, 1) {
+            let blocks;
+            (blocks, app.folded) = compute_blocks(app, scaled(area, app.scale), app.scale);
+            app.fine = blocks.iter().map(|b| b.rect).collect();
+            let scale = app.scale;
+            app.blocks = blocks.into_iter().map(|b| BlockRect { rect: cells_touched(b.rect, scale), ..b }).collect();
+        } else {
+            (app.blocks, app.folded) = compute_blocks(app, area, app.scale);
+            app.fine.clear();
+        }
+        app.inner = nested_blocks(app);
+        app.blocks_area = Some(area);
+    }
+    if app.shows_list() {
+        render_list(f, app, area);
+    } else {
+        for i in 0..app.blocks.len() {
+            let block = app.blocks[i];
+            if block.rect.width < 1 || block.rect.height < 1 {
+                continue;
+            }
+            let drawn = BlockRect { rect: app.drawn_rect(i), ..block };
+            let target = ClickTarget {
+                rect: block.rect,
+                fine: app.fine.get(i).copied(),
+                index: block.index,
+            };
+            if block.index == OTHER_BLOCK {
+                draw_other(f, app, drawn.rect);
+                app.other_target = Some(target);
+                continue;
+            }
+            draw_block(f, app, &drawn);
+            app.click_map.push(target);
+        }
+        if !app.fine.is_empty() {
+            draw_shared_cells(f, app, area);
+        }
+        let buf = f.buffer_mut();
+        for inner in &app.inner {
+            buf.set_style(inner.rect, inner.style);
+            if let Some(label) = &inner.label {
+                buf.set_stringn(inner.rect.x, inner.rect.y, label, inner.rect.width as usize, inner.style);
+            }
+        }
+    }
+
+    // Under a picture a refresh shows in the status line instead.
+    if app.scan_state.scanning && !(app.scan_state.stale && picture_wanted(app)) {
+        let overlay = Paragraph::new(scan_status(app, area.width * 40 / 100))
+            .style(Style::default().fg(app.theme.ui(Ui::Warn)).add_modifier(Modifier::BOLD));
+        let overlay_area = centered_rect(40, 3, area);
+        f.render_widget(Clear, overlay_area);
+        f.render_widget(overlay, overlay_area);
+    }
+
+    if let Some(confirm) = &app.confirm {
+        let size: u64 = confirm.targets.iter().map(|t| t.size).sum();
+        let files: u64 = confirm.targets.iter().map(|t| t.files).sum();
+        let plural = if files == 1 { "" } else { "s" };
+        let what = match confirm.targets.as_slice() {
+            [one] => {
+                let marked = if confirm.marked { "the marked " } else { "" };
+                format!("{}{} {}", marked, if one.is_dir { "directory" } else { "file" }, one.name)
+            }
+            many => format!("{} marked items", many.len()),
+        };
+        let (question, warning, color) = if confirm.trash {
+            (format!("Move {} to the trash?", what), "It can be restored from there.", app.theme.ui(Ui::Warn))
+        } else {
+            (format!("Permanently delete {}?", what), "This cannot be undone.", app.theme.ui(Ui::Bad))
+        };
+        let mut lines = vec![question, format!("{}, {} file{}", format_size(size), format_count(files), plural)];
+        // Everything that goes, as far as the screen allows. Marked entries
+        // may be in other folders than this one, so their paths are shown
+        // even when there is only one.
+        if confirm.marked {
+            let room = (area.height as usize).saturating_sub(8).max(2);
+            let shown = if confirm.targets.len() > room { room - 1 } else { room };
+            for target in confirm.targets.iter().take(shown) {
+                lines.push(format!("  {:>10}  {}", format_size(target.size), target.path.display()));
+            }
+            if confirm.targets.len() > shown {
+                lines.push(format!("  ... and {} more", confirm.targets.len() - shown));
+            }
+            let selected = app.selected_item().filter(|i| i.kind.is_entry() && !app.is_marked(&i.path));
+            if let Some(item) = selected {
+                lines.push(format!("The selected {} is not marked and stays.", item.name));
+            }
+        }
+        lines.extend([warning.to_string(), String::new(), "[y]es / [n]o".to_string()]);
+        let height = (lines.len() as u16 + 1).min(area.height);
+        let width = if confirm.marked { 80 } else { 60 };
+        let overlay = Paragraph::new(lines.join("\n"))
+            .style(Style::default().fg(color).add_modifier(Modifier::BOLD))
+            .block(Block::default().style(Style::default().bg(app.theme.overlay().1)));
+        let overlay_area = centered_rect(width, height, area);
+        f.render_widget(Clear, overlay_area);
+        f.render_widget(overlay, overlay_area);
+    }
+}
+
+// The scan overlay: counts, and once the scan can be measured against an
+// earlier one or the filesystem, how far it is, the time left and a bar.
+fn scan_status(app: &App, width: u16) -> Vec<Line<'static>> {
+    let spinner = match app.spinner {
+        0 => "|",
+        1 => "/",
+        2 => "-",
+        _ => "\\",
+    };
+    let mut msg = format!("Scanning {}  items={} errors={}", spinner, app.scan_state.scanned, app.scan_state.errors);
+    let Some(expected) = app.scan_state.expected.filter(|&e| e > 0) else {
+        return vec![Line::from(msg)];
+    };
+    // A scan that finds more than expected stays just short of done.
+    let done = (app.scan_state.bytes as f64 / expected as f64).min(0.99);
+    msg.push_str(&format!("  {:.0}%", done * 100.0));
+    if let Some(started) = app.last_launch.filter(|_| done >= 0.01) {
+        let left = started.elapsed().as_secs_f64() * (1.0 - done) / done;
+        msg.push_str(&format!(", about {} left", duration_text(left)));
+    }
+    let filled = (width as f64 * done).round() as usize;
+    let bar = format!("{}{}", "━".repeat(filled), "─".repeat((width as usize).saturating_sub(filled)));
+    vec![Line::from(msg), Line::styled(bar, Style::default().fg(app.theme.ui(Ui::Muted)))]
+}
 
-    if area.width < 2 || area.height < 2 {
-        return;
+fn duration_text(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
     }
+}
 
+fn render_volumes(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    let Some(screen) = &mut app.volume_screen else {
+        return;
+    };
     f.render_widget(Clear, area);
+    screen.rows.clear();
+    let title = if screen.start {
+        format!(
+            "Filesystems (Enter to scan, Esc for {}, {} to quit)",
+            app.current_path.display(),
+            app.config.keys.first(Action::Quit)
+        )
+    } else {
+        "Filesystems (Enter to scan, Esc to go back)".to_string()
+    };
+    let title = Paragraph::new(title)
+        .style(Style::default().fg(app.theme.ui(Ui::Warn)).add_modifier(Modifier::BOLD));
+    f.render_widget(title, Rect { height: 1, ..area });
 
-    if app.scan_state.scanning && app.items.is_empty() {
-        let spinner = match app.spinner {
-            0 => "|",
-            1 => "/",
-            2 => "-",
-            _ => "\\",
-        };
-        let msg = format!("Scanning {}  items={} errors={}", spinner, app.scan_state.scanned, app.scan_state.errors);
-        let p = Paragraph::new(msg).style(Style::default().fg(Color::Yellow));
-        f.render_widget(Clear, area);
-        f.render_widget(p, area);
-        return;
+    let path_w = screen
+        .volumes
+        .iter()
+        .map(|v| scan::display_name(v.path.as_os_str()).chars().count())
+        .max()
+        .unwrap_or(1)
+        .min(30);
+    let bar_w = 20usize;
+    for (i, volume) in screen.volumes.iter().enumerate() {
+        let y = area.y + 2 + i as u16;
+        if y >= area.y + area.height {
+            break;
+        }
+        let row = Rect {
+            x: area.x,
+            y,
+            width: area.width,
+            height: 1,
+        };
+        let filled = ((volume.used as f64 / volume.total as f64) * bar_w as f64).round() as usize;
+        let bar: String = (0..bar_w).map(|i| if i < filled.min(bar_w) { '█' } else { '░' }).collect();
+        let pct = (volume.used as f64 / volume.total as f64 * 100.0).round() as u64;
+        let path = truncate_middle(&scan::display_name(volume.path.as_os_str()), path_w);
+        let mut spans = vec![
+            Span::raw(format!(" {:<width$}  ", path, width = path_w)),
+            Span::styled(bar, Style::default().fg(app.theme.ui(Ui::Good))),
+            Span::raw(format!(
+                " {:>3}%  {:>9} / {:<9} {:>9} free  {} {}",
+                pct.min(100),
+                format_size(volume.used),
+                format_size(volume.total),
+                format_size(volume.total.saturating_sub(volume.used)),
+                volume.fstype,
+                volume.device
+            )),
+        ];
+        if volume.removable {
+            spans.push(Span::styled("  removable", Style::default().fg(app.theme.ui(Ui::Accent))));
+        }
+        let style = if i == screen.selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        f.render_widget(Paragraph::new(Line::from(spans)).style(style), row);
+        screen.rows.push(row);
     }
+}
 
-    if app.items.is_empty() {
-        let msg = if let Some(err) = &app.last_error {
-            format!("Error: {}", err)
-        } else {
-            "Empty directory".to_string()
+// One row per item, like ncdu: size, a bar relative to the largest item, the
+// share of the total, the number of entries inside and the name. Rows are
+// click targets just like treemap blocks.
+fn render_list(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    let rows = area.height as usize;
+    let len = app.items.len();
+    if let Some(selected) = app.selected {
+        if selected < app.list_top {
+            app.list_top = selected;
+        } else if selected >= app.list_top + rows {
+            app.list_top = selected + 1 - rows;
+        }
+    }
+    app.list_top = app.list_top.min(len.saturating_sub(rows));
+    // Narrow terminals drop the bar first, then the counts.
+    let bar_w = if area.width >= 60 { 20 } else if area.width >= 45 { 10 } else { 0 };
+    let show_count = area.width >= 35;
+    let largest = app.items.iter().map(|i| i.size).max().unwrap_or(0);
+    let size_w = app.looks.iter().map(|l| l.size_text.chars().count()).max().unwrap_or(0).max(10);
+    for (row, index) in (app.list_top..len).take(rows).enumerate() {
+        let item = &app.items[index];
+        let look = &app.looks[index];
+        let rect = Rect {
+            x: area.x,
+            y: area.y + row as u16,
+            width: area.width,
+            height: 1,
         };
-        let p = Paragraph::new(msg).style(Style::default().fg(Color::Yellow));
-        f.render_widget(Clear, area);
-        f.render_widget(p, area);
-        return;
+        let selected = app.selected == Some(index);
+        let marked = app.is_marked(&item.path) && item.kind.is_entry();
+        let fg = if app.dimmed(item) { app.theme.ui(Ui::Muted) } else { app.theme.foreground() };
+        let mut style = Style::default().fg(fg);
+        if marked {
+            style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        }
+        if selected {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        let prefix = match (selected, marked) {
+            (true, true) => ">*",
+            (true, false) => "> ",
+            (false, true) => " *",
+            (false, false) => "  ",
+        };
+        let mut spans = vec![Span::raw(format!("{}{:>width$} ", prefix, look.size_text, width = size_w))];
+        if bar_w > 0 {
+            let filled = match largest {
+                0 => 0,
+                _ => ((item.size as f64 / largest as f64) * bar_w as f64).round() as usize,
+            };
+            let bar: String = (0..bar_w).map(|i| if i < filled { '█' } else { '░' }).collect();
+            spans.push(Span::styled(bar, Style::default().fg(look.style.bg.unwrap_or(app.theme.ui(Ui::Good)))));
+        }
+        let pct = match app.total {
+            0 => 0.0,
+            total => item.size as f64 / total as f64 * 100.0,
+        };
+        spans.push(Span::raw(format!(" {:>5.1}% ", pct)));
+        if show_count {
+            let count = match item.kind {
+                ItemKind::File | ItemKind::FreeSpace => String::new(),
+                _ => item.count.to_string(),
+            };
+            spans.push(Span::styled(format!("{:>8}  ", count), Style::default().fg(app.theme.ui(Ui::Muted))));
+        }
+        let suffix = if item.kind == ItemKind::Dir { "/" } else { "" };
+        let mount = if look.mount { MOUNT_PREFIX } else { "" };
+        spans.push(Span::raw(format!("{}{}{}", mount, item.name, suffix)));
+        f.render_widget(Paragraph::new(Line::from(spans)).style(style), rect);
+        app.click_map.push(ClickTarget { rect, fine: None, index });
     }
+}
 
-    let sizes = &app.layout_sizes;
-    let has_zero = app.layout_has_zero;
+// The blocks, and the items folded into the "Other" block among them. The
+// area is in units `scale` of which make a cell.
+fn compute_blocks(app: &App, area: Rect, scale: (u16, u16)) -> (Vec<BlockRect>, Vec<usize>) {
+    // Layout inputs are derived from the items rather than stored next to them.
+    // Mounts are not sized, so they get a small fixed share to stay visible.
+    let mount_share = (app.total / 50).max(1);
+    let mut sizes: Vec<(usize, u64)> = app
+        .items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| (i, if item.kind == ItemKind::Mount { mount_share } else { item.size }))
+        .collect();
+    let has_zero = app.items.iter().any(|i| i.size == 0 && i.kind == ItemKind::Dir);
+    let folded = if app.view_mode == ViewMode::Files || has_zero { Vec::new() } else { small_items(app, area, scale) };
+    if !folded.is_empty() {
+        sizes.retain(|(i, _)| folded.binary_search(i).is_err());
+        sizes.push((OTHER_BLOCK, folded.iter().map(|&i| app.items[i].size).sum()));
+    }
+    let sizes = &sizes;
 
     let mut blocks = Vec::new();
+    let mut dropped = 0;
     if app.view_mode == ViewMode::Files {
         blocks = grid_layout(sizes, area);
     } else {
         if has_zero {
             blocks = grid_layout(sizes, area);
         } else {
-        if let Some((files_idx, files_size, files_count)) = app
-            .items
+        // The files of the folder, as one block or the largest of them, get
+        // a strip along the bottom.
+        let strip: Vec<(usize, u64)> = sizes
             .iter()
-            .enumerate()
-            .find(|(_, item)| item.kind == ItemKind::FilesAggregate)
-            .map(|(i, item)| (i, item.size, item.count))
-        {
-            if area.height >= 2 && files_count > 0 {
+            .cloned()
+            .filter(|&(i, _)| app.items.get(i).is_some_and(|item| in_files_strip(app, item)))
+            .collect();
+        if !strip.is_empty() {
+            let files_size: u64 = strip.iter().map(|(_, s)| s).sum();
+            let has_files = strip.iter().any(|&(i, _)| app.items[i].kind == ItemKind::File || app.items[i].count > 0);
+            if area.height >= 2 && has_files {
                 let mut files_h = if app.total == 0 {
                     1
                 } else {
                     ((area.height as f64) * (files_size as f64 / app.total as f64)).round() as u16
                 };
-                if files_h == 0 {
-                    files_h = 1;
-                }
+                // At least half a row.
+                files_h = files_h.max((scale.1 / 2).max(1));
                 let top_sizes: Vec<(usize, u64)> =
-                    sizes.iter().cloned().filter(|(i, _)| *i != files_idx).collect();
+                    sizes.iter().cloned().filter(|(i, _)| !strip.iter().any(|(s, _)| s == i)).collect();
                 if !top_sizes.is_empty() && files_h >= area.height {
                     files_h = area.height.saturating_sub(1);
                 }
@@ -478,76 +4376,433 @@ fn render_treemap(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
                     width: area.width,
                     height: files_h,
                 };
-                blocks.push(BlockRect {
-                    index: files_idx,
-                    rect: files_rect,
-                });
+                let mut strip = strip;
+                strip.sort_by_key(|(_, s)| std::cmp::Reverse(*s));
+                // Files too small for a cell of the strip are left out.
+                let files = treemap(&strip, files_rect);
+                dropped = strip.len() - files.len();
+                blocks.extend(files);
             } else {
                 blocks = treemap(sizes, area);
             }
         } else {
             blocks = treemap(sizes, area);
         }
-        if blocks.len() < sizes.len() {
+        if blocks.len() + dropped < sizes.len() {
             blocks = grid_layout(sizes, area);
         }
         }
     }
-    for block in blocks {
-        if block.rect.width < 1 || block.rect.height < 1 {
-            continue;
+    (blocks, folded)
+}
+
+// Whether the item is drawn in the files strip of the folders view: the
+// "(Files: N)" block, or the files it was expanded into.
+fn in_files_strip(app: &App, item: &Item) -> bool {
+    match item.kind {
+        ItemKind::FilesAggregate => true,
+        ItemKind::File | ItemKind::Others => app.view_mode == ViewMode::Dirs,
+        _ => false,
+    }
+}
+
+// Items that would get less than min_block_area cells, if there are enough of
+// them to be worth folding together. Ascending, like the items.
+fn small_items(app: &App, area: Rect, scale: (u16, u16)) -> Vec<usize> {
+    // In half cells once the layout is finer than cells.
+    let half_cell = (scale.0 as u32 * scale.1 as u32).div_ceil(2).max(1);
+    let min = app.config.min_block_area as f64 * half_cell as f64;
+    if min == 0.0 || app.total == 0 {
+        return Vec::new();
+    }
+    let cells_per_byte = area.width as f64 * area.height as f64 / app.total as f64;
+    let small: Vec<usize> = app
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !matches!(item.kind, ItemKind::Mount | ItemKind::FreeSpace) && !in_files_strip(app, item))
+        .filter(|(_, item)| (item.size as f64 * cells_per_byte) < min)
+        .map(|(i, _)| i)
+        .collect();
+    if small.len() < 2 {
+        return Vec::new();
+    }
+    small
+}
+
+// Color, label and size of the "Other" block.
+fn other_look(app: &App, folded: &[usize]) -> (Color, String, u64) {
+    let items = folded.iter().filter_map(|&i| app.items.get(i));
+    let size = items.clone().map(|i| i.size).sum();
+    let mtime = items.map(|i| i.mtime).max().unwrap_or(0);
+    let color = block_color(&app.theme, app.age_now(), 0, ItemKind::Others, mtime);
+    (color, format!("Other ({} items)", folded.len()), size)
+}
+
+fn draw_other(f: &mut ratatui::Frame, app: &App, rect: Rect) {
+    let (_, name, size) = other_look(app, &app.folded);
+    let style = other_style(app);
+    let buf = f.buffer_mut();
+    buf.set_style(rect, style);
+    if let Some(label) = label_for_rect(&name, &app.size_labels(&format_size(size), size), rect) {
+        buf.set_stringn(rect.x, rect.y, label, rect.width as usize, style);
+    }
+}
+
+fn other_style(app: &App) -> Style {
+    let (mut color, _, _) = other_look(app, &app.folded);
+    if app.folded.iter().filter_map(|&i| app.items.get(i)).all(|item| app.dimmed(item)) {
+        color = palette::dimmed(&app.theme, color);
+    }
+    let style = Style::default().bg(color).fg(palette::text_color(color));
+    if app.selected.is_some_and(|s| app.folded.binary_search(&s).is_ok()) {
+        style.add_modifier(Modifier::REVERSED)
+    } else {
+        style
+    }
+}
+
+// An area of cells in units `scale` of which make a cell.
+fn scaled(area: Rect, scale: (u16, u16)) -> Rect {
+    Rect {
+        x: area.x.saturating_mul(scale.0),
+        y: area.y.saturating_mul(scale.1),
+        width: area.width.saturating_mul(scale.0),
+        height: area.height.saturating_mul(scale.1),
+    }
+}
+
+// The cells a block laid out finer than cells has any part of.
+fn cells_touched(fine: Rect, scale: (u16, u16)) -> Rect {
+    let (left, top) = (fine.x / scale.0, fine.y / scale.1);
+    let (right, bottom) = (fine.right().div_ceil(scale.0), fine.bottom().div_ceil(scale.1));
+    Rect { x: left, y: top, width: right - left, height: bottom - top }
+}
+
+// The cells a block laid out finer than cells has to itself.
+fn cells_owned(fine: Rect, scale: (u16, u16)) -> Rect {
+    let (left, top) = (fine.x.div_ceil(scale.0), fine.y.div_ceil(scale.1));
+    let (right, bottom) = ((fine.right() / scale.0).max(left), (fine.bottom() / scale.1).max(top));
+    Rect { x: left, y: top, width: right - left, height: bottom - top }
+}
+
+fn covers(rect: Rect, x: u32, y: u32) -> bool {
+    (rect.left() as u32..rect.right() as u32).contains(&x) && (rect.top() as u32..rect.bottom() as u32).contains(&y)
+}
+
+// The color a block fills its cells with. A selected block is filled with
+// its text color, as REVERSED does.
+fn block_fill(app: &App, block: usize) -> Color {
+    let Some(block) = app.blocks.get(block) else {
+        return Color::Reset;
+    };
+    let style = if block.index == OTHER_BLOCK { other_style(app) } else { block_style(app, block.index) };
+    let color = if style.add_modifier.contains(Modifier::REVERSED) { style.fg } else { style.bg };
+    color.unwrap_or(Color::Reset)
+}
+
+// When laid out finer than cells, the cells that no block has to itself.
+// Whichever blocks have the middle of the top and of the bottom half of the
+// cell decide it: an upper half block in the color of the one above, on the
+// color of the one below, or just the color of one that has both.
+fn draw_shared_cells(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let (sx, sy) = (app.scale.0 as u32, app.scale.1 as u32);
+    let width = area.width as usize;
+    let mut owner: Vec<[Option<usize>; 2]> = vec![[None, None]; width * area.height as usize];
+    for (i, fine) in app.fine.iter().enumerate() {
+        let cells = cells_touched(*fine, app.scale).intersection(area);
+        for y in cells.top()..cells.bottom() {
+            let halves = [y as u32 * sy + sy / 4, y as u32 * sy + sy * 3 / 4];
+            for x in cells.left()..cells.right() {
+                let cell = &mut owner[(y - area.y) as usize * width + (x - area.x) as usize];
+                for (half, py) in halves.into_iter().enumerate() {
+                    if covers(*fine, x as u32 * sx + sx / 2, py) {
+                        cell[half] = Some(i);
+                    }
+                }
+            }
+        }
+    }
+    let owned: Vec<Rect> = (0..app.fine.len()).map(|i| app.drawn_rect(i)).collect();
+    let fill = |block: Option<usize>| block.map_or(Color::Reset, |i| block_fill(app, i));
+    let buf = f.buffer_mut();
+    for y in 0..area.height {
+        for x in 0..area.width {
+            let [top, bottom] = owner[y as usize * width + x as usize];
+            let (x, y) = (area.x + x, area.y + y);
+            let cell = if top != bottom {
+                buf.get_mut(x, y).set_symbol("▀").set_fg(fill(top)).set_bg(fill(bottom))
+            } else {
+                match top {
+                    Some(i) if !covers(owned[i], x as u32, y as u32) => {
+                        buf.get_mut(x, y).set_symbol(" ").set_bg(fill(top))
+                    }
+                    _ => continue,
+                }
+            };
+            cell.modifier = Modifier::empty();
+        }
+    }
+}
+
+// Whether the treemap can be shown as a picture: laid out in pixels and with
+// nothing over it. A refresh in the background leaves it up, since the
+// treemap stays as it was until the refresh is done.
+fn picture_wanted(app: &App) -> bool {
+    app.graphics.is_some()
+        && app.scale.0 > 1
+        && !app.fine.is_empty()
+        && app.blocks_area == Some(app.treemap_area)
+        && app.volume_screen.is_none()
+        && app.compare.is_none()
+        && !app.shows_list()
+        && !app.debug.visible
+        && app.error_list.is_none()
+        && app.find.is_none()
+        && app.bookmark_list.is_none()
+        && app.help.is_none()
+        && app.confirm.is_none()
+        && (!app.scan_state.scanning || app.scan_state.stale)
+        && !app.items.is_empty()
+}
+
+// The treemap as a picture at the scale it was laid out at: the blocks with a
+// pixel of background between them, lit from above where the terminal takes
+// any number of colors, and the level of the tree inside directory blocks.
+fn treemap_picture(app: &App, shaded: bool) -> Result<png::Canvas, String> {
+    let (sx, sy) = (app.scale.0 as u32, app.scale.1 as u32);
+    let area = app.treemap_area;
+    let (left, top) = (area.x as u32 * sx, area.y as u32 * sy);
+    let background = match app.theme.background {
+        palette::Background::Dark => [0, 0, 0],
+        palette::Background::Light => [0xff, 0xff, 0xff],
+    };
+    let mut canvas = png::Canvas::new(area.width as u32 * sx, area.height as u32 * sy, background)?;
+    let mut paint = |x: u32, y: u32, w: u32, h: u32, color: Color| {
+        let rgb = export::color_rgb(color);
+        let (w, h) = (w.saturating_sub(1).max(1), h.saturating_sub(1).max(1));
+        if !shaded {
+            canvas.fill_rect(x, y, w, h, rgb);
+            return;
+        }
+        for row in 0..h {
+            let light = 1.1 - 0.2 * row as f64 / h as f64;
+            canvas.fill_rect(x, y + row, w, 1, rgb.map(|c| (c as f64 * light).round().min(255.0) as u8));
+        }
+    };
+    for (i, fine) in app.fine.iter().enumerate() {
+        let fine = fine.intersection(scaled(area, app.scale));
+        let (x, y) = ((fine.x as u32).saturating_sub(left), (fine.y as u32).saturating_sub(top));
+        paint(x, y, fine.width as u32, fine.height as u32, block_fill(app, i));
+    }
+    for inner in &app.inner {
+        let rect = inner.rect.intersection(area);
+        let (x, y) = ((rect.x - area.x) as u32 * sx, (rect.y - area.y) as u32 * sy);
+        paint(x, y, rect.width as u32 * sx, rect.height as u32 * sy, inner.style.bg.unwrap_or(Color::Reset));
+    }
+    Ok(canvas)
+}
+
+// What goes on top of a picture that covers the text: the text in the
+// treemap, written again cell by cell.
+fn picture_text(buffer: &ratatui::buffer::Buffer, area: Rect) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            let cell = buffer.get(x, y);
+            // Half blocks only stand in for the picture.
+            if matches!(cell.symbol(), " " | "" | "▀") {
+                continue;
+            }
+            queue!(out, MoveTo(x, y), SetColors(Colors::new(cell.fg.into(), cell.bg.into())))?;
+            for (modifier, attribute) in [
+                (Modifier::BOLD, Attribute::Bold),
+                (Modifier::DIM, Attribute::Dim),
+                (Modifier::ITALIC, Attribute::Italic),
+                (Modifier::UNDERLINED, Attribute::Underlined),
+                (Modifier::REVERSED, Attribute::Reverse),
+            ] {
+                if cell.modifier.contains(modifier) {
+                    queue!(out, SetAttribute(attribute))?;
+                }
+            }
+            queue!(out, Print(cell.symbol()))?;
+            if !cell.modifier.is_empty() {
+                queue!(out, SetAttribute(Attribute::Reset))?;
+            }
         }
-        draw_block(f, app, &block);
-        app.click_map.push(ClickTarget {
-            rect: block.rect,
-            index: block.index,
-        });
     }
+    queue!(out, ResetColor)?;
+    Ok(out)
+}
+
+// Shows the picture of the treemap over the cells when it changed, or takes
+// it away once something covers the treemap.
+fn show_picture(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+    wanted: Option<(u64, Vec<u8>)>,
+) -> io::Result<()> {
+    let Some(protocol) = &app.graphics else {
+        return Ok(());
+    };
+    let area = app.treemap_area;
+    let out = match wanted {
+        Some((digest, _)) if app.image == Some(digest) => return Ok(()),
+        Some((digest, text)) => {
+            // Not tried again until the treemap changes.
+            app.image = Some(digest);
+            let picture = match treemap_picture(app, protocol.gradients()) {
+                Ok(picture) => picture,
+                Err(err) => {
+                    log::warning!("graphics", "{}", err);
+                    return Ok(());
+                }
+            };
+            let mut out = Vec::new();
+            queue!(out, MoveTo(area.x, area.y))?;
+            out.extend(protocol.show(&picture, area.width, area.height));
+            out.extend(text);
+            out
+        }
+        None if app.image.is_some() => {
+            app.image = None;
+            let hide = protocol.hide();
+            // What Sixel drew stays until the cells under it are written again.
+            if hide.is_empty() {
+                terminal.clear()?;
+                terminal.draw(|f| ui(f, app))?;
+                return Ok(());
+            }
+            hide
+        }
+        None => return Ok(()),
+    };
+    let backend = terminal.backend_mut();
+    backend.write_all(&out)?;
+    backend.flush()
+}
 
-    if app.scan_state.scanning {
-        let spinner = match app.spinner {
-            0 => "|",
-            1 => "/",
-            2 => "-",
-            _ => "\\",
+// One level of the tree inside every directory block big enough to show it,
+// so what fills a directory is visible without opening it. Clicks still go
+// to the directory itself.
+fn nested_blocks(app: &App) -> Vec<InnerBlock> {
+    let Some(tree) = &app.tree else {
+        return Vec::new();
+    };
+    let size = |node: &Node| if app.allocated { node.allocated } else { node.size };
+    let mut inner = Vec::new();
+    for (i, block) in app.blocks.iter().enumerate() {
+        let rect = app.drawn_rect(i);
+        let Some(item) = app.items.get(block.index) else {
+            continue;
         };
-        let msg = format!("Scanning {}  items={} errors={}", spinner, app.scan_state.scanned, app.scan_state.errors);
-        let overlay = Paragraph::new(msg)
-            .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
-        let overlay_area = centered_rect(40, 3, area);
-        f.render_widget(Clear, overlay_area);
-        f.render_widget(overlay, overlay_area);
+        if item.kind != ItemKind::Dir || rect.width < NESTED_MIN_WIDTH || rect.height < NESTED_MIN_HEIGHT {
+            continue;
+        }
+        let Some(node) = tree.find(&item.path) else {
+            continue;
+        };
+        let mut sizes: Vec<(usize, u64)> = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| (i, size(child)))
+            .filter(|(_, s)| *s > 0)
+            .collect();
+        sizes.sort_by_key(|(_, s)| std::cmp::Reverse(*s));
+        // The rest keeps its share of the area but is left undrawn.
+        if sizes.len() > NESTED_MAX {
+            let rest = sizes.split_off(NESTED_MAX);
+            sizes.push((usize::MAX, rest.iter().map(|(_, s)| s).sum()));
+        }
+        let area = Rect {
+            x: rect.x + 1,
+            y: rect.y + 1,
+            width: rect.width - 2,
+            height: rect.height - 2,
+        };
+        for child_block in treemap(&sizes, area) {
+            let Some(child) = node.children.get(child_block.index) else {
+                continue;
+            };
+            if child_block.rect.width == 0 || child_block.rect.height == 0 {
+                continue;
+            }
+            let kind = if child.is_dir { ItemKind::Dir } else { ItemKind::File };
+            let index = block.index + child_block.index + 1;
+            let color = block_color(&app.theme, app.age_now(), index, kind, child.mtime);
+            inner.push(InnerBlock {
+                rect: child_block.rect,
+                label: label_for_rect(
+                    &scan::display_name(&child.name),
+                    &app.size_labels(&format_size(size(child)), size(child)),
+                    child_block.rect,
+                ),
+                style: Style::default().bg(color).fg(palette::text_color(color)),
+            });
+        }
     }
+    inner
+}
 
-    if let Some(confirm) = &app.confirm {
-        let msg = format!(
-            "Delete {} {}?\n\n[y]es / [n]o",
-            if confirm.is_dir { "directory" } else { "file" },
-            confirm.target_name
-        );
-        let overlay = Paragraph::new(msg)
-            .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
-            .block(Block::default().style(Style::default().bg(Color::Black)));
-        let overlay_area = centered_rect(60, 5, area);
-        f.render_widget(Clear, overlay_area);
-        f.render_widget(overlay, overlay_area);
+// The look of an item's block: dimmed by the search, marked, selected.
+fn block_style(app: &App, index: usize) -> Style {
+    let item = &app.items[index];
+    let look = &app.looks[index];
+    let base_style = match look.style.bg {
+        Some(bg) if app.dimmed(item) => {
+            let bg = palette::dimmed(&app.theme, bg);
+            Style::default().bg(bg).fg(palette::text_color(bg))
+        }
+        _ => look.style,
+    };
+    let base_style = if app.is_marked(&item.path) && item.kind.is_entry() {
+        base_style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+    } else {
+        base_style
+    };
+    if app.selected == Some(index) {
+        base_style.add_modifier(Modifier::REVERSED)
+    } else {
+        base_style
     }
 }
 
 fn draw_block(f: &mut ratatui::Frame, app: &App, block: &BlockRect) {
     let item = &app.items[block.index];
-    let color = color_for_item(block.index, item.kind);
-    let fg = text_color(color);
-    let base_style = Style::default().bg(color).fg(fg);
-
-    let size_text = format_size(item.size);
-    let label = label_for_rect(item.name.as_str(), &size_text, block.rect);
-    if let Some(label) = label {
-        let p = Paragraph::new(label).style(base_style).block(Block::default().style(base_style));
-        f.render_widget(p, block.rect);
-    } else {
-        let b = Block::default().style(base_style);
-        f.render_widget(b, block.rect);
+    let look = &app.looks[block.index];
+    let base_style = block_style(app, block.index);
+    let marked = app.is_marked(&item.path) && item.kind.is_entry();
+    let selected = app.selected == Some(block.index);
+    let name = match (selected, marked) {
+        (true, true) => Cow::Owned(format!("> * {}", item.name)),
+        (true, false) => Cow::Owned(format!("> {}", item.name)),
+        (false, true) => Cow::Owned(format!("* {}", item.name)),
+        (false, false) => Cow::Borrowed(&*item.name),
+    };
+    let name = if look.mount { Cow::Owned(format!("{}{}", MOUNT_PREFIX, name)) } else { name };
+    // Writing straight into the buffer skips building a widget per block.
+    let buf = f.buffer_mut();
+    buf.set_style(block.rect, base_style);
+    // A mount point is framed, with its label in the top edge; nested
+    // blocks already keep a cell clear all around.
+    let mut label_rect = block.rect;
+    if look.mount && block.rect.width >= 4 && block.rect.height >= 3 {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .border_style(base_style)
+            .render(block.rect, buf);
+        label_rect = Rect { x: block.rect.x + 1, width: block.rect.width - 2, ..block.rect };
+    }
+    // A mount has no size to take a share of.
+    let sizes = match item.kind {
+        ItemKind::Mount => vec![look.size_text.clone()],
+        _ => app.size_labels(&look.size_text, item.size),
+    };
+    if let Some(label) = label_for_rect(&name, &sizes, label_rect) {
+        buf.set_stringn(label_rect.x, label_rect.y, label, label_rect.width as usize, base_style);
     }
 }
 
@@ -555,17 +4810,12 @@ fn render_bottom(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
     let device_label = app.fs_device.as_deref().unwrap_or("-");
     let version_label = VERSION_LABEL;
     let desired_bar = 20usize;
-    let min_bar = 10usize;
     let device_w = device_label.len();
     let version_w = version_label.len();
     let total_w = area.width as usize;
 
     let info_width = if total_w >= device_w + desired_bar + version_w {
         device_w + desired_bar + version_w
-    } else if total_w >= device_w + min_bar + version_w {
-        total_w
-    } else if total_w >= version_w + min_bar {
-        total_w
     } else {
         total_w
     };
@@ -580,54 +4830,221 @@ fn render_bottom(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
     };
     let text_area = chunks[0];
 
-    let up_enabled = app.current_path.parent().is_some();
-    let up_label = "[Up]";
-    let view_label = match app.view_mode {
-        ViewMode::Dirs => "[Dirs]",
-        ViewMode::Files => "[Files]",
+    let up_enabled = app.current_path.parent().is_some() || !app.imported;
+    // Clickable labels are pointless without a mouse, so the keys take their place.
+    let up_label = if app.config.mouse { "[Up]" } else { "" };
+    let mut view_label = match (app.view_mode, app.allocated) {
+        (ViewMode::Dirs, false) => "[Dirs",
+        (ViewMode::Files, false) => "[Files",
+        (ViewMode::Dirs, true) => "[Dirs, on disk",
+        (ViewMode::Files, true) => "[Files, on disk",
+        (ViewMode::Types, false) => "[Types",
+        (ViewMode::Types, true) => "[Types, on disk",
+        (ViewMode::Largest, false) => "[Largest files",
+        (ViewMode::Largest, true) => "[Largest files, on disk",
+    }
+    .to_string();
+    if app.sort != SortKey::Size {
+        view_label.push_str(", by ");
+        view_label.push_str(app.sort.name());
+    }
+    view_label.push(']');
+    if app.cached {
+        view_label.push_str(" (cached)");
+    }
+    let marked_label = if app.marked.is_empty() {
+        String::new()
+    } else {
+        let size: u64 = app.marked.iter().map(|m| m.size).sum();
+        format!("[{} marked, {}]", app.marked.len(), format_size(size))
+    };
+    // Once the scan is over; while it runs the overlay counts them.
+    let errors_label = match app.scan_errors.len() {
+        _ if app.scan_state.scanning => String::new(),
+        0 => String::new(),
+        1 => format!("[1 error, {}]", app.config.keys.first(Action::Errors)),
+        n => format!("[{} errors, {}]", n, app.config.keys.first(Action::Errors)),
+    };
+    let keys = &app.config.keys;
+    let hovered = hovered_item(app).map(|item| hover_info(app, item));
+    let search = match (&app.path_prompt, &app.compare) {
+        (Some(prompt), _) => Some((true, path_prompt_line(prompt))),
+        (None, Some(_)) => Some((
+            false,
+            "Comparing: j/k select, Enter compare inside, Backspace compare the parents, Esc close".to_string(),
+        )),
+        (None, None) => app.search.as_ref().map(|s| (s.typing, search_line(app, s))),
+    };
+    let search_style = Style::default().fg(app.theme.ui(Ui::Accent));
+    let (help, help_style) = match (&app.last_error, hovered, &app.notice, search) {
+        // While typing, the box itself.
+        (_, _, _, Some((true, line))) => (line, search_style),
+        (Some(err), _, _, _) if !app.items.is_empty() => (err.clone(), Style::default().fg(app.theme.ui(Ui::Bad))),
+        (_, Some(info), _, _) => (info, Style::default().fg(app.theme.foreground())),
+        (_, _, Some(notice), _) => (notice.clone(), Style::default().fg(app.theme.ui(Ui::Good))),
+        (_, _, _, Some((false, line))) => (line, search_style),
+        _ if app.scan_state.scanning && picture_wanted(app) => {
+            let status = scan_status(app, 0).remove(0);
+            let text = status.spans.iter().map(|s| s.content.as_ref()).collect();
+            (text, Style::default().fg(app.theme.ui(Ui::Warn)))
+        }
+        _ if app.config.mouse => (
+            format!(
+                "{} help, {} quit, click to enter, {} up, {} view",
+                keys.first(Action::Help),
+                keys.first(Action::Quit),
+                keys.first(Action::Up),
+                keys.first(Action::Files)
+            ),
+            Style::default().fg(app.theme.ui(Ui::Muted)),
+        ),
+        _ => (
+            format!(
+                "{} help, {}/{} select, {} open, {} up, {} mark, {} delete, {} view, {} quit",
+                keys.first(Action::Help),
+                keys.first(Action::Next),
+                keys.first(Action::Previous),
+                keys.first(Action::Open),
+                keys.first(Action::Up),
+                keys.first(Action::Mark),
+                keys.first(Action::Delete),
+                keys.first(Action::Files),
+                keys.first(Action::Quit)
+            ),
+            Style::default().fg(app.theme.ui(Ui::Accent)),
+        ),
     };
-    let help = "q quit, click to enter, Backspace/h up, f view";
-
-    let mut path = app.current_path.to_string_lossy().to_string();
 
-    let reserved = up_label.len() + 2 + view_label.len() + 2 + help.len() + 2;
+    let reserved =
+        up_label.len() + 2 + view_label.len() + 2 + marked_label.len() + 2 + errors_label.len() + 2 + help.len() + 2;
     let max_width = text_area.width as usize;
-    if max_width > reserved {
-        let max_path = max_width - reserved;
-        path = truncate_middle(&path, max_path);
-    } else if max_width > 3 {
-        path = truncate_middle(&path, max_width.saturating_sub(1));
-    }
+    let max_path = if max_width > reserved { max_width - reserved } else { max_width.saturating_sub(1).max(1) };
+    let pieces = breadcrumbs(&app.current_path, max_path);
 
     let mut spans = Vec::new();
-    spans.push(Span::styled(path.clone(), Style::default().fg(Color::White)));
+    let mut path_w = 0u16;
+    app.crumbs.clear();
+    let current = pieces.len() - 1;
+    for (i, (text, target)) in pieces.into_iter().enumerate() {
+        let w = text.chars().count() as u16;
+        let style = if i == current {
+            Style::default().fg(app.theme.foreground()).add_modifier(Modifier::BOLD)
+        } else if target.is_some() {
+            Style::default().fg(app.theme.foreground())
+        } else {
+            Style::default().fg(app.theme.ui(Ui::Muted))
+        };
+        if let Some(target) = target.filter(|_| app.config.mouse) {
+            if path_w + w <= text_area.width {
+                app.crumbs.push((Rect { x: text_area.x + path_w, y: text_area.y, width: w, height: 1 }, target));
+            }
+        }
+        spans.push(Span::styled(text, style));
+        path_w += w;
+    }
     spans.push(Span::raw("  "));
 
     let up_style = if up_enabled {
-        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+        Style::default().fg(app.theme.ui(Ui::Good)).add_modifier(Modifier::BOLD)
     } else {
-        Style::default().fg(Color::DarkGray)
+        Style::default().fg(app.theme.ui(Ui::Muted))
     };
 
-    spans.push(Span::styled(up_label, up_style));
-    spans.push(Span::raw("  "));
-    spans.push(Span::styled(view_label, Style::default().fg(Color::Magenta)));
+    if !up_label.is_empty() {
+        spans.push(Span::styled(up_label, up_style));
+        spans.push(Span::raw("  "));
+    }
+    spans.push(Span::styled(view_label, Style::default().fg(app.theme.ui(Ui::Highlight))));
     spans.push(Span::raw("  "));
-    spans.push(Span::styled(help, Style::default().fg(Color::DarkGray)));
+    if !marked_label.is_empty() {
+        spans.push(Span::styled(marked_label, Style::default().fg(app.theme.ui(Ui::Warn))));
+        spans.push(Span::raw("  "));
+    }
+    if !errors_label.is_empty() {
+        spans.push(Span::styled(errors_label, Style::default().fg(app.theme.ui(Ui::Bad))));
+        spans.push(Span::raw("  "));
+    }
+    spans.push(Span::styled(help, help_style));
 
     let p = Paragraph::new(Line::from(spans));
     f.render_widget(p, text_area);
 
     let up_width = up_label.len() as u16;
-    let up_x = text_area.x + path.len() as u16 + 2;
-    app.up_rect = if up_enabled && up_x + up_width <= text_area.x + text_area.width {
+    let up_x = text_area.x + path_w + 2;
+    app.up_rect = if app.config.mouse && up_enabled && up_x + up_width <= text_area.x + text_area.width {
         Some(Rect { x: up_x, y: text_area.y, width: up_width, height: 1 })
     } else {
         None
     };
 
     if info_width > 0 && chunks.len() > 1 && app.fs_total > 0 {
-        render_usage_bar(f, chunks[1], app.fs_used, app.fs_total, device_label, version_label);
+        render_usage_bar(f, chunks[1], app.fs_used, app.fs_total, device_label, version_label, &app.theme);
+    }
+}
+
+fn hovered_item(app: &App) -> Option<&Item> {
+    if app.confirm.is_some()
+        || app.volume_screen.is_some()
+        || app.help.is_some()
+        || app.error_list.is_some()
+        || app.find.is_some()
+        || app.bookmark_list.is_some()
+        || app.compare.is_some()
+    {
+        return None;
+    }
+    let (x, y) = app.hover?;
+    let target = app.click_map.iter().find(|t| app.hits(t, x, y))?;
+    app.items.get(target.index)
+}
+
+// The whole story of a block whose label got cut: name, exact size, share of
+// this folder, item count and last change.
+fn hover_info(app: &App, item: &Item) -> String {
+    if item.kind == ItemKind::Mount {
+        return format!("{}  another filesystem, not counted", item.name);
+    }
+    if item.kind == ItemKind::FreeSpace {
+        let share = if app.fs_total > 0 { item.size as f64 * 100.0 / app.fs_total as f64 } else { 0.0 };
+        return format!("{}  {} bytes  {:.1}% of the filesystem unused", item.name, item.size, share);
+    }
+    let pct = if app.total > 0 { item.size as f64 * 100.0 / app.total as f64 } else { 0.0 };
+    let suffix = if item.kind == ItemKind::Dir { "/" } else { "" };
+    let mut info = format!("{}{}  {} bytes  {:.1}%", item.name, suffix, item.size, pct);
+    if item.kind != ItemKind::File {
+        info.push_str(&format!("  {} items", item.count));
+    }
+    if item.mtime > 0 {
+        info.push_str(&format!("  modified {}", export::date_time(item.mtime as u64)));
+    }
+    if app.is_mount_point(item) {
+        info.push_str("  mount point");
+        let mount = app.volumes().and_then(|v| v.mounts.get(&*item.path));
+        if let Some((_, Some(device))) = mount {
+            info.push_str(&format!(" of {}", device));
+        }
+        if app.config.mount_usage {
+            info.push_str(", sized by its filesystem");
+        }
+    }
+    info
+}
+
+// Pseudo filesystems are left out; they are never scanned into anyway.
+fn mount_points(pseudo: &[String]) -> HashSet<PathBuf> {
+    fsinfo::mount_table()
+        .into_iter()
+        .filter(|m| !pseudo.contains(&m.fstype))
+        .map(|m| m.path)
+        .collect()
+}
+
+// A mount point has another device than the folder above it.
+fn is_mount_root(path: &Path) -> bool {
+    match (fs::metadata(path), path.parent().map(fs::metadata)) {
+        (Ok(m), Some(Ok(parent))) => m.dev() != parent.dev(),
+        (Ok(_), None) => true,
+        _ => false,
     }
 }
 
@@ -635,20 +5052,89 @@ fn contains(rect: Rect, x: u16, y: u16) -> bool {
     x >= rect.x && x < rect.x + rect.width && y >= rect.y && y < rect.y + rect.height
 }
 
+// The path as pieces for the bottom bar: every ancestor with the folder it
+// opens, then the separators and the current folder, which open nothing. When
+// it does not fit, the leading folders give way to "...".
+fn breadcrumbs(path: &Path, max: usize) -> Vec<(String, Option<PathBuf>)> {
+    let mut segments: Vec<(String, PathBuf)> = Vec::new();
+    let mut walked = PathBuf::new();
+    for component in path.components() {
+        walked.push(component);
+        let name = match component {
+            Component::RootDir => "/".to_string(),
+            other => scan::display_name(other.as_os_str()),
+        };
+        segments.push((name, walked.clone()));
+    }
+    if segments.is_empty() {
+        return vec![(scan::display_name(path.as_os_str()), None)];
+    }
+    let width = |skip: usize| -> usize {
+        let shown = &segments[skip..];
+        let names: usize = shown.iter().map(|(n, _)| n.chars().count()).sum();
+        let separators = shown.windows(2).filter(|w| w[0].0 != "/").count();
+        names + separators + if skip > 0 { 4 } else { 0 }
+    };
+    let mut skip = 0;
+    while skip + 1 < segments.len() && width(skip) > max {
+        skip += 1;
+    }
+
+    let mut pieces = Vec::new();
+    if skip > 0 {
+        pieces.push(("...".to_string(), None));
+        pieces.push(("/".to_string(), None));
+    }
+    let last = segments.len() - 1;
+    let fixed = width(skip) - segments[last].0.chars().count();
+    for (i, (name, target)) in segments.into_iter().enumerate().skip(skip) {
+        if i > skip && pieces.last().is_some_and(|(p, _): &(String, _)| p != "/") {
+            pieces.push(("/".to_string(), None));
+        }
+        if i == last {
+            pieces.push((truncate_middle(&name, max.saturating_sub(fixed)), None));
+        } else {
+            pieces.push((name, Some(target)));
+        }
+    }
+    pieces
+}
+
 fn truncate_middle(s: &str, max: usize) -> String {
-    if s.len() <= max {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() <= max {
         return s.to_string();
     }
     if max <= 3 {
         return "...".to_string();
     }
     let keep = (max - 3) / 2;
-    let start = &s[..keep];
-    let end = &s[s.len() - keep..];
+    let start: String = chars[..keep].iter().collect();
+    let end: String = chars[chars.len() - keep..].iter().collect();
     format!("{}...{}", start, end)
 }
 
-fn label_for_rect(name: &str, size: &str, rect: Rect) -> Option<String> {
+// "42%", with a decimal below 10% and "<0.1%" for crumbs.
+fn share_text(part: u64, whole: u64) -> String {
+    let pct = match whole {
+        0 => 0.0,
+        whole => part as f64 / whole as f64 * 100.0,
+    };
+    if pct >= 10.0 {
+        format!("{:.0}%", pct)
+    } else if pct >= 0.1 || part == 0 {
+        format!("{:.1}%", pct)
+    } else {
+        "<0.1%".to_string()
+    }
+}
+
+// The name with the first of `sizes` that leaves room for it.
+fn label_for_rect(name: &str, sizes: &[String], rect: Rect) -> Option<String> {
+    sizes.iter().find_map(|size| fit_label(name, size, rect))
+}
+
+fn fit_label(name: &str, size: &str, rect: Rect) -> Option<String> {
     if rect.height < 1 || rect.width < 4 {
         return None;
     }
@@ -685,79 +5171,81 @@ fn label_for_rect(name: &str, size: &str, rect: Rect) -> Option<String> {
     Some(format!("{} {}", name_out, size))
 }
 
-fn color_for_item(idx: usize, kind: ItemKind) -> Color {
-    const DIR_COLORS: [Color; 8] = [
-        Color::Blue,
-        Color::Cyan,
-        Color::Green,
-        Color::Yellow,
-        Color::Magenta,
-        Color::LightBlue,
-        Color::LightGreen,
-        Color::LightYellow,
-    ];
-    const FILE_COLORS: [Color; 4] = [
-        Color::DarkGray,
-        Color::Gray,
-        Color::LightBlue,
-        Color::LightMagenta,
+// Largest, fullest or newest first, names alphabetically. Ties fall back to
+// size and then name so the layout is stable. The folded rest of a long file
+// list stays last, however big it is.
+fn sort_items(items: &mut [Item], sort: SortKey, natural: bool) {
+    let by_name = |a: &Item, b: &Item| {
+        if natural {
+            collate::natural_cmp(&a.name, &b.name)
+        } else {
+            a.path.as_os_str().cmp(b.path.as_os_str())
+        }
+    };
+    items.sort_by(|a, b| {
+        let first = match sort {
+            SortKey::Size => std::cmp::Ordering::Equal,
+            SortKey::Name => by_name(a, b),
+            SortKey::Count => b.count.cmp(&a.count),
+            SortKey::Modified => b.mtime.cmp(&a.mtime),
+        };
+        let rest = |i: &Item| i.kind == ItemKind::Others;
+        rest(a)
+            .cmp(&rest(b))
+            .then(first)
+            .then_with(|| b.size.cmp(&a.size))
+            .then_with(|| by_name(a, b))
+    });
+}
+
+// Deletes or trashes one confirmed target with its hooks, audit record and
+// log line. Runs on the deleting thread; the app forgets what it had cached
+// about the target once the batch is done.
+fn delete_target(fs: &dyn FileSystem, config: &Config, target: &Target, trash: bool) -> (bool, Result<(), String>) {
+    let verb = if trash { "trash" } else { "delete" };
+    let mut fields = vec![
+        ("path", Field::Text(target.path.to_string_lossy().into_owned())),
+        ("action", Field::Text(verb.to_string())),
+        ("kind", Field::Text(if target.is_dir { "dir" } else { "file" }.to_string())),
+        ("bytes", Field::Number(target.size)),
     ];
-    match kind {
-        ItemKind::Dir => DIR_COLORS[idx % DIR_COLORS.len()],
-        ItemKind::File => FILE_COLORS[idx % FILE_COLORS.len()],
-        ItemKind::FilesAggregate => Color::LightMagenta,
-    }
-}
-
-fn text_color(bg: Color) -> Color {
-    match bg {
-        Color::Yellow
-        | Color::LightYellow
-        | Color::LightGreen
-        | Color::LightBlue
-        | Color::Cyan => Color::Black,
-        _ => Color::White,
-    }
-}
-
-fn format_size(bytes: u64) -> String {
-    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
-    let mut size = bytes as f64;
-    let mut unit = 0usize;
-    while size >= 1024.0 && unit + 1 < UNITS.len() {
-        size /= 1024.0;
-        unit += 1;
-    }
-    if unit == 0 {
-        format!("{} {}", bytes, UNITS[unit])
-    } else if size >= 100.0 {
-        format!("{:.0} {}", size, UNITS[unit])
-    } else if size >= 10.0 {
-        format!("{:.1} {}", size, UNITS[unit])
-    } else {
-        format!("{:.2} {}", size, UNITS[unit])
+    let outcome = hook::run(&config.hooks, HookEvent::PreDelete, &fields).and_then(|()| {
+        let outcome = if trash { trash::send(&target.path) } else { perform_delete(fs, target) };
+        fields.push(("error", Field::Text(outcome.clone().err().unwrap_or_default())));
+        hook::spawn(&config.hooks, HookEvent::PostDelete, fields);
+        outcome
+    });
+    let logged = audit::record(config, verb, &target.path, target.size, &outcome);
+    match &outcome {
+        Ok(()) => log::info!("action", "{} {}", verb, target.path.display()),
+        Err(e) => log::warning!("action", "{} {} failed: {}", verb, target.path.display(), e),
     }
+    (outcome.is_ok(), outcome.and(logged))
 }
 
-fn fs_usage(path: &Path) -> Option<(u64, u64)> {
-    let c = CString::new(path.as_os_str().as_bytes()).ok()?;
-    let mut vfs: libc::statvfs = unsafe { std::mem::zeroed() };
-    let rc = unsafe { libc::statvfs(c.as_ptr(), &mut vfs) };
-    if rc != 0 {
-        return None;
+fn perform_delete(fs: &dyn FileSystem, target: &Target) -> Result<(), String> {
+    fs.remove(&target.path).map_err(|e| format!("Delete failed: {}", e))
+}
+
+// The path prompt in the bottom bar, with the folders Tab goes through.
+fn path_prompt_line(prompt: &PathPrompt) -> String {
+    let (label, enter) = if prompt.compare { ("Compare with", "compare") } else { ("Go to", "go") };
+    if prompt.choices.is_empty() {
+        format!("{}: {}█  Tab complete, Enter {}, Esc cancel", label, prompt.text, enter)
+    } else {
+        format!("{}: {}█  Tab: {}", label, prompt.text, prompt.choices.join("  "))
     }
-    let frsize = vfs.f_frsize as u64;
-    let total = (vfs.f_blocks as u64).saturating_mul(frsize);
-    let avail = (vfs.f_bavail as u64).saturating_mul(frsize);
-    let used = total.saturating_sub(avail);
-    Some((used, total))
 }
 
-fn perform_delete(action: &ConfirmAction) -> Result<(), String> {
-    if action.is_dir {
-        fs::remove_dir_all(&action.target_path).map_err(|e| format!("Delete failed: {}", e))
+// The search box in the bottom bar, with how many items match.
+fn search_line(app: &App, search: &Search) -> String {
+    let matching = app.items.iter().filter(|i| search.matches(&i.name)).count();
+    if search.typing {
+        let hide = if search.hide { "show others" } else { "hide others" };
+        format!("/{}█  {} matching; Enter select, Tab {}, Esc clear", search.query, matching, hide)
     } else {
-        fs::remove_file(&action.target_path).map_err(|e| format!("Delete failed: {}", e))
+        let key = app.config.keys.first(Action::Search);
+        format!("/{}  {} matching; {} edit, Esc clear", search.query, matching, key)
     }
 }
 
@@ -768,6 +5256,7 @@ fn render_usage_bar(
     total: u64,
     device_label: &str,
     version_label: &str,
+    theme: &Theme,
 ) {
     if area.width < 4 || total == 0 {
         return;
@@ -820,7 +5309,7 @@ fn render_usage_bar(
         if label.len() > device_w {
             label = truncate_middle(&label, device_w);
         }
-        let p = Paragraph::new(label).style(Style::default().fg(Color::White));
+        let p = Paragraph::new(label).style(Style::default().fg(theme.foreground()));
         f.render_widget(p, device_rect);
     }
 
@@ -846,71 +5335,24 @@ fn render_usage_bar(
     }
     let final_bar: String = chars.into_iter().collect();
 
+    let (fill, track) = palette::usage_bar_colors(theme);
     let p = Paragraph::new(final_bar)
-        .style(Style::default().fg(Color::Black).bg(Color::LightGreen))
-        .block(Block::default().style(Style::default().bg(Color::DarkGray)));
+        .style(Style::default().fg(palette::text_color(fill)).bg(fill))
+        .block(Block::default().style(Style::default().bg(track)));
     f.render_widget(p, bar_rect);
 
     if device_w > 0 {
         let version_rect = parts[idx];
         let p = Paragraph::new(version_label)
-            .style(Style::default().fg(Color::DarkGray))
+            .style(Style::default().fg(theme.ui(Ui::Muted)))
             .alignment(ratatui::layout::Alignment::Right);
         f.render_widget(p, version_rect);
     }
 }
 
-fn current_device(path: &Path) -> Option<String> {
-    let canon = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
-    let mut best: Option<(usize, String)> = None;
-    for line in mounts.lines() {
-        let mut parts = line.split_whitespace();
-        let dev = parts.next()?;
-        let mnt = parts.next()?;
-        let dev = unescape_mount_field(dev);
-        let mnt = unescape_mount_field(mnt);
-        let mnt_path = Path::new(&mnt);
-        if !canon.starts_with(mnt_path) {
-            continue;
-        }
-        let mnt_len = mnt_path.as_os_str().len();
-        if let Some((best_len, _)) = &best {
-            if mnt_len <= *best_len {
-                continue;
-            }
-        }
-        best = Some((mnt_len, dev));
-    }
-    best.map(|(_, dev)| dev)
-}
-
-fn unescape_mount_field(s: &str) -> String {
-    let mut out = String::new();
-    let mut chars = s.chars().peekable();
-    while let Some(ch) = chars.next() {
-        if ch == '\\' {
-            let a = chars.next();
-            let b = chars.next();
-            let c = chars.next();
-            match (a, b, c) {
-                (Some('0'), Some('4'), Some('0')) => out.push(' '),
-                (Some('0'), Some('1'), Some('1')) => out.push('\t'),
-                (Some('0'), Some('1'), Some('2')) => out.push('\n'),
-                (Some('1'), Some('3'), Some('4')) => out.push('\\'),
-                (Some(x), Some(y), Some(z)) => {
-                    out.push('\\');
-                    out.push(x);
-                    out.push(y);
-                    out.push(z);
-                }
-                _ => out.push('\\'),
-            }
-        } else {
-            out.push(ch);
-        }
-    }
-    out
+fn overlay_style(theme: &Theme) -> Style {
+    let (fg, bg) = theme.overlay();
+    Style::default().fg(fg).bg(bg)
 }
 
 fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
@@ -919,3 +5361,110 @@ fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
     let y = area.y + (area.height.saturating_sub(height)) / 2;
     Rect { x, y, width, height }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::Memory;
+
+    // The paths a --jsonl report of /data on `fs` lists with `flags`.
+    fn reported(fs: &Memory, flags: &[&str]) -> String {
+        let out = env::temp_dir().join(format!("duviz-report-{}-{}.jsonl", std::process::id(), flags.len()));
+        let args = ["--jsonl", "/data", "--out", out.to_str().unwrap()];
+        let options = cli::parse(args.iter().chain(flags).map(std::ffi::OsString::from)).unwrap();
+        run_report(fs, ReportFormat::JsonLines, &options, &Config::default()).unwrap();
+        let text = fs::read_to_string(&out).unwrap();
+        let _ = fs::remove_file(&out);
+        text
+    }
+
+    fn named(names: &[&str], kind: ItemKind) -> Vec<Item> {
+        let item = |name: &&str| Item {
+            name: Arc::from(*name),
+            path: Arc::from(Path::new("/d").join(name)),
+            size: 1,
+            allocated: 1,
+            kind,
+            count: 1,
+            partial: false,
+            mtime: 0,
+        };
+        names.iter().map(item).collect()
+    }
+
+    fn sorted_names(names: &[&str], natural: bool) -> Vec<String> {
+        let mut items = named(names, ItemKind::File);
+        sort_items(&mut items, SortKey::Name, natural);
+        items.iter().map(|i| i.name.to_string()).collect()
+    }
+
+    #[test]
+    fn natural_order_compares_digit_runs_by_value() {
+        let names = ["file10", "file2", "File1", "file01", "file001", "b", "A", "a", "file"];
+        let natural = ["A", "a", "b", "file", "File1", "file001", "file01", "file2", "file10"];
+        assert_eq!(sorted_names(&names, true), natural);
+        let raw = ["A", "File1", "a", "b", "file", "file001", "file01", "file10", "file2"];
+        assert_eq!(sorted_names(&names, false), raw);
+    }
+
+    #[test]
+    fn natural_order_handles_mixed_and_long_digit_runs() {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+        assert_eq!(collate::natural_cmp("v1.9.10", "v1.10.2"), Less);
+        assert_eq!(collate::natural_cmp("img2b", "img2a10"), Greater);
+        assert_eq!(collate::natural_cmp("a1b2", "a1b2"), Equal);
+        assert_eq!(collate::natural_cmp("x", "x0"), Less);
+        // Runs longer than any integer type still compare by value.
+        let long = format!("n{}", "9".repeat(40));
+        let longer = format!("n1{}", "0".repeat(40));
+        assert_eq!(collate::natural_cmp(&long, &longer), Less);
+        assert_eq!(collate::natural_cmp(&format!("n{}1", "0".repeat(60)), "n2"), Less);
+        // Only equal names are equal; leading zeros and case are tie-breaks.
+        assert_eq!(collate::natural_cmp("007", "7"), Less);
+        assert_eq!(collate::natural_cmp("Ab", "ab"), Less);
+    }
+
+    #[test]
+    fn folded_rest_stays_last_by_name() {
+        let mut items = named(&["zz", "aa"], ItemKind::File);
+        items.extend(named(&["0 more"], ItemKind::Others));
+        sort_items(&mut items, SortKey::Name, true);
+        assert_eq!(items.iter().map(|i| &*i.name).collect::<Vec<_>>(), ["aa", "zz", "0 more"]);
+    }
+
+    #[test]
+    fn reports_cross_mounts_unless_told_not_to() {
+        let fs = Memory::new();
+        fs.dir("/data").file("/data/top", 42).mount("/data/mnt", 2).file("/data/mnt/disk", 1_000);
+        assert!(reported(&fs, &[]).contains("\"/data/mnt/disk\""));
+        let one_fs = reported(&fs, &["-x"]);
+        assert!(one_fs.contains("\"/data/top\"") && !one_fs.contains("/data/mnt"));
+    }
+
+    #[test]
+    fn tree_counts_toward_the_cache_limit() {
+        let scan = |name: &str| CachedScan {
+            items: Arc::from(named(&[name], ItemKind::Dir)),
+            errors: Arc::from(Vec::new()),
+            stamp: 0,
+            bytes: 0,
+            used: 0,
+            from_disk: false,
+        };
+        let key = |name: &str| CacheKey { path: PathBuf::from(name), view: ViewMode::Dirs };
+        let one = estimate_bytes(&named(&["a"], ItemKind::Dir), &[]);
+        let mut cache = ScanCache::new(3 * one);
+        for name in ["a", "b", "c"] {
+            cache.insert(key(name), scan(name));
+        }
+        assert_eq!(cache.entries.len(), 3);
+        // Room for one scan is left beside the tree; the most recently used stays.
+        cache.get(&key("a"));
+        cache.set_tree_bytes(2 * one);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.contains(&key("a")));
+        cache.set_tree_bytes(0);
+        cache.insert(key("b"), scan("b"));
+        assert_eq!(cache.entries.len(), 2);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::json::{self, Value};
+use crate::scan::Node;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::PathBuf;
+#[cfg(test)]
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ncdu's export format (`ncdu -o`, version 1.2): a directory is an array whose
+// first element describes it and whose other elements are its entries, a
+// file is an object. Sizes are the entry's own, not totals.
+pub fn to_json(root: &Node) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut out = format!(
+        "[1,2,{{\"progname\":\"duviz\",\"progver\":\"{}\",\"timestamp\":{}}},\n",
+        env!("CARGO_PKG_VERSION"),
+        time
+    );
+    let name = root.path().to_string_lossy();
+    write_node(root, &name, &mut out);
+    out.push_str("]\n");
+    out
+}
+
+fn write_node(node: &Node, name: &str, out: &mut String) {
+    let name = json::escape(name);
+    if !node.is_dir {
+        let _ = write!(out, "{{\"name\":{},\"asize\":{},\"dsize\":{}", name, node.size, node.allocated);
+        write_mtime(node, out);
+        out.push('}');
+        return;
+    }
+    // Trees keep totals, so a directory's own size is what its entries leave.
+    let asize = node.size.saturating_sub(node.children.iter().map(|c| c.size).sum());
+    let dsize = node.allocated.saturating_sub(node.children.iter().map(|c| c.allocated).sum());
+    let _ = write!(out, "[{{\"name\":{},\"asize\":{},\"dsize\":{}", name, asize, dsize);
+    write_mtime(node, out);
+    out.push('}');
+    for child in &node.children {
+        out.push_str(",\n");
+        let child_name = child.name.to_string_lossy();
+        write_node(child, &child_name, out);
+    }
+    out.push(']');
+}
+
+// Extended-mode field; a directory's is the newest in it, not its own.
+fn write_mtime(node: &Node, out: &mut String) {
+    if node.mtime > 0 {
+        let _ = write!(out, ",\"mtime\":{}", node.mtime);
+    }
+}
+
+pub fn read(text: &str) -> Result<Node, String> {
+    let doc = json::parse(text)?;
+    let parts = doc.as_array().ok_or("not an ncdu export")?;
+    if parts.first().and_then(Value::as_u64) != Some(1) {
+        return Err("unsupported ncdu export version".to_string());
+    }
+    let root = parts.get(3).ok_or("ncdu export without a tree")?;
+    let info = root.as_array().and_then(|a| a.first()).ok_or("ncdu export without a root directory")?;
+    let name = info.get("name").and_then(Value::as_str).ok_or("root directory without a name")?;
+    let mut reader = Reader {
+        seen: HashSet::new(),
+    };
+    reader.dir(root, name.into(), PathBuf::from(name), 0)
+}
+
+struct Reader {
+    // Hard links are listed under every name but count once, as in ncdu.
+    seen: HashSet<(u64, u64)>,
+}
+
+impl Reader {
+    // `name` is the path for the root and the entry's own name below it.
+    fn dir(&mut self, value: &Value, name: OsString, path: PathBuf, parent_dev: u64) -> Result<Node, String> {
+        let entries = value.as_array().unwrap_or(&[]);
+        let info = entries
+            .first()
+            .ok_or_else(|| format!("{}: empty directory entry", path.display()))?;
+        let dev = info.get("dev").and_then(Value::as_u64).unwrap_or(parent_dev);
+        let mut node = Node {
+            name,
+            size: info.get("asize").and_then(Value::as_u64).unwrap_or(0),
+            allocated: info.get("dsize").and_then(Value::as_u64).unwrap_or(0),
+            count: 0,
+            is_dir: true,
+            mount: false,
+            mtime: info.get("mtime").and_then(Value::as_u64).unwrap_or(0) as i64,
+            children: Vec::new(),
+        };
+        for entry in &entries[1..] {
+            let info = match entry.as_array() {
+                Some(dir) => dir.first(),
+                None => Some(entry),
+            };
+            let Some(child_name) = info.and_then(|i| i.get("name")).and_then(Value::as_str) else {
+                return Err(format!("{}: entry without a name", path.display()));
+            };
+            let child = if entry.as_array().is_some() {
+                self.dir(entry, child_name.into(), path.join(child_name), dev)?
+            } else {
+                // Excluded entries and other filesystems were never sized.
+                if entry.get("excluded").is_some() {
+                    continue;
+                }
+                let hard_link = entry.get("hlnkc").and_then(Value::as_bool).unwrap_or(false)
+                    || entry.get("nlink").and_then(Value::as_u64).unwrap_or(1) > 1;
+                let ino = entry.get("ino").and_then(Value::as_u64);
+                if let (true, Some(ino)) = (hard_link, ino) {
+                    if !self.seen.insert((dev, ino)) {
+                        continue;
+                    }
+                }
+                let size = entry.get("asize").and_then(Value::as_u64).unwrap_or(0);
+                Node {
+                    name: child_name.into(),
+                    size,
+                    allocated: entry.get("dsize").and_then(Value::as_u64).unwrap_or(size),
+                    count: 1,
+                    is_dir: false,
+                    mount: false,
+                    mtime: entry.get("mtime").and_then(Value::as_u64).unwrap_or(0) as i64,
+                    children: Vec::new(),
+                }
+            };
+            node.size = node.size.saturating_add(child.size);
+            node.allocated = node.allocated.saturating_add(child.allocated);
+            node.count += child.count;
+            node.mtime = node.mtime.max(child.mtime);
+            node.children.push(child);
+        }
+        node.children.sort_by_key(|c| std::cmp::Reverse(c.size));
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStringExt;
+
+    fn node(name: OsString, size: u64, children: Vec<Node>) -> Node {
+        let is_dir = size == 0;
+        let size = size + children.iter().map(|c| c.size).sum::<u64>();
+        let count = if is_dir { children.iter().map(|c| c.count).sum() } else { 1 };
+        Node { name, size, allocated: size, count, is_dir, mount: false, mtime: 7, children }
+    }
+
+    #[test]
+    fn names_with_control_characters_and_bad_bytes_read_back() {
+        let odd = OsString::from_vec(b"caf\xe9".to_vec());
+        let root = node(
+            "/data".into(),
+            0,
+            vec![
+                node("tab\there \"quoted\"".into(), 30, vec![]),
+                node("sub".into(), 0, vec![node("esc\u{1b}[1m\n".into(), 20, vec![]), node(odd, 10, vec![])]),
+            ],
+        );
+        let text = to_json(&root);
+        assert!(text.contains(r#"{"name":"tab\there \"quoted\"","asize":30,"dsize":30,"mtime":7}"#));
+        assert!(text.contains(r#""esc\u001b[1m\n""#));
+        let back = read(&text).unwrap();
+        assert_eq!((back.path(), back.size, back.count), (Path::new("/data"), 60, 3));
+        let sub = back.children.iter().find(|c| c.name == "sub").unwrap();
+        let names: Vec<_> = sub.children.iter().map(|c| (c.name.to_string_lossy().into_owned(), c.size)).collect();
+        // Export names are UTF-8, so a byte that is not becomes U+FFFD.
+        assert_eq!(names, [("esc\u{1b}[1m\n".to_string(), 20), ("caf\u{fffd}".to_string(), 10)]);
+        assert_eq!(back.children[0].name, "tab\there \"quoted\"");
+    }
+
+    #[test]
+    fn hard_links_and_excluded_entries_count_once() {
+        let text = r#"[1,2,{"progname":"ncdu"},
+[{"name":"/r","asize":4096},
+{"name":"a","asize":100,"ino":5,"hlnkc":true},
+{"name":"b","asize":100,"ino":5,"hlnkc":true},
+{"name":"skip","excluded":"pattern"},
+[{"name":"d","asize":4096},{"name":"c","asize":1,"dsize":4096}]]]"#;
+        let root = read(text).unwrap();
+        assert_eq!((root.size, root.allocated, root.count), (4096 + 100 + 4096 + 1, 100 + 4096, 2));
+        assert!(read("[2,0,{}]").is_err());
+    }
+}
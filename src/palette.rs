@@ -0,0 +1,638 @@
+use crate::scan::ItemKind;
+use ratatui::style::Color;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    Ansi8,
+}
+
+impl ColorDepth {
+    pub fn parse(value: &str) -> Option<Option<ColorDepth>> {
+        match value {
+            "auto" => Some(None),
+            "truecolor" | "24bit" => Some(Some(ColorDepth::TrueColor)),
+            "256" => Some(Some(ColorDepth::Ansi256)),
+            "16" => Some(Some(ColorDepth::Ansi16)),
+            "8" => Some(Some(ColorDepth::Ansi8)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn parse(value: &str) -> Option<Option<Background>> {
+        match value {
+            "auto" => Some(None),
+            "dark" => Some(Some(Background::Dark)),
+            "light" => Some(Some(Background::Light)),
+            _ => None,
+        }
+    }
+}
+
+// Built-in sets of colors, for the treemap and the rest of the screen alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    Default,
+    // Blue against orange and yellow, never red against green.
+    Deuteranopia,
+    HighContrast,
+    Monochrome,
+}
+
+impl Palette {
+    const ALL: [Palette; 4] = [Palette::Default, Palette::Deuteranopia, Palette::HighContrast, Palette::Monochrome];
+
+    pub fn parse(value: &str) -> Option<Palette> {
+        Palette::ALL.into_iter().find(|p| p.name() == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "default",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::HighContrast => "high-contrast",
+            Palette::Monochrome => "monochrome",
+        }
+    }
+
+    pub fn next(self) -> Palette {
+        let i = Palette::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        Palette::ALL[(i + 1) % Palette::ALL.len()]
+    }
+}
+
+// What a piece of text outside the treemap is there for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ui {
+    // Headings and side notes.
+    Accent,
+    // The current view and other state worth a second look.
+    Highlight,
+    // Key names in the help.
+    Key,
+    // Notices and the bars in the list and filesystem screens.
+    Good,
+    // Scan progress, titles and the trash confirmation.
+    Warn,
+    // Errors and the permanent delete confirmation.
+    Bad,
+    // Counts, hints and empty bar tracks.
+    Muted,
+}
+
+// The colors of a base16 or base24 scheme, base00 first.
+#[derive(Debug, Clone, Copy)]
+pub struct Scheme {
+    base: [[u8; 3]; 24],
+    len: usize,
+}
+
+// Scheme files are YAML, but all that matters are the `baseXX: "rrggbb"`
+// lines, which look the same in the old flat format and in the newer one
+// that nests them under `palette:`.
+pub fn load_scheme(path: &Path) -> Result<Scheme, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut base = [[0u8; 3]; 24];
+    let mut found = [false; 24];
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let Some(index) = key.trim().strip_prefix("base").and_then(|n| u8::from_str_radix(n, 16).ok()) else {
+            continue;
+        };
+        let value = value.split('#').find(|v| !v.trim().trim_matches(['"', '\'']).is_empty()).unwrap_or("");
+        let hex = value.trim().trim_matches(['"', '\'']);
+        let rgb = match u32::from_str_radix(hex, 16) {
+            Ok(v) if hex.len() == 6 => [(v >> 16) as u8, (v >> 8) as u8, v as u8],
+            _ => return Err(format!("{}: base{:02X} is not a color: {}", path.display(), index, value.trim())),
+        };
+        if let Some(slot) = base.get_mut(index as usize) {
+            *slot = rgb;
+            found[index as usize] = true;
+        }
+    }
+    let len = match (found[..16].iter().all(|&f| f), found[16..].iter().all(|&f| f)) {
+        (true, true) => 24,
+        (true, false) => 16,
+        _ => return Err(format!("{}: not a base16 or base24 scheme", path.display())),
+    };
+    Ok(Scheme { base, len })
+}
+
+impl Scheme {
+    pub fn background(&self) -> Background {
+        if luma(self.base[0]) > 128.0 {
+            Background::Light
+        } else {
+            Background::Dark
+        }
+    }
+
+    // Directories and file types take the accent colors (base08-base0F, plus
+    // base12-base17 in base24), files the muted tones and aggregates the purple.
+    fn rgb(&self, idx: usize, kind: ItemKind) -> [u8; 3] {
+        const ACCENTS: [usize; 14] = [0x0D, 0x0C, 0x0B, 0x0A, 0x0E, 0x09, 0x08, 0x0F, 0x16, 0x15, 0x14, 0x13, 0x17, 0x12];
+        let accents = if self.len == 24 { &ACCENTS[..] } else { &ACCENTS[..8] };
+        match kind {
+            ItemKind::Dir | ItemKind::Extension => self.base[accents[idx % accents.len()]],
+            ItemKind::File => self.base[[0x02, 0x03, 0x04][idx % 3]],
+            ItemKind::FilesAggregate | ItemKind::Others => self.base[0x0E],
+            ItemKind::Mount => self.base[0x01],
+            ItemKind::FreeSpace => self.base[0x00],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub depth: ColorDepth,
+    pub background: Background,
+    pub scheme: Option<Scheme>,
+    pub palette: Palette,
+}
+
+impl Theme {
+    // Plain text on the terminal's own background.
+    pub fn foreground(&self) -> Color {
+        match self.background {
+            Background::Dark => Color::White,
+            Background::Light => Color::Black,
+        }
+    }
+
+    // Named colors throughout, so they follow the terminal's own theme.
+    pub fn ui(&self, role: Ui) -> Color {
+        let pick = |on_dark, on_light| match self.background {
+            Background::Dark => on_dark,
+            Background::Light => on_light,
+        };
+        match (self.palette, role) {
+            (Palette::Monochrome, Ui::Muted) => Color::DarkGray,
+            (Palette::Monochrome, _) => self.foreground(),
+            (Palette::HighContrast, Ui::Muted) => pick(Color::Gray, Color::DarkGray),
+            (Palette::HighContrast, Ui::Accent) => pick(Color::LightCyan, Color::Blue),
+            (Palette::HighContrast, Ui::Highlight) => pick(Color::LightMagenta, Color::Magenta),
+            (Palette::HighContrast, Ui::Key | Ui::Good) => pick(Color::LightGreen, Color::Green),
+            (Palette::HighContrast, Ui::Warn) => pick(Color::LightYellow, Color::Magenta),
+            (Palette::HighContrast, Ui::Bad) => pick(Color::LightRed, Color::Red),
+            (Palette::Deuteranopia, Ui::Key | Ui::Good) => pick(Color::LightBlue, Color::Blue),
+            (_, Ui::Accent) => Color::Cyan,
+            (_, Ui::Highlight) => Color::Magenta,
+            (_, Ui::Key | Ui::Good) => Color::Green,
+            (_, Ui::Warn) => Color::Yellow,
+            (_, Ui::Bad) => Color::Red,
+            (_, Ui::Muted) => Color::DarkGray,
+        }
+    }
+
+    // Text and background of the help, debug and confirmation boxes.
+    pub fn overlay(&self) -> (Color, Color) {
+        match (self.palette, self.background) {
+            (Palette::HighContrast | Palette::Monochrome, Background::Light) => (Color::Black, Color::White),
+            _ => (Color::White, Color::Black),
+        }
+    }
+}
+
+// Asks the terminal for its background color (OSC 11), followed by a device
+// attributes request that every terminal answers, so one that ignores OSC 11
+// costs no more than a round trip. Needs raw mode. Terminals that don't
+// answer at all are assumed dark, unless COLORFGBG says otherwise.
+pub fn detect_background() -> Background {
+    let term = env::var("TERM").unwrap_or_default();
+    if term.is_empty() || term == "linux" || term == "dumb" {
+        return Background::Dark;
+    }
+    let reply = query_terminal("\x1b]11;?\x1b\\\x1b[c", Duration::from_millis(300));
+    if let Some(rgb) = reply.as_deref().and_then(parse_osc_color) {
+        return if luma(rgb) > 128.0 { Background::Light } else { Background::Dark };
+    }
+    // COLORFGBG is "fg;bg" with ANSI color numbers; 7 and 9-15 are light.
+    match env::var("COLORFGBG").ok().and_then(|v| v.rsplit(';').next()?.parse::<u8>().ok()) {
+        Some(7) | Some(9..=15) => Background::Light,
+        _ => Background::Dark,
+    }
+}
+
+// Reads the replies up to the device attributes answer (ESC [ ? ... c).
+pub fn query_terminal(query: &str, timeout: Duration) -> Option<String> {
+    let mut stdout = io::stdout();
+    stdout.write_all(query.as_bytes()).ok()?;
+    stdout.flush().ok()?;
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut fds = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if left.is_zero() || unsafe { libc::poll(&mut fds, 1, left.as_millis() as i32) } <= 0 {
+            break;
+        }
+        let mut buf = [0u8; 64];
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+        let text = String::from_utf8_lossy(&reply);
+        if text.rfind("\x1b[?").is_some_and(|i| text[i..].contains('c')) {
+            break;
+        }
+    }
+    Some(String::from_utf8_lossy(&reply).into_owned())
+}
+
+// "ESC ] 11 ; rgb:RRRR/GGGG/BBBB", with one to four hex digits per channel.
+fn parse_osc_color(reply: &str) -> Option<[u8; 3]> {
+    let start = reply.find("]11;rgb:")? + "]11;rgb:".len();
+    let end = reply[start..].find(['\x1b', '\x07']).map_or(reply.len(), |i| start + i);
+    let mut channels = reply[start..end].split('/').map(|c| {
+        let value = u32::from_str_radix(c, 16).ok()?;
+        let max = (1u32 << (4 * c.len().clamp(1, 4))) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    Some([channels.next()??, channels.next()??, channels.next()??])
+}
+
+// COLORTERM is the only reliable truecolor signal; otherwise go by TERM and
+// fall back to asking terminfo through tput.
+pub fn detect() -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::TrueColor;
+    }
+    let term = env::var("TERM").unwrap_or_default();
+    if term.contains("256color") {
+        return ColorDepth::Ansi256;
+    }
+    // The Linux console and its relatives only have 8 background colors.
+    if term.is_empty() || term == "linux" || term == "dumb" || term.starts_with("vt") {
+        return ColorDepth::Ansi8;
+    }
+    let colors = Command::new("tput")
+        .arg("colors")
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8_lossy(&out.stdout).trim().parse::<u32>().ok());
+    match colors {
+        Some(n) if n >= 256 => ColorDepth::Ansi256,
+        Some(n) if n < 16 => ColorDepth::Ansi8,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+// A palette's colors by rank: folders and file types cycle through `dirs`,
+// files through `files`.
+struct Swatches<T: 'static> {
+    dirs: &'static [T],
+    files: &'static [T],
+    aggregate: T,
+    mount: T,
+    free: T,
+}
+
+impl<T: Copy> Swatches<T> {
+    fn pick(&self, idx: usize, kind: ItemKind) -> T {
+        match kind {
+            ItemKind::Dir | ItemKind::Extension => self.dirs[idx % self.dirs.len()],
+            ItemKind::File => self.files[idx % self.files.len()],
+            ItemKind::FilesAggregate | ItemKind::Others => self.aggregate,
+            ItemKind::Mount => self.mount,
+            ItemKind::FreeSpace => self.free,
+        }
+    }
+}
+
+// On 16 and 8 colors the named colors already follow the terminal's theme,
+// so a scheme only replaces the gradients, and only those of the default
+// palette.
+pub fn color_for_item(theme: &Theme, idx: usize, kind: ItemKind) -> Color {
+    let rgb = || match (&theme.scheme, theme.palette) {
+        (Some(scheme), Palette::Default) => scheme.rgb(idx, kind),
+        (None, Palette::Default) => gradient_rgb(idx, kind, theme.background),
+        (_, palette) => lift(rgb_swatches(palette).pick(idx, kind), theme.background),
+    };
+    match theme.depth {
+        ColorDepth::TrueColor => {
+            let [r, g, b] = rgb();
+            Color::Rgb(r, g, b)
+        }
+        ColorDepth::Ansi256 => Color::Indexed(cube_index(rgb())),
+        ColorDepth::Ansi16 => named_swatches(theme.palette).pick(idx, kind),
+        ColorDepth::Ansi8 => console_swatches(theme.palette).pick(idx, kind),
+    }
+}
+
+// Fixed colors for the palettes other than the default, which has gradients.
+fn rgb_swatches(palette: Palette) -> Swatches<[u8; 3]> {
+    match palette {
+        // Okabe and Ito's set, which stays apart under every common kind of
+        // color blindness.
+        Palette::Default | Palette::Deuteranopia => Swatches {
+            dirs: &[
+                [0x00, 0x72, 0xb2],
+                [0xe6, 0x9f, 0x00],
+                [0x56, 0xb4, 0xe9],
+                [0xd5, 0x5e, 0x00],
+                [0xf0, 0xe4, 0x42],
+                [0x00, 0x9e, 0x73],
+            ],
+            files: &[[0x4a, 0x55, 0x63], [0x6b, 0x75, 0x80], [0x8c, 0x93, 0x9b]],
+            aggregate: [0xcc, 0x79, 0xa7],
+            mount: [0x33, 0x33, 0x33],
+            free: [0x1a, 0x1a, 0x1a],
+        },
+        // Full saturation, dark and light in turn so neighbours never blur.
+        Palette::HighContrast => Swatches {
+            dirs: &[
+                [0x00, 0x40, 0xff],
+                [0xff, 0xe0, 0x00],
+                [0xc0, 0x00, 0x00],
+                [0x00, 0xff, 0xff],
+                [0x80, 0x00, 0xc0],
+                [0x00, 0xff, 0x40],
+                [0x00, 0x60, 0x00],
+                [0xff, 0x80, 0x00],
+            ],
+            files: &[[0x30, 0x30, 0x30], [0xd0, 0xd0, 0xd0]],
+            aggregate: [0xff, 0x00, 0xff],
+            mount: [0x00, 0x00, 0x00],
+            free: [0x18, 0x18, 0x18],
+        },
+        Palette::Monochrome => Swatches {
+            dirs: &[[0x70, 0x70, 0x70], [0xb0, 0xb0, 0xb0], [0x50, 0x50, 0x50], [0x90, 0x90, 0x90]],
+            files: &[[0x38, 0x38, 0x38], [0x48, 0x48, 0x48], [0x60, 0x60, 0x60]],
+            aggregate: [0xd0, 0xd0, 0xd0],
+            mount: [0x20, 0x20, 0x20],
+            free: [0x10, 0x10, 0x10],
+        },
+    }
+}
+
+fn named_swatches(palette: Palette) -> Swatches<Color> {
+    match palette {
+        Palette::Default => Swatches {
+            dirs: &[
+                Color::Blue,
+                Color::Cyan,
+                Color::Green,
+                Color::Yellow,
+                Color::Magenta,
+                Color::LightBlue,
+                Color::LightGreen,
+                Color::LightYellow,
+            ],
+            files: &[Color::DarkGray, Color::Gray, Color::LightBlue, Color::LightMagenta],
+            aggregate: Color::LightMagenta,
+            mount: Color::DarkGray,
+            free: Color::Black,
+        },
+        Palette::Deuteranopia => Swatches {
+            dirs: &[Color::Blue, Color::Yellow, Color::LightBlue, Color::Magenta, Color::LightYellow, Color::Cyan],
+            files: &[Color::DarkGray, Color::Gray],
+            aggregate: Color::LightMagenta,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+        Palette::HighContrast => Swatches {
+            dirs: &[Color::Blue, Color::LightYellow, Color::Red, Color::LightCyan, Color::Magenta, Color::LightGreen],
+            files: &[Color::Black, Color::White],
+            aggregate: Color::LightMagenta,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+        Palette::Monochrome => Swatches {
+            dirs: &[Color::Gray, Color::DarkGray, Color::White],
+            files: &[Color::DarkGray, Color::Gray],
+            aggregate: Color::White,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+    }
+}
+
+// Only colors that the Linux console can show as backgrounds.
+fn console_swatches(palette: Palette) -> Swatches<Color> {
+    match palette {
+        Palette::Default | Palette::HighContrast => Swatches {
+            dirs: &[Color::Blue, Color::Cyan, Color::Green, Color::Yellow, Color::Magenta, Color::Red],
+            files: &[Color::Gray, Color::Cyan],
+            aggregate: Color::Magenta,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+        Palette::Deuteranopia => Swatches {
+            dirs: &[Color::Blue, Color::Yellow, Color::Cyan, Color::Magenta],
+            files: &[Color::Gray, Color::Cyan],
+            aggregate: Color::Magenta,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+        // Blocks keep apart by their borders alone.
+        Palette::Monochrome => Swatches {
+            dirs: &[Color::Gray],
+            files: &[Color::Gray],
+            aggregate: Color::Gray,
+            mount: Color::Black,
+            free: Color::Black,
+        },
+    }
+}
+
+// Green for things touched in the last week, through yellow to red for ones
+// untouched for two years or more, on a log scale so months stay apart.
+// The other palettes go from blue to orange, from green to red at full
+// strength, or from light to dark gray. Unknown ages (imported listings) are
+// gray.
+pub fn age_color(theme: &Theme, age: Option<i64>) -> Color {
+    const WEEK: f64 = 7.0 * 86400.0;
+    const TWO_YEARS: f64 = 730.0 * 86400.0;
+    let Some(age) = age else {
+        return match theme.depth {
+            ColorDepth::Ansi8 => Color::Blue,
+            ColorDepth::Ansi16 => Color::DarkGray,
+            _ => Color::Indexed(244),
+        };
+    };
+    let t = ((age as f64).max(WEEK) / WEEK).ln() / (TWO_YEARS / WEEK).ln();
+    let t = t.clamp(0.0, 1.0);
+    let rgb = match theme.palette {
+        Palette::Default => {
+            let lift = match theme.background {
+                Background::Dark => 0.0,
+                Background::Light => 0.15,
+            };
+            hsl_to_rgb(120.0 * (1.0 - t), 0.6, 0.4 + lift)
+        }
+        Palette::Deuteranopia => lift(mix([0x00, 0x72, 0xb2], [0xe6, 0x9f, 0x00], t), theme.background),
+        Palette::HighContrast => hsl_to_rgb(120.0 * (1.0 - t), 1.0, 0.45),
+        Palette::Monochrome => lift(mix([0xd0, 0xd0, 0xd0], [0x30, 0x30, 0x30], t), theme.background),
+    };
+    let steps = match (theme.palette, theme.depth) {
+        (Palette::Deuteranopia, _) => [Color::Blue, Color::Cyan, Color::Yellow],
+        (Palette::Monochrome, ColorDepth::Ansi8) => [Color::Gray, Color::Gray, Color::Black],
+        (Palette::Monochrome, _) => [Color::White, Color::Gray, Color::DarkGray],
+        _ => [Color::Green, Color::Yellow, Color::Red],
+    };
+    match theme.depth {
+        ColorDepth::TrueColor => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+        ColorDepth::Ansi256 => Color::Indexed(cube_index(rgb)),
+        ColorDepth::Ansi16 | ColorDepth::Ansi8 => steps[((t * 3.0) as usize).min(2)],
+    }
+}
+
+// Bright backgrounds show up as plain or blinking cells on 8-color consoles.
+pub fn usage_bar_colors(theme: &Theme) -> (Color, Color) {
+    match (theme.depth, theme.background, theme.palette) {
+        (ColorDepth::Ansi8, _, Palette::Deuteranopia) => (Color::Blue, Color::Black),
+        (ColorDepth::Ansi8, _, Palette::Monochrome) => (Color::Gray, Color::Black),
+        (ColorDepth::Ansi8, _, _) => (Color::Green, Color::Black),
+        (_, Background::Light, Palette::Deuteranopia) => (Color::Blue, Color::Gray),
+        (_, Background::Light, Palette::HighContrast) => (Color::Black, Color::Gray),
+        (_, Background::Light, Palette::Monochrome) => (Color::DarkGray, Color::Gray),
+        (_, Background::Light, Palette::Default) => (Color::Green, Color::Gray),
+        (_, Background::Dark, Palette::Deuteranopia) => (Color::LightBlue, Color::DarkGray),
+        (_, Background::Dark, Palette::HighContrast) => (Color::White, Color::DarkGray),
+        (_, Background::Dark, Palette::Monochrome) => (Color::Gray, Color::DarkGray),
+        (_, Background::Dark, Palette::Default) => (Color::LightGreen, Color::DarkGray),
+    }
+}
+
+// Items arrive largest first, so stepping the hue by rank gives neighbouring
+// blocks distinct but related colors. Files get a muted version of the same.
+// On a light background everything is lifted so blocks don't look like holes.
+fn gradient_rgb(idx: usize, kind: ItemKind, background: Background) -> [u8; 3] {
+    let hue = (210.0 + idx as f64 * 37.0) % 360.0;
+    let lift = match background {
+        Background::Dark => 0.0,
+        Background::Light => 0.2,
+    };
+    match kind {
+        ItemKind::Dir | ItemKind::Extension => hsl_to_rgb(hue, 0.55, 0.45 + lift),
+        ItemKind::File => hsl_to_rgb(hue, 0.15, 0.35 + lift + (idx % 4) as f64 * 0.08),
+        ItemKind::FilesAggregate | ItemKind::Others => hsl_to_rgb(300.0, 0.45, 0.55 + lift),
+        ItemKind::Mount => hsl_to_rgb(0.0, 0.0, 0.2 + lift),
+        ItemKind::FreeSpace => hsl_to_rgb(0.0, 0.0, 0.1 + lift),
+    }
+}
+
+// A block left out by a search: its color faded most of the way into the
+// background. Named colors cannot fade, so they turn a dull gray.
+pub fn dimmed(theme: &Theme, color: Color) -> Color {
+    let background = match theme.background {
+        Background::Dark => [0x1c, 0x1c, 0x1c],
+        Background::Light => [0xe4, 0xe4, 0xe4],
+    };
+    match color {
+        Color::Rgb(r, g, b) => {
+            let [r, g, b] = mix([r, g, b], background, 0.7);
+            Color::Rgb(r, g, b)
+        }
+        Color::Indexed(n) => Color::Indexed(cube_index(mix(indexed_rgb(n), background, 0.7))),
+        _ => theme.ui(Ui::Muted),
+    }
+}
+
+// The fixed colors' counterpart of the gradients' lift on light backgrounds.
+fn lift(rgb: [u8; 3], background: Background) -> [u8; 3] {
+    match background {
+        Background::Dark => rgb,
+        Background::Light => mix(rgb, [0xff, 0xff, 0xff], 0.25),
+    }
+}
+
+fn mix(from: [u8; 3], to: [u8; 3], t: f64) -> [u8; 3] {
+    let channel = |i: usize| (from[i] as f64 + (to[i] as f64 - from[i] as f64) * t).round() as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let to_u8 = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    [to_u8(r), to_u8(g), to_u8(b)]
+}
+
+const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+fn cube_index([r, g, b]: [u8; 3]) -> u8 {
+    let level = |v: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+pub fn indexed_rgb(n: u8) -> [u8; 3] {
+    match n {
+        16..=231 => {
+            let n = n - 16;
+            [
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            ]
+        }
+        232..=255 => {
+            let v = 8 + (n - 232) * 10;
+            [v, v, v]
+        }
+        _ => [0x80, 0x80, 0x80],
+    }
+}
+
+pub fn text_color(bg: Color) -> Color {
+    match bg {
+        Color::Yellow
+        | Color::LightYellow
+        | Color::LightGreen
+        | Color::LightBlue
+        | Color::Cyan
+        | Color::Gray => Color::Black,
+        Color::Rgb(r, g, b) => contrast([r, g, b]),
+        Color::Indexed(n) => contrast(indexed_rgb(n)),
+        _ => Color::White,
+    }
+}
+
+fn luma([r, g, b]: [u8; 3]) -> f64 {
+    0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+}
+
+fn contrast(rgb: [u8; 3]) -> Color {
+    if luma(rgb) > 140.0 {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
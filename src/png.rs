@@ -0,0 +1,232 @@
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    // Fails for an empty size, or one whose pixels would not fit in memory,
+    // rather than wrapping around to a smaller buffer.
+    pub fn new(width: u32, height: u32, rgb: [u8; 3]) -> Result<Self, String> {
+        let bad_size = || format!("cannot make a {}x{} image", width, height);
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(3))
+            .filter(|&n| n > 0)
+            .ok_or_else(bad_size)?;
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(len).map_err(|_| bad_size())?;
+        pixels.extend(rgb.iter().copied().cycle().take(len));
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn fill_rect(&mut self, x: u32, y: u32, w: u32, h: u32, rgb: [u8; 3]) {
+        let x_end = x.saturating_add(w).min(self.width) as usize;
+        let y_end = y.saturating_add(h).min(self.height) as usize;
+        let stride = self.width as usize * 3;
+        for py in y.min(self.height) as usize..y_end {
+            for px in x.min(self.width) as usize..x_end {
+                let i = py * stride + px * 3;
+                self.pixels[i..i + 3].copy_from_slice(&rgb);
+            }
+        }
+    }
+
+    pub fn rgb(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let stride = self.width as usize * 3;
+        let mut raw = Vec::with_capacity(self.pixels.len() + self.height as usize);
+        for row in self.pixels.chunks(stride) {
+            // Sub filter: solid runs turn into zero runs, which deflate well.
+            raw.push(1);
+            for (i, b) in row.iter().enumerate() {
+                let left = if i >= 3 { row[i - 3] } else { 0 };
+                raw.push(b.wrapping_sub(left));
+            }
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        write_chunk(&mut out, b"IHDR", &ihdr);
+        write_chunk(&mut out, b"IDAT", &zlib(&raw));
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut i = 0usize;
+    while i < data.len() {
+        let mut run = 0usize;
+        if i > 0 {
+            let prev = data[i - 1];
+            while run < 258 && i + run < data.len() && data[i + run] == prev {
+                run += 1;
+            }
+        }
+        if run >= 3 {
+            write_length(&mut bits, run as u16);
+            // distance 1: fixed code 0, five bits, no extra bits
+            bits.write_code(0, 5);
+            i += run;
+        } else {
+            write_symbol(&mut bits, data[i] as u16);
+            i += 1;
+        }
+    }
+    write_symbol(&mut bits, 256);
+    let mut out = vec![0x78, 0x01];
+    out.extend_from_slice(&bits.finish());
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn write_length(bits: &mut BitWriter, len: u16) {
+    let idx = LENGTH_BASE.iter().rposition(|&base| base <= len).unwrap_or(0);
+    write_symbol(bits, 257 + idx as u16);
+    let extra = LENGTH_EXTRA[idx];
+    if extra > 0 {
+        bits.write((len - LENGTH_BASE[idx]) as u32, extra);
+    }
+}
+
+fn write_symbol(bits: &mut BitWriter, sym: u16) {
+    match sym {
+        0..=143 => bits.write_code(0x30 + sym as u32, 8),
+        144..=255 => bits.write_code(0x190 + (sym - 144) as u32, 9),
+        256..=279 => bits.write_code((sym - 256) as u32, 7),
+        _ => bits.write_code(0xc0 + (sym - 280) as u32, 8),
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, n: u8) {
+        for i in 0..n {
+            self.acc |= ((value >> i) & 1) << self.count;
+            self.count += 1;
+            if self.count == 8 {
+                self.out.push(self.acc as u8);
+                self.acc = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn write_code(&mut self, code: u32, n: u8) {
+        // Huffman codes are packed starting from their most significant bit.
+        for i in (0..n).rev() {
+            self.write((code >> i) & 1, 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= 65_521;
+        b %= 65_521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // Long enough to need the modulo between chunks.
+        assert_eq!(adler32(&[0xff; 6000]), 0xa497_59ea);
+    }
+
+    #[test]
+    fn runs_become_back_references() {
+        // 7, then 258 and 41 more copies at distance 1; checked with zlib.decompress.
+        assert_eq!(zlib(&[7; 300]), [0x78, 0x01, 0x63, 0x1f, 0x05, 0x44, 0x03, 0x00, 0xd3, 0xfa, 0x08, 0x35]);
+    }
+
+    #[test]
+    fn tiny_image_encodes_to_known_bytes() {
+        let mut canvas = Canvas::new(2, 1, [255, 0, 0]).unwrap();
+        canvas.fill_rect(1, 0, 1, 1, [0, 0, 255]);
+        let png = canvas.encode();
+        let expected: &[u8] = &[
+            0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a,
+            0x00, 0x00, 0x00, 0x0d, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00,
+            0x7b, 0x40, 0xe8, 0xdd,
+            0x00, 0x00, 0x00, 0x0f, b'I', b'D', b'A', b'T',
+            0x78, 0x01, 0x63, 0xfc, 0xcf, 0xc0, 0xc0, 0xc8, 0xf0, 0x1f, 0x00, 0x07, 0x0a, 0x02, 0x01,
+            0x80, 0x6f, 0x3b, 0xc1,
+            0x00, 0x00, 0x00, 0x00, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82,
+        ];
+        assert_eq!(png, expected);
+    }
+
+    #[test]
+    fn empty_or_huge_canvas_is_refused() {
+        assert!(Canvas::new(0, 10, [0; 3]).is_err());
+        assert!(Canvas::new(u32::MAX, u32::MAX, [0; 3]).is_err());
+    }
+}
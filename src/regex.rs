@@ -0,0 +1,391 @@
+// A small regular expression matcher, enough to search names: literals, .
+// for any character, [abc], [a-z] and [^abc] sets, \d \w \s (and \D \W \S)
+// classes, ^ and $ anchors, greedy * + ? after anything, (groups) and |
+// between alternatives. A backslash makes any other character literal. A
+// match may be anywhere in the text unless anchored.
+//
+// The pattern is compiled to a small program that is run for every place in
+// the text at once (Thompson's construction), so matching takes time in
+// proportion to the length of the name times that of the pattern. Patterns
+// like (a+)+$ that make a backtracking matcher take exponential time are no
+// slower than any other.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    // Ranges of characters, and whether the set matches what is outside them.
+    Set(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Set(Vec<(char, char)>, bool),
+    Start,
+    End,
+    // Go on at both places.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    // Case-insensitive unless the pattern has a capital in it that is not
+    // part of an escape, like smartcase in vim and less.
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let capital = chars
+            .iter()
+            .enumerate()
+            .any(|(i, c)| c.is_uppercase() && (i == 0 || chars[i - 1] != '\\'));
+        let mut parser = Parser { chars, pos: 0, ignore_case: !capital };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unmatched ')' in '{}'", pattern));
+        }
+        let mut program = Vec::new();
+        compile_alternatives(&alternatives, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { program, ignore_case: !capital })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = if self.ignore_case {
+            text.chars().flat_map(char::to_lowercase).collect()
+        } else {
+            text.chars().collect()
+        };
+        // The instructions waiting for the character at `pos`, and the
+        // position each instruction was last added for, so none is added
+        // twice for the same one.
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut added = vec![usize::MAX; self.program.len()];
+        for pos in 0..=text.len() {
+            if self.add(&mut current, &mut added, 0, pos, &text) {
+                return true;
+            }
+            next.clear();
+            for &pc in &current {
+                let takes = match (&self.program[pc], text.get(pos)) {
+                    (Inst::Char(c), Some(t)) => c == t,
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Set(ranges, negated), Some(t)) => {
+                        ranges.iter().any(|(low, high)| (low..=high).contains(&t)) != *negated
+                    }
+                    _ => false,
+                };
+                if takes && self.add(&mut next, &mut added, pc + 1, pos + 1, &text) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    // Adds the instruction at `pc` and everything reachable from it without
+    // taking a character; true if that reaches the end of the pattern.
+    fn add(&self, list: &mut Vec<usize>, added: &mut [usize], pc: usize, pos: usize, text: &[char]) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if added[pc] == pos {
+                continue;
+            }
+            added[pc] = pos;
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(first, second) => stack.extend([*second, *first]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == text.len() => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => list.push(pc),
+            }
+        }
+        false
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    ignore_case: bool,
+}
+
+impl Parser {
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.chars.get(self.pos) == Some(&'|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == '|' || c == ')' {
+                break;
+            }
+            self.pos += 1;
+            let node = match c {
+                '.' => Node::Any,
+                '^' => Node::Start,
+                '$' => Node::End,
+                '(' => {
+                    let group = self.alternatives()?;
+                    if self.chars.get(self.pos) != Some(&')') {
+                        return Err("unclosed '('".to_string());
+                    }
+                    self.pos += 1;
+                    Node::Group(group)
+                }
+                '[' => self.set()?,
+                '\\' => self.escape()?,
+                '*' | '+' | '?' => return Err(format!("nothing to repeat before '{}'", c)),
+                c => Node::Char(self.fold(c)),
+            };
+            let node = match self.chars.get(self.pos) {
+                Some('*') => Node::Repeat(Box::new(node), 0, None),
+                Some('+') => Node::Repeat(Box::new(node), 1, None),
+                Some('?') => Node::Repeat(Box::new(node), 0, Some(1)),
+                _ => {
+                    nodes.push(node);
+                    continue;
+                }
+            };
+            self.pos += 1;
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let Some(&c) = self.chars.get(self.pos) else {
+            return Err("trailing '\\'".to_string());
+        };
+        self.pos += 1;
+        Ok(match class(c) {
+            Some((ranges, negated)) => Node::Set(ranges, negated),
+            None => Node::Char(self.fold(c)),
+        })
+    }
+
+    // Just after the '['.
+    fn set(&mut self) -> Result<Node, String> {
+        let negated = self.chars.get(self.pos) == Some(&'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return Err("unclosed '['".to_string());
+            };
+            self.pos += 1;
+            // A ']' right after the '[' is a member, as in POSIX.
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = match c {
+                '\\' => match self.chars.get(self.pos).copied() {
+                    Some(e) => {
+                        self.pos += 1;
+                        match class(e) {
+                            Some((more, false)) => {
+                                ranges.extend(more);
+                                continue;
+                            }
+                            _ => e,
+                        }
+                    }
+                    None => return Err("unclosed '['".to_string()),
+                },
+                c => c,
+            };
+            let ranged =
+                self.chars.get(self.pos) == Some(&'-') && self.chars.get(self.pos + 1).is_some_and(|&h| h != ']');
+            let high = if ranged {
+                self.pos += 2;
+                self.chars[self.pos - 1]
+            } else {
+                low
+            };
+            ranges.push((self.fold(low), self.fold(high)));
+        }
+        Ok(Node::Set(ranges, negated))
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    }
+}
+
+// The character classes behind \d, \w and \s, and their negations.
+fn class(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => return None,
+    };
+    Some((ranges, c.is_uppercase()))
+}
+
+// Each alternative but the last is tried next to the ones after it; all of
+// them go on after the last.
+fn compile_alternatives(alternatives: &[Vec<Node>], program: &mut Vec<Inst>) {
+    let mut jumps = Vec::new();
+    for (i, seq) in alternatives.iter().enumerate() {
+        let split = program.len();
+        let last = i + 1 == alternatives.len();
+        if !last {
+            program.push(Inst::Split(split + 1, 0));
+        }
+        for node in seq {
+            compile_node(node, program);
+        }
+        if !last {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+    }
+    let end = program.len();
+    for jump in jumps {
+        program[jump] = Inst::Jump(end);
+    }
+}
+
+fn compile_node(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Set(ranges, negated) => program.push(Inst::Set(ranges.clone(), *negated)),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alternatives) => compile_alternatives(alternatives, program),
+        // The parser only makes ?, * and +. The first place of a split is
+        // tried first, which keeps them greedy.
+        Node::Repeat(inner, 0, Some(_)) => {
+            let split = program.len();
+            program.push(Inst::Split(0, 0));
+            compile_node(inner, program);
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+        Node::Repeat(inner, 0, None) => {
+            let split = program.len();
+            program.push(Inst::Split(0, 0));
+            compile_node(inner, program);
+            program.push(Inst::Jump(split));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+        Node::Repeat(inner, _, _) => {
+            let start = program.len();
+            compile_node(inner, program);
+            program.push(Inst::Split(start, program.len() + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+    use std::time::{Duration, Instant};
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_match_anywhere() {
+        assert!(matches("log", "syslog.1"));
+        assert!(matches("", "anything"));
+        assert!(!matches("logs", "syslog.1"));
+    }
+
+    #[test]
+    fn smartcase() {
+        assert!(matches("readme", "README.md"));
+        assert!(matches("README", "README.md"));
+        assert!(!matches("README", "readme.md"));
+        // An escaped capital is a class, not a capital.
+        assert!(matches("\\Dmd", "README.md"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^lib", "libc.so"));
+        assert!(!matches("^lib", "glibc.so"));
+        assert!(matches("\\.so$", "libc.so"));
+        assert!(!matches("\\.so$", "libc.so.6"));
+        assert!(matches("^$", ""));
+    }
+
+    #[test]
+    fn classes_and_sets() {
+        assert!(matches("\\d\\d", "core.12"));
+        assert!(!matches("\\d\\d", "core.1"));
+        assert!(matches("^\\w+$", "snake_case9"));
+        assert!(!matches("^\\w+$", "two words"));
+        assert!(matches("\\s", "two words"));
+        assert!(matches("^[a-c]+$", "cab"));
+        assert!(!matches("^[^a-c]+$", "cab"));
+        assert!(matches("[]x]", "a]"));
+        assert!(matches("[a\\d]$", "v2"));
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "ac"));
+    }
+
+    #[test]
+    fn repetition_and_alternatives() {
+        assert!(matches("^ab*c$", "ac"));
+        assert!(matches("^ab*c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab?c$", "abc"));
+        assert!(!matches("^ab?c$", "abbc"));
+        assert!(matches("\\.(jpe?g|png)$", "photo.jpeg"));
+        assert!(matches("\\.(jpe?g|png)$", "icon.png"));
+        assert!(!matches("\\.(jpe?g|png)$", "notes.txt"));
+        assert!(matches("^(ab)+$", "ababab"));
+        assert!(!matches("^(ab)+$", "ababa"));
+        assert!(matches("^(a*)*$", "aaa"));
+        assert!(matches("^(|x)y$", "y"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for pattern in ["(a", "a)", "[ab", "a\\", "*a", "a|+"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn nested_repetition_is_linear() {
+        let name = format!("{}b", "a".repeat(5000));
+        let started = Instant::now();
+        assert!(!matches("^(a+)+$", &name));
+        assert!(!matches("(a*)*c", &name));
+        assert!(!matches("(a|aa)+$", &name));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,82 +1,679 @@
-use std::collections::HashMap;
-use std::fs;
+use crate::config::{Hook, HookEvent};
+use crate::diskcache::Digest;
+use crate::glob;
+use crate::hook::{self, Field};
+use crate::log::{self, Level};
+use crate::vfs::{FileSystem, Kind, Meta};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::hash::Hash;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc::{self, Receiver, Sender},
-    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    mpsc::{self, Sender},
+    Arc, Condvar, Mutex, OnceLock,
 };
 use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ItemKind {
     Dir,
     File,
     FilesAggregate,
+    // Files beyond the Files view limit, folded into one block.
+    Others,
+    // All files below the directory with one extension, in the Types view.
+    Extension,
+    // Another filesystem mounted here, left out with --one-file-system.
+    Mount,
+    // What is left on the filesystem, shown at its root. Never scanned.
+    FreeSpace,
+}
+
+impl ItemKind {
+    // Whether the item is a real entry that can be marked or deleted.
+    pub fn is_entry(self) -> bool {
+        matches!(self, ItemKind::Dir | ItemKind::File)
+    }
 }
 
+// Name and path are shared, not owned: the same listing is held by the
+// scan cache, the view and its sorted copies, and cloning an item of a
+// folder with millions of entries must not copy its strings each time.
 #[derive(Debug, Clone)]
 pub struct Item {
-    pub name: String,
-    pub path: PathBuf,
+    pub name: Arc<str>,
+    pub path: Arc<Path>,
+    // Apparent size (file lengths), and the blocks actually used on disk.
     pub size: u64,
+    pub allocated: u64,
     pub kind: ItemKind,
     pub count: u64,
+    // Sizing timed out, so `size` is only a lower bound.
+    pub partial: bool,
+    // Last modification in seconds since the epoch; for a folder or a group
+    // of files the newest of anything in it. 0 when unknown.
+    pub mtime: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    // The real name, not the one shown; for the root of a tree, the whole
+    // path it was scanned at. Paths below are rebuilt from the names on the
+    // way down, so a big tree does not keep each full path.
+    pub name: OsString,
+    pub size: u64,
+    pub allocated: u64,
+    pub count: u64,
+    pub is_dir: bool,
+    // Another filesystem mounted here, kept by a deep scan but not walked.
+    pub mount: bool,
+    // Newest modification in the subtree, as in Item.
+    pub mtime: i64,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    // For the root of a tree.
+    pub fn path(&self) -> &Path {
+        Path::new(&self.name)
+    }
+
+    pub fn find(&self, path: &Path) -> Option<&Node> {
+        let rel = path.strip_prefix(self.path()).ok()?;
+        let mut node = self;
+        for part in rel.iter() {
+            node = node.children.iter().find(|c| c.is_dir && !c.mount && c.name == part)?;
+        }
+        Some(node)
+    }
+
+    // Takes the entry at `path` out of the tree and subtracts it from every
+    // directory above it, so a deleted entry disappears without a rescan.
+    pub fn remove(&mut self, path: &Path) -> Option<Node> {
+        let rel = path.strip_prefix(self.path()).ok()?.to_path_buf();
+        self.remove_below(&rel)
+    }
+
+    fn remove_below(&mut self, rel: &Path) -> Option<Node> {
+        let mut parts = rel.iter();
+        let first = parts.next()?;
+        let idx = self.children.iter().position(|c| c.name == first)?;
+        let removed = if parts.as_path().as_os_str().is_empty() {
+            self.children.remove(idx)
+        } else {
+            self.children[idx].remove_below(parts.as_path())?
+        };
+        self.size = self.size.saturating_sub(removed.size);
+        self.allocated = self.allocated.saturating_sub(removed.allocated);
+        self.count = self.count.saturating_sub(removed.count);
+        Some(removed)
+    }
+}
+
+// Names are for display only; paths keep the real bytes. Invalid UTF-8 and
+// control characters are shown as escapes so that distinct names stay
+// distinct on screen.
+pub fn display_name(name: &OsStr) -> String {
+    let mut out = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    out
+}
+
+// The listing of `node`, found at `path`.
+pub fn items_from_node(node: &Node, path: &Path, view: ViewMode) -> (Vec<Item>, u64) {
+    let mut items: Vec<Item> = Vec::new();
+    let mut files_total = 0u64;
+    let mut files_allocated = 0u64;
+    let mut files_count = 0u64;
+    let mut files_mtime = 0i64;
+    for child in &node.children {
+        match (child.is_dir, view) {
+            (true, ViewMode::Dirs) if child.mount => items.push(mount_item(path.join(&child.name))),
+            (true, ViewMode::Dirs) => items.push(Item {
+                name: display_name(&child.name).into(),
+                path: path.join(&child.name).into(),
+                size: child.size,
+                allocated: child.allocated,
+                kind: ItemKind::Dir,
+                count: child.count,
+                partial: false,
+                mtime: child.mtime,
+            }),
+            (false, ViewMode::Dirs) => {
+                files_total = files_total.saturating_add(child.size);
+                files_allocated = files_allocated.saturating_add(child.allocated);
+                files_count += 1;
+                files_mtime = files_mtime.max(child.mtime);
+            }
+            (false, ViewMode::Files) => items.push(Item {
+                name: display_name(&child.name).into(),
+                path: path.join(&child.name).into(),
+                size: child.size,
+                allocated: child.allocated,
+                kind: ItemKind::File,
+                count: 0,
+                partial: false,
+                mtime: child.mtime,
+            }),
+            (true, ViewMode::Files) => {}
+            // These need the whole subtree, see types_from_node and
+            // largest_from_node.
+            (_, ViewMode::Types | ViewMode::Largest) => {}
+        }
+    }
+    if view == ViewMode::Dirs {
+        items.push(Item {
+            name: format!("(Files: {})", files_count).into(),
+            path: path.into(),
+            size: files_total,
+            allocated: files_allocated,
+            kind: ItemKind::FilesAggregate,
+            count: files_count,
+            partial: false,
+            mtime: files_mtime,
+        });
+    }
+    let total: u64 = items.iter().map(|i| i.size).sum();
+    (items, total)
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub errno: Option<i32>,
+    pub message: String,
+}
+
+impl ScanError {
+    fn io(path: &Path, err: &std::io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            errno: err.raw_os_error(),
+            message: match err.raw_os_error() {
+                Some(code) => strerror(code),
+                None => err.to_string(),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ViewMode {
     Dirs,
     Files,
+    // The whole subtree's files grouped by extension.
+    Types,
+    // The biggest files anywhere in the subtree.
+    Largest,
+}
+
+impl ViewMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewMode::Dirs => "dirs",
+            ViewMode::Files => "files",
+            ViewMode::Types => "types",
+            ViewMode::Largest => "largest",
+        }
+    }
+}
+
+// What a scan leaves out: whole directories (duplicate views of a mount,
+// Windows drives), pseudo-filesystem mount points and --exclude patterns,
+// which are matched against an entry's name or, when they contain a slash,
+// its whole path.
+#[derive(Debug, Clone, Default)]
+pub struct Exclude {
+    pub paths: Vec<PathBuf>,
+    pub pseudo: Vec<PathBuf>,
+    pub patterns: Vec<String>,
+}
+
+impl Exclude {
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.paths.iter().any(|p| p == path) || self.pseudo.iter().any(|p| p == path) {
+            return true;
+        }
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let full = path.to_string_lossy();
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        self.patterns
+            .iter()
+            .any(|p| glob::matches(p, if p.contains('/') { &full } else { &name }))
+    }
 }
 
 #[derive(Debug)]
 pub enum ScanMsg {
     Progress { scanned: u64, errors: u64 },
-    Done { items: Vec<Item>, total: u64, errors: u64 },
+    // The entries sized so far, while the others are still being sized.
+    Partial { items: Vec<Item> },
+    // `stamp` is the dir_stamp of the folder as it was scanned.
+    Done { items: Vec<Item>, total: u64, errors: Vec<ScanError>, stamp: u64 },
+    // The whole subtree of a deep scan.
+    Tree { root: Node, errors: Vec<ScanError> },
+    // The folder no longer has the stamp a check was asked about.
+    Stale,
+    // Sent after a folders listing and after a check.
+    Volumes(Volumes),
     Error(String),
 }
 
-pub struct ScanHandle {
-    pub cancel: Arc<AtomicBool>,
-    pub rx: Receiver<ScanMsg>,
+// The filesystems behind a listing: the one the folder is on, when it is its
+// root, and those mounted on its folders. Looked up on the scanner thread once
+// per scan, so neither drawing nor new results have to stat them.
+#[derive(Debug, Clone, Default)]
+pub struct Volumes {
+    pub path: PathBuf,
+    // Used and total bytes.
+    pub root: Option<(u64, u64)>,
+    // Used bytes and the device, like /dev/sdb1, of each mount point.
+    pub mounts: HashMap<PathBuf, (Option<u64>, Option<String>)>,
 }
 
-pub fn start_scan(path: PathBuf, view: ViewMode) -> ScanHandle {
-    let (tx, rx) = mpsc::channel();
-    let cancel = Arc::new(AtomicBool::new(false));
-    let cancel_thread = cancel.clone();
-
-    let tx_thread = tx.clone();
-    thread::spawn(move || {
-        let result = match view {
-            ViewMode::Dirs => scan_dir_approx(&path, tx_thread, &cancel_thread),
-            ViewMode::Files => scan_files_direct(&path, tx_thread, &cancel_thread),
+fn volumes(fs: &dyn FileSystem, path: &Path, dirs: &[PathBuf]) -> Volumes {
+    let dev = |p: &Path| fs.metadata(p).ok().map(|m| m.dev);
+    let here = dev(path);
+    let root = match path.parent() {
+        Some(parent) => here.is_some() && dev(parent).is_some_and(|d| Some(d) != here),
+        None => here.is_some(),
+    };
+    let mounts = dirs
+        .iter()
+        .filter(|dir| dev(dir).is_some_and(|d| Some(d) != here))
+        .map(|dir| (dir.clone(), (fs.usage(dir).map(|(used, _)| used), fs.device(dir))))
+        .collect();
+    Volumes {
+        path: path.to_path_buf(),
+        root: if root { fs.usage(path) } else { None },
+        mounts,
+    }
+}
+
+// The UI redraws at most once per frame, so progress is only worth sending a
+// few times per frame. Checking the clock every 256 entries keeps the check
+// itself off the hot path.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+// Partial results are heavier (a copy of every sized entry), so they are
+// sent less often.
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct ProgressTicker {
+    last: Instant,
+}
+
+impl ProgressTicker {
+    fn new() -> Self {
+        Self { last: Instant::now() }
+    }
+
+    fn due(&mut self, scanned: u64) -> bool {
+        if !scanned.is_multiple_of(256) || self.last.elapsed() < PROGRESS_INTERVAL {
+            return false;
+        }
+        self.last = Instant::now();
+        true
+    }
+}
+
+// Folder sizes from earlier scans, by path. Only used to decide which
+// folders to size first.
+pub type SizeHints = HashMap<PathBuf, u64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // What the user asked to see.
+    Foreground,
+    // Speculative scans such as prefetches. They only run while no
+    // foreground scan waits, are cancelled when one comes in, and don't run
+    // the scan hooks.
+    Background,
+}
+
+// What to scan, for Scanner::submit. `hints` are sizes known from before, to
+// scan the biggest folders first.
+pub struct ScanRequest {
+    pub path: PathBuf,
+    pub view: ViewMode,
+    pub exclude: Arc<Exclude>,
+    pub limits: ScanLimits,
+    pub hints: Arc<SizeHints>,
+    pub priority: Priority,
+}
+
+struct ScanJob {
+    generation: u64,
+    path: PathBuf,
+    view: ViewMode,
+    exclude: Arc<Exclude>,
+    limits: ScanLimits,
+    hints: Arc<SizeHints>,
+    priority: Priority,
+    deep: bool,
+    // Only compare the folder, with these items, against a stamp.
+    check: Option<(Arc<[Item]>, u64)>,
+    cancel: Arc<AtomicBool>,
+}
+
+// A cancelled scan that has not returned after this long, while another
+// waits, is stuck (in a read of a dead network mount, say) and left behind.
+const STUCK_AFTER: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct Jobs {
+    foreground: VecDeque<ScanJob>,
+    background: VecDeque<ScanJob>,
+    // The cancel flag of a background scan that is running.
+    running: Option<Arc<AtomicBool>>,
+    // The cancel flag of whatever scan is running, and since when it has
+    // been seen cancelled with another one waiting.
+    current: Option<Arc<AtomicBool>>,
+    stuck_since: Option<Instant>,
+    // The thread that takes the jobs; one that was left behind stops
+    // once its scan returns.
+    runner: u64,
+    closed: bool,
+}
+
+// The scans waiting for the scan thread, foreground ones first.
+#[derive(Default)]
+struct JobQueue {
+    jobs: Mutex<Jobs>,
+    ready: Condvar,
+}
+
+impl JobQueue {
+    fn push(&self, job: ScanJob) {
+        let mut jobs = self.jobs.lock().unwrap();
+        match job.priority {
+            Priority::Foreground => {
+                if let Some(running) = jobs.running.take() {
+                    running.store(true, Ordering::Relaxed);
+                }
+                jobs.foreground.push_back(job);
+            }
+            Priority::Background => jobs.background.push_back(job),
+        }
+        self.ready.notify_all();
+    }
+
+    // Waits for the next job for `runner` to run; None once the scanner is
+    // gone or another thread has taken over.
+    fn next(&self, runner: u64) -> Option<ScanJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            if jobs.runner != runner {
+                return None;
+            }
+            jobs.running = None;
+            jobs.current = None;
+            jobs.stuck_since = None;
+            if let Some(job) = jobs.foreground.pop_front() {
+                jobs.current = Some(Arc::clone(&job.cancel));
+                return Some(job);
+            }
+            if let Some(job) = jobs.background.pop_front() {
+                jobs.running = Some(Arc::clone(&job.cancel));
+                jobs.current = Some(Arc::clone(&job.cancel));
+                return Some(job);
+            }
+            if jobs.closed {
+                return None;
+            }
+            jobs = self.ready.wait(jobs).unwrap();
+        }
+    }
+
+    // Hands the queue to a new thread, through `start`, whenever the
+    // running scan is stuck. Returns once the scanner is gone.
+    fn watch(&self, start: &dyn Fn(u64)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        while !jobs.closed {
+            let waiting = !jobs.foreground.is_empty() || !jobs.background.is_empty();
+            let cancelled = jobs.current.as_ref().is_some_and(|c| c.load(Ordering::Relaxed));
+            if !waiting || !cancelled {
+                jobs.stuck_since = None;
+            } else if jobs.stuck_since.get_or_insert_with(Instant::now).elapsed() >= STUCK_AFTER {
+                log::warning!("scan", "a cancelled scan does not return; starting another scan thread");
+                jobs.runner += 1;
+                jobs.running = None;
+                jobs.current = None;
+                jobs.stuck_since = None;
+                start(jobs.runner);
+            }
+            jobs = self.ready.wait_timeout(jobs, Duration::from_millis(500)).unwrap().0;
+        }
+    }
+
+    fn close(&self) {
+        self.jobs.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+// A single long-lived thread runs the listings one at a time, so navigating
+// never spawns threads and no scan can outlive the one that replaced it.
+// Directory sizing is handed on to the shared sizing pool. Only a scan that
+// is stuck past cancelling gets the thread replaced, by a watchdog.
+pub struct Scanner {
+    queue: Arc<JobQueue>,
+}
+
+impl Scanner {
+    // `sink` receives every message tagged with the scan's generation, and
+    // None once that scan has finished or was cancelled.
+    pub fn spawn(
+        fs: Arc<dyn FileSystem>,
+        hooks: Vec<Hook>,
+        sink: impl Fn(u64, Option<ScanMsg>) + Send + Sync + 'static,
+    ) -> Scanner {
+        let queue = Arc::new(JobQueue::default());
+        let run = Arc::new(move |job: ScanJob| run_job(&fs, &hooks, &sink, job));
+        let start = {
+            let queue = Arc::clone(&queue);
+            move |runner: u64| {
+                let (jobs, run) = (Arc::clone(&queue), Arc::clone(&run));
+                thread::spawn(move || {
+                    while let Some(job) = jobs.next(runner) {
+                        run(job);
+                    }
+                });
+            }
         };
-        if let Err(err) = result {
-            let _ = tx.send(ScanMsg::Error(err));
+        start(0);
+        let jobs = Arc::clone(&queue);
+        thread::spawn(move || jobs.watch(&start));
+        Scanner { queue }
+    }
+
+    // Queues a scan and returns its cancel flag. Setting the flag makes the
+    // running scan stop early, or a queued one get skipped.
+    pub fn submit(&self, generation: u64, request: ScanRequest) -> Arc<AtomicBool> {
+        let ScanRequest { path, view, exclude, limits, hints, priority } = request;
+        self.queue(ScanJob {
+            generation,
+            path,
+            view,
+            exclude,
+            limits,
+            hints,
+            priority,
+            deep: false,
+            check: None,
+            cancel: Arc::default(),
+        })
+    }
+
+    // Like submit, but walks the whole subtree into one tree that the UI
+    // then navigates without scanning again.
+    pub fn submit_tree(
+        &self,
+        generation: u64,
+        path: PathBuf,
+        exclude: Arc<Exclude>,
+        limits: ScanLimits,
+    ) -> Arc<AtomicBool> {
+        self.queue(ScanJob {
+            generation,
+            path,
+            view: ViewMode::Dirs,
+            exclude,
+            limits,
+            hints: Arc::default(),
+            priority: Priority::Foreground,
+            deep: true,
+            check: None,
+            cancel: Arc::default(),
+        })
+    }
+
+    // Asks whether a folder listed with `items` still has `stamp`, without
+    // scanning it; the answer is a Stale message or none.
+    pub fn check(&self, generation: u64, path: PathBuf, items: Arc<[Item]>, stamp: u64) -> Arc<AtomicBool> {
+        self.queue(ScanJob {
+            generation,
+            path,
+            view: ViewMode::Dirs,
+            exclude: Arc::default(),
+            limits: ScanLimits { workers: 0, timeout: None, files: 0, count_links: false, depth: None, one_fs: false },
+            hints: Arc::default(),
+            priority: Priority::Foreground,
+            deep: false,
+            check: Some((items, stamp)),
+            cancel: Arc::default(),
+        })
+    }
+
+    fn queue(&self, job: ScanJob) -> Arc<AtomicBool> {
+        let cancel = Arc::clone(&job.cancel);
+        self.queue.push(job);
+        cancel
+    }
+}
+
+impl Drop for Scanner {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+// Runs one scan on the scanner thread and tells `sink` when it is over.
+fn run_job(fs: &Arc<dyn FileSystem>, hooks: &[Hook], sink: &dyn Fn(u64, Option<ScanMsg>), job: ScanJob) {
+    if job.cancel.load(Ordering::Relaxed) {
+        log::trace!("scan", "skipped cancelled scan of {}", job.path.display());
+        sink(job.generation, None);
+        return;
+    }
+    if let Some((items, stamp)) = &job.check {
+        if dir_stamp(fs.as_ref(), &job.path, items) != *stamp {
+            sink(job.generation, Some(ScanMsg::Stale));
         }
+        let dirs: Vec<PathBuf> =
+            items.iter().filter(|i| i.kind == ItemKind::Dir).map(|i| i.path.to_path_buf()).collect();
+        sink(job.generation, Some(ScanMsg::Volumes(volumes(fs.as_ref(), &job.path, &dirs))));
+        sink(job.generation, None);
+        return;
+    }
+    let label = || format!("{} ({:?} view)", job.path.display(), job.view);
+    let _span = log::span(Level::Info, "scan", label);
+    let hooks: &[Hook] = match job.priority {
+        Priority::Foreground => hooks,
+        Priority::Background => &[],
+    };
+    let mut fields = vec![
+        ("path", Field::Text(job.path.to_string_lossy().into_owned())),
+        ("view", Field::Text(job.view.name().to_string())),
+    ];
+    let done = Cell::new(None);
+    let dirs = RefCell::new(None);
+    let emit = |msg: ScanMsg| {
+        match &msg {
+            ScanMsg::Done { items, total, errors, .. } => {
+                done.set(Some((*total, items.len() as u64, errors.len() as u64)));
+                if job.view == ViewMode::Dirs {
+                    let paths = items.iter().filter(|i| i.kind == ItemKind::Dir).map(|i| i.path.to_path_buf());
+                    dirs.replace(Some(paths.collect::<Vec<_>>()));
+                }
+            }
+            ScanMsg::Tree { root, errors } => {
+                done.set(Some((root.size, root.count, errors.len() as u64)));
+                let paths = root.children.iter().filter(|c| c.is_dir).map(|c| root.path().join(&c.name));
+                dirs.replace(Some(paths.collect()));
+            }
+            _ => {}
+        }
+        sink(job.generation, Some(msg))
+    };
+    let result = hook::run(hooks, HookEvent::PreScan, &fields).and_then(|()| match job.view {
+        _ if job.deep => scan_tree_deep(fs.as_ref(), &job.path, &job.exclude, job.limits, &emit, &job.cancel),
+        ViewMode::Dirs => scan_dir_approx(fs, &job.path, &job.exclude, job.limits, &job.hints, &emit, &job.cancel),
+        ViewMode::Files => scan_files_direct(fs.as_ref(), &job.path, &job.exclude, job.limits, &emit, &job.cancel),
+        ViewMode::Types => scan_types(fs.as_ref(), &job.path, &job.exclude, job.limits, &emit, &job.cancel),
+        ViewMode::Largest => scan_largest(fs.as_ref(), &job.path, &job.exclude, job.limits, &emit, &job.cancel),
     });
-
-    ScanHandle { cancel, rx }
+    if let Err(err) = result {
+        log::warning!("scan", "{}: {}", job.path.display(), err);
+        emit(ScanMsg::Error(err));
+    }
+    if let Some(dirs) = dirs.take().filter(|_| !job.cancel.load(Ordering::Relaxed)) {
+        emit(ScanMsg::Volumes(volumes(fs.as_ref(), &job.path, &dirs)));
+    }
+    if let Some((bytes, items, errors)) = done.get() {
+        fields.push(("bytes", Field::Number(bytes)));
+        fields.push(("items", Field::Number(items)));
+        fields.push(("errors", Field::Number(errors)));
+        hook::spawn(hooks, HookEvent::PostScan, fields);
+    }
+    sink(job.generation, None);
 }
 
-fn scan_dir_approx(path: &Path, tx: Sender<ScanMsg>, cancel: &Arc<AtomicBool>) -> Result<(), String> {
+fn scan_dir_approx(
+    fs: &Arc<dyn FileSystem>,
+    path: &Path,
+    exclude: &Arc<Exclude>,
+    limits: ScanLimits,
+    hints: &SizeHints,
+    emit: &dyn Fn(ScanMsg),
+    cancel: &Arc<AtomicBool>,
+) -> Result<(), String> {
     if is_proc_path(path) {
         return Err("/proc is excluded".to_string());
     }
     let base = path.to_path_buf();
-    let base_canon = fs::canonicalize(&base).unwrap_or(base.clone());
+    let base_canon = fs.canonicalize(&base).unwrap_or(base.clone());
     let mut items: Vec<Item> = Vec::new();
-    let mut errors = 0u64;
+    let mut errors: Vec<ScanError> = Vec::new();
     let mut scanned = 0u64;
+    let mut progress = ProgressTicker::new();
 
-    let read_dir = fs::read_dir(path).map_err(|e| format!("Failed to read dir: {}", e))?;
+    let read_dir = fs.read_dir(path).map_err(|e| format!("Failed to read dir: {}", e))?;
 
     let mut dir_names: HashMap<PathBuf, usize> = HashMap::new();
     let mut files_total = 0u64;
+    let mut files_allocated = 0u64;
     let mut files_count = 0u64;
+    let mut files_mtime = 0i64;
+    let inodes: Arc<Inodes> = Arc::default();
+    let base_dev = fs.metadata(&base_canon).ok().map(|m| m.dev);
 
     for entry in read_dir {
         if cancel.load(Ordering::Relaxed) {
@@ -84,121 +681,379 @@ fn scan_dir_approx(path: &Path, tx: Sender<ScanMsg>, cancel: &Arc<AtomicBool>) -
         }
         let entry = match entry {
             Ok(e) => e,
-            Err(_) => {
-                errors += 1;
+            Err(e) => {
+                errors.push(ScanError::io(&base_canon, &e));
                 continue;
             }
         };
-        let child_path = {
-            let p = entry.path();
-            if p.is_absolute() {
-                p
-            } else {
-                base_canon.join(entry.file_name())
-            }
+        let child_path = if entry.path.is_absolute() {
+            entry.path
+        } else {
+            base_canon.join(&entry.name)
         };
-        if is_proc_path(&child_path) {
+        // Pseudo filesystems are shown like mounts left out by
+        // --one-file-system: there, but not sized.
+        if entry.kind == Kind::Dir && exclude.pseudo.contains(&child_path) {
+            items.push(mount_item(child_path));
             continue;
         }
-        let name = entry.file_name().to_string_lossy().to_string();
-
-        let file_type = match entry.file_type() {
-            Ok(t) => t,
-            Err(_) => {
-                errors += 1;
-                continue;
-            }
-        };
-
-        if file_type.is_symlink() {
+        if is_proc_path(&child_path) || exclude.matches(&child_path) {
             continue;
         }
+        let name = display_name(&entry.name);
 
-        if file_type.is_file() {
-            match entry.metadata() {
-                Ok(m) => files_total = files_total.saturating_add(m.len()),
-                Err(_) => errors += 1,
+        if entry.kind == Kind::File {
+            match fs.symlink_metadata(&child_path) {
+                Ok(m) if !first_link(&inodes, &m, limits.count_links) => {}
+                Ok(m) => {
+                    files_total = files_total.saturating_add(m.len);
+                    files_allocated = files_allocated.saturating_add(m.blocks.saturating_mul(512));
+                    files_mtime = files_mtime.max(m.mtime);
+                }
+                Err(e) => errors.push(ScanError::io(&child_path, &e)),
             }
             files_count += 1;
             scanned += 1;
-            if scanned % 2000 == 0 {
-                let _ = tx.send(ScanMsg::Progress { scanned, errors });
+            if progress.due(scanned) {
+                emit(ScanMsg::Progress { scanned, errors: errors.len() as u64 });
             }
             continue;
         }
 
-        if file_type.is_dir() {
+        if entry.kind == Kind::Dir {
+            // Other filesystems mounted right here, with --one-file-system,
+            // are shown but not sized.
+            if limits.one_fs && fs.symlink_metadata(&child_path).is_ok_and(|m| Some(m.dev) != base_dev) {
+                items.push(mount_item(child_path));
+                continue;
+            }
             let idx = items.len();
             items.push(Item {
-                name,
-                path: child_path.clone(),
+                name: name.into(),
+                path: child_path.as_path().into(),
                 size: 0,
+                allocated: 0,
                 kind: ItemKind::Dir,
                 count: 0,
+                partial: false,
+                mtime: 0,
             });
-            let key = normalize_path(&base_canon, &child_path);
+            let key = normalize_path(fs.as_ref(), &base_canon, &child_path);
             dir_names.insert(key, idx);
             scanned += 1;
-            if scanned % 2000 == 0 {
-                let _ = tx.send(ScanMsg::Progress { scanned, errors });
+            if progress.due(scanned) {
+                emit(ScanMsg::Progress { scanned, errors: errors.len() as u64 });
             }
         }
     }
 
     let files_label = format!("(Files: {})", files_count);
     items.push(Item {
-        name: files_label,
-        path: base_canon.clone(),
+        name: files_label.into(),
+        path: base_canon.as_path().into(),
         size: files_total,
+        allocated: files_allocated,
         kind: ItemKind::FilesAggregate,
         count: files_count,
+        partial: false,
+        mtime: files_mtime,
     });
 
     if !dir_names.is_empty() {
         if cancel.load(Ordering::Relaxed) {
             return Ok(());
         }
-        let dir_paths: Vec<PathBuf> = items
+        let mut dir_paths: Vec<Arc<Path>> = items
             .iter()
             .filter(|i| i.kind == ItemKind::Dir)
             .map(|i| i.path.clone())
             .collect();
-        match du_sizes_parallel(&dir_paths, cancel) {
-            Ok(batch_sizes) => {
-                for (p, size) in batch_sizes {
-                    let key = normalize_path(&base_canon, &p);
-                    if let Some(idx) = dir_names.get(&key) {
-                        if let Some(item) = items.get_mut(*idx) {
-                            item.size = size;
-                        }
-                    }
+        largest_first(&mut dir_paths, hints);
+        // Directories show up as their sizes come in, so a long scan draws a
+        // growing treemap instead of only a spinner.
+        let mut done: HashSet<usize> = HashSet::new();
+        let mut last_partial = Instant::now();
+        size_dirs_parallel(fs, &dir_paths, exclude, limits, &inodes, cancel, &mut |mut sized| {
+            errors.append(&mut sized.errors);
+            let key = normalize_path(fs.as_ref(), &base_canon, &sized.path);
+            if let Some(&idx) = dir_names.get(&key) {
+                if let Some(item) = items.get_mut(idx) {
+                    item.size = sized.size;
+                    item.allocated = sized.allocated;
+                    item.count = sized.count;
+                    item.partial = sized.partial;
+                    item.mtime = sized.mtime;
+                    done.insert(idx);
                 }
             }
-            Err(_) => {
-                errors += dir_names.len() as u64;
+            if last_partial.elapsed() >= PARTIAL_INTERVAL && done.len() < dir_names.len() {
+                last_partial = Instant::now();
+                let shown: Vec<Item> = items
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, item)| item.kind != ItemKind::Dir || done.contains(i))
+                    .map(|(_, item)| item.clone())
+                    .collect();
+                emit(ScanMsg::Partial { items: shown });
             }
+        });
+        emit(ScanMsg::Progress { scanned, errors: errors.len() as u64 });
+    }
+
+    // The app orders the items in its current sort mode.
+    let total: u64 = items.iter().map(|i| i.size).sum();
+    let stamp = dir_stamp(fs.as_ref(), path, &items);
+    emit(ScanMsg::Done { items, total, errors, stamp });
+    Ok(())
+}
+
+// Keeps every directory and file of the subtree, like ncdu, so nothing below
+// `path` needs another scan. Sizing runs on this thread alone: the tree is
+// built in one walk instead of one per subdirectory.
+fn scan_tree_deep(
+    fs: &dyn FileSystem,
+    path: &Path,
+    exclude: &Exclude,
+    limits: ScanLimits,
+    emit: &dyn Fn(ScanMsg),
+    cancel: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let opts = TreeOptions {
+        max_depth: limits.depth.unwrap_or(usize::MAX),
+        max_children: usize::MAX,
+        keep_files: true,
+        follow_links: false,
+        allocated: false,
+        count_links: limits.count_links,
+        one_fs: limits.one_fs,
+        mounts: true,
+        du: false,
+    };
+    let mut scanned = 0u64;
+    let mut progress = ProgressTicker::new();
+    let (root, errors) = walk_tree(fs, path, opts, exclude, &Inodes::default(), &mut |_, _| {
+        scanned += 1;
+        if progress.due(scanned) {
+            // The walker only hands its errors over at the end.
+            emit(ScanMsg::Progress { scanned, errors: 0 });
         }
-        let _ = tx.send(ScanMsg::Progress { scanned, errors });
+        !cancel.load(Ordering::Relaxed)
+    })?;
+    if !cancel.load(Ordering::Relaxed) {
+        emit(ScanMsg::Tree { root, errors });
     }
+    Ok(())
+}
 
+// Walks the whole subtree and adds up its files by extension. The totals so
+// far are sent along the way, like the folder sizes of a Dirs scan.
+fn scan_types(
+    fs: &dyn FileSystem,
+    path: &Path,
+    exclude: &Exclude,
+    limits: ScanLimits,
+    emit: &dyn Fn(ScanMsg),
+    cancel: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let opts = TreeOptions {
+        max_depth: 0,
+        max_children: 0,
+        keep_files: false,
+        follow_links: false,
+        allocated: false,
+        count_links: limits.count_links,
+        one_fs: limits.one_fs,
+        mounts: false,
+        du: false,
+    };
+    let base = fs.canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut types = TypeTotals::default();
+    let mut scanned = 0u64;
+    let mut progress = ProgressTicker::new();
+    let mut last_partial = Instant::now();
+    let (_, errors) = walk_tree(fs, path, opts, exclude, &Inodes::default(), &mut |node_path, node| {
+        if !node.is_dir {
+            types.add(node_path, node);
+        }
+        scanned += 1;
+        if progress.due(scanned) {
+            emit(ScanMsg::Progress { scanned, errors: 0 });
+            if last_partial.elapsed() >= PARTIAL_INTERVAL {
+                last_partial = Instant::now();
+                emit(ScanMsg::Partial { items: types.items(&base, limits.files) });
+            }
+        }
+        !cancel.load(Ordering::Relaxed)
+    })?;
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let items = types.items(&base, limits.files);
     let total: u64 = items.iter().map(|i| i.size).sum();
-    items.sort_by(|a, b| b.size.cmp(&a.size));
+    let stamp = dir_stamp(fs, path, &items);
+    emit(ScanMsg::Done { items, total, errors, stamp });
+    Ok(())
+}
 
-    let _ = tx.send(ScanMsg::Done { items, total, errors });
+// Like scan_types, but keeps the biggest files themselves, named by their
+// path below the scanned folder.
+fn scan_largest(
+    fs: &dyn FileSystem,
+    path: &Path,
+    exclude: &Exclude,
+    limits: ScanLimits,
+    emit: &dyn Fn(ScanMsg),
+    cancel: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let opts = TreeOptions {
+        max_depth: 0,
+        max_children: 0,
+        keep_files: false,
+        follow_links: false,
+        allocated: false,
+        count_links: limits.count_links,
+        one_fs: limits.one_fs,
+        mounts: false,
+        du: false,
+    };
+    let base = fs.canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut files = TopFiles::new(limits.files);
+    let mut scanned = 0u64;
+    let mut progress = ProgressTicker::new();
+    let (_, errors) = walk_tree(fs, path, opts, exclude, &Inodes::default(), &mut |node_path, node| {
+        if !node.is_dir {
+            files.push(file_item(&base, node_path, node));
+        }
+        scanned += 1;
+        if progress.due(scanned) {
+            emit(ScanMsg::Progress { scanned, errors: 0 });
+        }
+        !cancel.load(Ordering::Relaxed)
+    })?;
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let items = files.finish(&base);
+    let total: u64 = items.iter().map(|i| i.size).sum();
+    let stamp = dir_stamp(fs, path, &items);
+    emit(ScanMsg::Done { items, total, errors, stamp });
     Ok(())
 }
 
-fn scan_files_direct(path: &Path, tx: Sender<ScanMsg>, cancel: &Arc<AtomicBool>) -> Result<(), String> {
+fn file_item(base: &Path, path: &Path, file: &Node) -> Item {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    Item {
+        name: display_name(relative.as_os_str()).into(),
+        path: path.into(),
+        size: file.size,
+        allocated: file.allocated,
+        kind: ItemKind::File,
+        count: 0,
+        partial: false,
+        mtime: file.mtime,
+    }
+}
+
+// Calls `each` for every file of a tree, with its path. False if the depth
+// limit of a deep scan cut some of them off.
+fn each_file(node: &Node, path: &Path, each: &mut dyn FnMut(&Path, &Node)) -> bool {
+    if !node.is_dir {
+        each(path, node);
+        return true;
+    }
+    if node.children.is_empty() && node.count > 0 {
+        return false;
+    }
+    node.children.iter().all(|child| each_file(child, &path.join(&child.name), each))
+}
+
+// The Types view of a tree that has every file, for `node` found at `path`.
+pub fn types_from_node(node: &Node, path: &Path, limit: usize) -> Option<Vec<Item>> {
+    let mut types = TypeTotals::default();
+    each_file(node, path, &mut |file_path, file| types.add(file_path, file)).then(|| types.items(path, limit))
+}
+
+// The Largest view of a tree that has every file.
+pub fn largest_from_node(node: &Node, path: &Path, limit: usize) -> Option<Vec<Item>> {
+    let mut files = TopFiles::new(limit);
+    each_file(node, path, &mut |file_path, file| files.push(file_item(path, file_path, file)))
+        .then(|| files.finish(path))
+}
+
+// Lowercased, so .JPG and .jpg are one type. Version numbers are skipped:
+// libfoo.so.1.2 counts as .so.
+fn extension_of(path: &Path) -> Option<String> {
+    let mut rest = Path::new(path.file_name()?);
+    loop {
+        let ext = rest.extension()?.to_string_lossy().to_lowercase();
+        let stem = Path::new(rest.file_stem()?);
+        if !ext.bytes().all(|b| b.is_ascii_digit()) || stem.extension().is_none() {
+            return Some(ext);
+        }
+        rest = stem;
+    }
+}
+
+#[derive(Default)]
+struct TypeTotals {
+    by_extension: HashMap<String, Item>,
+}
+
+impl TypeTotals {
+    fn add(&mut self, path: &Path, file: &Node) {
+        let name = match extension_of(path) {
+            Some(ext) => format!(".{}", ext),
+            None => "(no extension)".to_string(),
+        };
+        let item = self.by_extension.entry(name).or_insert_with_key(|name| Item {
+            name: name.as_str().into(),
+            path: Path::new("").into(),
+            size: 0,
+            allocated: 0,
+            kind: ItemKind::Extension,
+            count: 0,
+            partial: false,
+            mtime: 0,
+        });
+        item.size = item.size.saturating_add(file.size);
+        item.allocated = item.allocated.saturating_add(file.allocated);
+        item.count += 1;
+        item.mtime = item.mtime.max(file.mtime);
+    }
+
+    // The `limit` biggest types, and the rest as one block.
+    fn items(&self, dir: &Path, limit: usize) -> Vec<Item> {
+        let mut items = TopFiles::new(limit);
+        for item in self.by_extension.values() {
+            items.push(Item {
+                path: dir.into(),
+                ..item.clone()
+            });
+        }
+        items.finish(dir)
+    }
+}
+
+fn scan_files_direct(
+    fs: &dyn FileSystem,
+    path: &Path,
+    exclude: &Exclude,
+    limits: ScanLimits,
+    emit: &dyn Fn(ScanMsg),
+    cancel: &Arc<AtomicBool>,
+) -> Result<(), String> {
     if is_proc_path(path) {
         return Err("/proc is excluded".to_string());
     }
     let base = path.to_path_buf();
-    let base_canon = fs::canonicalize(&base).unwrap_or(base);
-    let mut items: Vec<Item> = Vec::new();
-    let mut errors = 0u64;
+    let base_canon = fs.canonicalize(&base).unwrap_or(base);
+    let mut items = TopFiles::new(limits.files);
+    let inodes = Inodes::default();
+    let mut errors: Vec<ScanError> = Vec::new();
     let mut scanned = 0u64;
+    let mut progress = ProgressTicker::new();
 
-    let read_dir = fs::read_dir(path).map_err(|e| format!("Failed to read dir: {}", e))?;
+    let read_dir = fs.read_dir(path).map_err(|e| format!("Failed to read dir: {}", e))?;
 
     for entry in read_dir {
         if cancel.load(Ordering::Relaxed) {
@@ -206,132 +1061,855 @@ fn scan_files_direct(path: &Path, tx: Sender<ScanMsg>, cancel: &Arc<AtomicBool>)
         }
         let entry = match entry {
             Ok(e) => e,
-            Err(_) => {
-                errors += 1;
+            Err(e) => {
+                errors.push(ScanError::io(&base_canon, &e));
                 continue;
             }
         };
-        let child_path = {
-            let p = entry.path();
-            if p.is_absolute() {
-                p
-            } else {
-                base_canon.join(entry.file_name())
-            }
+        let child_path = if entry.path.is_absolute() {
+            entry.path
+        } else {
+            base_canon.join(&entry.name)
         };
-        if is_proc_path(&child_path) {
+        if is_proc_path(&child_path) || exclude.matches(&child_path) {
             continue;
         }
-        let file_type = match entry.file_type() {
-            Ok(t) => t,
-            Err(_) => {
-                errors += 1;
-                continue;
-            }
-        };
-        if file_type.is_symlink() || file_type.is_dir() {
+        if matches!(entry.kind, Kind::Symlink | Kind::Dir) {
             continue;
         }
-        let size = match entry.metadata() {
-            Ok(m) => m.len(),
-            Err(_) => {
-                errors += 1;
-                0
+        let (size, allocated, mtime) = match fs.symlink_metadata(&child_path) {
+            // Further links to a file that is already listed.
+            Ok(m) if !first_link(&inodes, &m, limits.count_links) => continue,
+            Ok(m) => (m.len, m.blocks.saturating_mul(512), m.mtime),
+            Err(e) => {
+                errors.push(ScanError::io(&child_path, &e));
+                (0, 0, 0)
             }
         };
-        let name = entry.file_name().to_string_lossy().to_string();
-        items.push(Item {
-            name,
-            path: child_path,
-            size,
-            kind: ItemKind::File,
-            count: 0,
-        });
+        if !items.fold(size, allocated, mtime) {
+            items.push(Item {
+                name: display_name(&entry.name).into(),
+                path: child_path.into(),
+                size,
+                allocated,
+                kind: ItemKind::File,
+                count: 0,
+                partial: false,
+                mtime,
+            });
+        }
         scanned += 1;
-        if scanned % 2000 == 0 {
-            let _ = tx.send(ScanMsg::Progress { scanned, errors });
+        if progress.due(scanned) {
+            emit(ScanMsg::Progress { scanned, errors: errors.len() as u64 });
         }
     }
 
+    let items = items.finish(&base_canon);
     let total: u64 = items.iter().map(|i| i.size).sum();
-    items.sort_by(|a, b| b.size.cmp(&a.size));
-
-    let _ = tx.send(ScanMsg::Done { items, total, errors });
+    let stamp = dir_stamp(fs, path, &items);
+    emit(ScanMsg::Done { items, total, errors, stamp });
     Ok(())
 }
 
-fn du_sizes_parallel(paths: &[PathBuf], cancel: &Arc<AtomicBool>) -> Result<Vec<(PathBuf, u64)>, String> {
-    if paths.is_empty() {
-        return Ok(Vec::new());
+// Keeps the largest `limit` files seen so far and folds the rest into a
+// running total, so a directory with millions of files needs memory for
+// twice the limit at most.
+struct TopFiles {
+    items: Vec<Item>,
+    limit: usize,
+    // The smallest size kept by the last trim. Anything no bigger is folded
+    // without building an item for it.
+    floor: Option<u64>,
+    rest_size: u64,
+    rest_allocated: u64,
+    rest_count: u64,
+    rest_mtime: i64,
+}
+
+impl TopFiles {
+    fn new(limit: usize) -> Self {
+        Self {
+            items: Vec::new(),
+            limit: limit.max(1),
+            floor: None,
+            rest_size: 0,
+            rest_allocated: 0,
+            rest_count: 0,
+            rest_mtime: 0,
+        }
     }
-    let workers = std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(2)
-        .min(8);
-    let work = Arc::new(std::sync::Mutex::new(paths.to_vec()));
-    let (tx, rx) = mpsc::channel();
 
-    let mut handles = Vec::new();
-    for _ in 0..workers {
-        let work = Arc::clone(&work);
-        let tx = tx.clone();
-        let cancel = Arc::clone(cancel);
-        handles.push(thread::spawn(move || {
-            loop {
-                if cancel.load(Ordering::Relaxed) {
-                    break;
+    // Adds a file that is too small to be listed to the rest, and says
+    // whether it was.
+    fn fold(&mut self, size: u64, allocated: u64, mtime: i64) -> bool {
+        if self.floor.is_none_or(|floor| size > floor) {
+            return false;
+        }
+        self.add_rest(size, allocated, mtime);
+        true
+    }
+
+    fn add_rest(&mut self, size: u64, allocated: u64, mtime: i64) {
+        self.rest_size = self.rest_size.saturating_add(size);
+        self.rest_allocated = self.rest_allocated.saturating_add(allocated);
+        self.rest_count += 1;
+        self.rest_mtime = self.rest_mtime.max(mtime);
+    }
+
+    fn push(&mut self, item: Item) {
+        self.items.push(item);
+        if self.items.len() >= self.limit * 2 {
+            self.trim();
+        }
+    }
+
+    fn trim(&mut self) {
+        if self.items.len() <= self.limit {
+            return;
+        }
+        self.items.select_nth_unstable_by_key(self.limit, |i| std::cmp::Reverse(i.size));
+        self.floor = self.items[..self.limit].iter().map(|i| i.size).min();
+        for item in self.items.split_off(self.limit) {
+            self.add_rest(item.size, item.allocated, item.mtime);
+        }
+    }
+
+    fn finish(mut self, dir: &Path) -> Vec<Item> {
+        self.trim();
+        if self.rest_count > 0 {
+            self.items.push(Item {
+                name: format!("({} more)", self.rest_count).into(),
+                path: dir.into(),
+                size: self.rest_size,
+                allocated: self.rest_allocated,
+                kind: ItemKind::Others,
+                count: self.rest_count,
+                partial: false,
+                mtime: self.rest_mtime,
+            });
+        }
+        self.items
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TreeOptions {
+    pub max_depth: usize,
+    pub max_children: usize,
+    pub keep_files: bool,
+    pub follow_links: bool,
+    pub allocated: bool,
+    // Count every hard link of a file, as du -l does, instead of only the first.
+    pub count_links: bool,
+    // Leave out other filesystems mounted below the root, as du -x does.
+    pub one_fs: bool,
+    // Keep what is left out that way, and pseudo filesystems, as mount nodes.
+    pub mounts: bool,
+    // Count the entries of folders and symlinks themselves too, as du does.
+    // Everywhere else a folder is what it holds.
+    pub du: bool,
+}
+
+// Files already counted, by device and inode. One set is shared by all the
+// walkers of a scan, so a file hard-linked from two sibling directories is
+// only counted in whichever of them is sized first.
+type Inodes = Mutex<HashSet<(u64, u64)>>;
+
+// Whether this is the first link to the file seen in the scan. Only files
+// with several links need to be remembered.
+fn first_link(inodes: &Inodes, meta: &Meta, count_links: bool) -> bool {
+    count_links || meta.nlink <= 1 || inodes.lock().unwrap().insert((meta.dev, meta.ino))
+}
+
+pub fn scan_tree(
+    fs: &dyn FileSystem,
+    path: &Path,
+    opts: TreeOptions,
+    exclude: &Exclude,
+) -> Result<(Node, Vec<ScanError>), String> {
+    scan_tree_with(fs, path, opts, exclude, &mut |_, _| true)
+}
+
+pub fn scan_tree_with(
+    fs: &dyn FileSystem,
+    path: &Path,
+    opts: TreeOptions,
+    exclude: &Exclude,
+    visit: &mut dyn FnMut(&Path, &Node) -> bool,
+) -> Result<(Node, Vec<ScanError>), String> {
+    walk_tree(fs, path, opts, exclude, &Inodes::default(), visit)
+}
+
+fn walk_tree(
+    fs: &dyn FileSystem,
+    path: &Path,
+    opts: TreeOptions,
+    exclude: &Exclude,
+    inodes: &Inodes,
+    visit: &mut dyn FnMut(&Path, &Node) -> bool,
+) -> Result<(Node, Vec<ScanError>), String> {
+    walk_tree_split(fs, path, opts, exclude, inodes, &|_| false, visit)
+}
+
+// Like walk_tree, but a subfolder that `hand_off` takes is left for someone
+// else to size and is not counted here.
+fn walk_tree_split(
+    fs: &dyn FileSystem,
+    path: &Path,
+    opts: TreeOptions,
+    exclude: &Exclude,
+    inodes: &Inodes,
+    hand_off: &dyn Fn(&Path) -> bool,
+    visit: &mut dyn FnMut(&Path, &Node) -> bool,
+) -> Result<(Node, Vec<ScanError>), String> {
+    if is_proc_path(path) {
+        return Err("/proc is excluded".to_string());
+    }
+    let base = fs.canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let meta = fs.metadata(&base).map_err(|e| format!("Failed to read dir: {}", e))?;
+    if meta.kind != Kind::Dir {
+        return Err(format!("{} is not a directory", base.display()));
+    }
+    let mut walker = TreeWalker {
+        fs,
+        dev: meta.dev,
+        opts,
+        exclude,
+        errors: Vec::new(),
+        inodes,
+        seen_dirs: HashSet::from([(meta.dev, meta.ino)]),
+        stopped: false,
+        hand_off,
+        visit,
+    };
+    let root = walker.walk(&base, base.clone().into_os_string(), &meta, 0);
+    Ok((root, walker.errors))
+}
+
+struct TreeWalker<'a> {
+    fs: &'a dyn FileSystem,
+    dev: u64,
+    opts: TreeOptions,
+    exclude: &'a Exclude,
+    errors: Vec<ScanError>,
+    inodes: &'a Inodes,
+    seen_dirs: HashSet<(u64, u64)>,
+    stopped: bool,
+    hand_off: &'a dyn Fn(&Path) -> bool,
+    visit: &'a mut dyn FnMut(&Path, &Node) -> bool,
+}
+
+impl TreeWalker<'_> {
+    // Allocated mode counts the blocks on disk like plain du, so sparse files
+    // shrink and compressed filesystems report their real footprint.
+    // Every node keeps the blocks on disk as well, whatever the size mode.
+    fn size_of(&self, meta: &Meta) -> u64 {
+        if self.opts.allocated {
+            meta.blocks.saturating_mul(512)
+        } else {
+            meta.len
+        }
+    }
+
+    fn emit(&mut self, path: &Path, node: &Node) {
+        if !self.stopped && !(self.visit)(path, node) {
+            self.stopped = true;
+        }
+    }
+
+    // A filesystem left out is still shown where it is mounted, unsized,
+    // like the mounts of a shallow scan.
+    fn keep_mount(&self, parent: &mut Node, name: OsString, keep_children: bool) {
+        if !self.opts.mounts || !keep_children {
+            return;
+        }
+        parent.children.push(Node {
+            name,
+            size: 0,
+            allocated: 0,
+            count: 0,
+            is_dir: true,
+            mount: true,
+            mtime: 0,
+            children: Vec::new(),
+        });
+    }
+
+    // For du, a directory's own entry size counts toward its total.
+    fn walk(&mut self, path: &Path, name: OsString, meta: &Meta, depth: usize) -> Node {
+        let own = |size: u64| if self.opts.du { size } else { 0 };
+        let mut node = Node {
+            name,
+            size: own(self.size_of(meta)),
+            allocated: own(meta.blocks.saturating_mul(512)),
+            count: 0,
+            is_dir: true,
+            mount: false,
+            mtime: meta.mtime,
+            children: Vec::new(),
+        };
+        let read_dir = match self.fs.read_dir(path) {
+            Ok(r) => r,
+            Err(e) => {
+                self.errors.push(ScanError::io(path, &e));
+                self.emit(path, &node);
+                return node;
+            }
+        };
+        let keep_children = depth < self.opts.max_depth;
+        for entry in read_dir {
+            if self.stopped {
+                break;
+            }
+            let entry = match entry {
+                Ok(e) => e,
+                Err(e) => {
+                    self.errors.push(ScanError::io(path, &e));
+                    continue;
                 }
-                let next = {
-                    let mut guard = work.lock().unwrap();
-                    guard.pop()
+            };
+            let child_path = entry.path;
+            if entry.kind == Kind::Dir && self.exclude.pseudo.contains(&child_path) {
+                self.keep_mount(&mut node, entry.name, keep_children);
+                continue;
+            }
+            if is_proc_path(&child_path) || self.exclude.matches(&child_path) {
+                continue;
+            }
+            let meta = match self.fs.symlink_metadata(&child_path) {
+                Ok(m) => m,
+                Err(e) => {
+                    self.errors.push(ScanError::io(&child_path, &e));
+                    continue;
+                }
+            };
+            // Unless following is enabled, links are skipped, though for du
+            // their own size counts. Followed links resolve to their target,
+            // and the seen sets keep a target that is also reachable directly
+            // (or an ancestor, for a cycle) from being counted twice. The
+            // filesystem checks below then see the target's, so with one_fs a
+            // link out of the root's filesystem is left out, as du -Lx does.
+            let meta = if entry.kind == Kind::Symlink {
+                match self.fs.metadata(&child_path) {
+                    Ok(target) if self.opts.follow_links => target,
+                    _ if self.opts.du => {
+                        node.size = node.size.saturating_add(self.size_of(&meta));
+                        node.allocated = node.allocated.saturating_add(meta.blocks.saturating_mul(512));
+                        continue;
+                    }
+                    _ => continue,
+                }
+            } else {
+                meta
+            };
+            if meta.kind == Kind::Dir {
+                if self.opts.one_fs && meta.dev != self.dev {
+                    self.keep_mount(&mut node, entry.name, keep_children);
+                    continue;
+                }
+                if self.opts.follow_links && !self.seen_dirs.insert((meta.dev, meta.ino)) {
+                    continue;
+                }
+                if (self.hand_off)(&child_path) {
+                    continue;
+                }
+                let child = self.walk(&child_path, entry.name, &meta, depth + 1);
+                node.size = node.size.saturating_add(child.size);
+                node.allocated = node.allocated.saturating_add(child.allocated);
+                node.count += child.count;
+                node.mtime = node.mtime.max(child.mtime);
+                if keep_children {
+                    node.children.push(child);
+                }
+            } else if meta.kind == Kind::File {
+                // Count hard-linked files once, as du does, unless every link
+                // should count. Followed links can reach any file twice.
+                let shared = (meta.nlink > 1 && !self.opts.count_links) || self.opts.follow_links;
+                let other_fs = self.opts.one_fs && meta.dev != self.dev;
+                if shared && (other_fs || !self.inodes.lock().unwrap().insert((meta.dev, meta.ino))) {
+                    continue;
+                }
+                let child = Node {
+                    name: entry.name,
+                    size: self.size_of(&meta),
+                    allocated: meta.blocks.saturating_mul(512),
+                    count: 1,
+                    is_dir: false,
+                    mount: false,
+                    mtime: meta.mtime,
+                    children: Vec::new(),
                 };
-                let Some(path) = next else { break };
-                let size = du_size_single(&path).unwrap_or(0);
-                let _ = tx.send((path, size));
+                self.emit(&child_path, &child);
+                node.size = node.size.saturating_add(child.size);
+                node.allocated = node.allocated.saturating_add(child.allocated);
+                node.count += 1;
+                node.mtime = node.mtime.max(child.mtime);
+                if keep_children && self.opts.keep_files {
+                    node.children.push(child);
+                }
             }
-        }));
+        }
+        node.children.sort_by_key(|c| std::cmp::Reverse(c.size));
+        if node.children.len() > self.opts.max_children {
+            let rest = node.children.split_off(self.opts.max_children);
+            node.children.push(Node {
+                name: format!("({} more)", rest.len()).into(),
+                size: rest.iter().map(|c| c.size).sum(),
+                allocated: rest.iter().map(|c| c.allocated).sum(),
+                count: rest.iter().map(|c| c.count).sum(),
+                is_dir: false,
+                mount: false,
+                mtime: rest.iter().map(|c| c.mtime).max().unwrap_or(0),
+                children: Vec::new(),
+            });
+        }
+        self.emit(path, &node);
+        node
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScanLimits {
+    pub workers: usize,
+    pub timeout: Option<Duration>,
+    // Largest files listed individually in the Files view.
+    pub files: usize,
+    pub count_links: bool,
+    // Levels kept by a deep scan; deeper folders are scanned when opened.
+    pub depth: Option<usize>,
+    // Leave out other filesystems mounted in the scanned folder.
+    pub one_fs: bool,
+}
+
+// A folder to size, or a part of one: a busy worker hands subfolders of the
+// folder it walks to idle ones, so one huge folder does not leave the rest
+// of the pool waiting.
+struct SizeJob {
+    fs: Arc<dyn FileSystem>,
+    path: Arc<Path>,
+    // The folder whose total this counts toward.
+    top: Arc<Path>,
+    exclude: Arc<Exclude>,
+    timeout: Option<Duration>,
+    // When sizing of `top` began; the timeout applies to all its parts.
+    started: Option<Instant>,
+    count_links: bool,
+    one_fs: bool,
+    inodes: Arc<Inodes>,
+    cancel: Arc<AtomicBool>,
+    // Parts of `top` sent to the pool and not yet received back.
+    pending: Arc<AtomicUsize>,
+    reply: Sender<Sized>,
+}
+
+struct Sized {
+    path: PathBuf,
+    size: u64,
+    allocated: u64,
+    count: u64,
+    partial: bool,
+    mtime: i64,
+    errors: Vec<ScanError>,
+}
+
+impl Sized {
+    fn add(&mut self, part: Sized) {
+        self.size = self.size.saturating_add(part.size);
+        self.allocated = self.allocated.saturating_add(part.allocated);
+        self.count += part.count;
+        self.partial |= part.partial;
+        self.mtime = self.mtime.max(part.mtime);
+        self.errors.extend(part.errors);
+    }
+}
+
+// The sizing pool's workers, how many of them are busy and how many jobs
+// wait for one.
+static POOL_WORKERS: AtomicUsize = AtomicUsize::new(0);
+static POOL_BUSY: AtomicUsize = AtomicUsize::new(0);
+static POOL_QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+// Busy and total sizing workers, for the debug overlay.
+pub fn sizing_load() -> (usize, usize) {
+    (POOL_BUSY.load(Ordering::Relaxed), POOL_WORKERS.load(Ordering::Relaxed))
+}
+
+// One pool of sizing workers lives for the whole process, so navigating does
+// not spawn a fresh fleet each time. Its size is fixed by the first scan.
+fn sizing_pool(workers: usize) -> Sender<SizeJob> {
+    static POOL: OnceLock<Mutex<Sender<SizeJob>>> = OnceLock::new();
+    let pool = POOL.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<SizeJob>();
+        let rx = Arc::new(Mutex::new(rx));
+        POOL_WORKERS.store(workers.max(1), Ordering::Relaxed);
+        for _ in 0..workers.max(1) {
+            let rx = Arc::clone(&rx);
+            thread::spawn(move || loop {
+                let job = match rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                POOL_QUEUED.fetch_sub(1, Ordering::Relaxed);
+                // Jobs of a superseded scan are dropped without running.
+                if job.cancel.load(Ordering::Relaxed) {
+                    continue;
+                }
+                POOL_BUSY.fetch_add(1, Ordering::Relaxed);
+                let sized = size_dir(&job);
+                POOL_BUSY.fetch_sub(1, Ordering::Relaxed);
+                let _ = job.reply.send(sized);
+            });
+        }
+        Mutex::new(tx)
+    });
+    pool.lock().unwrap().clone()
+}
+
+fn send_job(pool: &Sender<SizeJob>, job: SizeJob) -> bool {
+    POOL_QUEUED.fetch_add(1, Ordering::Relaxed);
+    job.pending.fetch_add(1, Ordering::Relaxed);
+    match pool.send(job) {
+        Ok(()) => true,
+        Err(mpsc::SendError(job)) => {
+            POOL_QUEUED.fetch_sub(1, Ordering::Relaxed);
+            job.pending.fetch_sub(1, Ordering::Relaxed);
+            false
+        }
+    }
+}
+
+// Sizes the folders on the pool and calls `each` as each one's total is
+// complete, with all its parts added up.
+fn size_dirs_parallel(
+    fs: &Arc<dyn FileSystem>,
+    paths: &[Arc<Path>],
+    exclude: &Arc<Exclude>,
+    limits: ScanLimits,
+    inodes: &Arc<Inodes>,
+    cancel: &Arc<AtomicBool>,
+    each: &mut dyn FnMut(Sized),
+) {
+    let pool = sizing_pool(limits.workers);
+    let (tx, rx) = mpsc::channel();
+    let mut totals: HashMap<PathBuf, (Sized, Arc<AtomicUsize>)> = HashMap::new();
+    for path in paths {
+        let pending: Arc<AtomicUsize> = Arc::default();
+        let job = SizeJob {
+            fs: Arc::clone(fs),
+            path: Arc::clone(path),
+            top: Arc::clone(path),
+            exclude: Arc::clone(exclude),
+            timeout: limits.timeout,
+            started: None,
+            count_links: limits.count_links,
+            one_fs: limits.one_fs,
+            inodes: Arc::clone(inodes),
+            cancel: Arc::clone(cancel),
+            pending: Arc::clone(&pending),
+            reply: tx.clone(),
+        };
+        if send_job(&pool, job) {
+            let empty = Sized {
+                path: path.to_path_buf(),
+                size: 0,
+                allocated: 0,
+                count: 0,
+                partial: false,
+                mtime: 0,
+                errors: Vec::new(),
+            };
+            totals.insert(path.to_path_buf(), (empty, pending));
+        }
     }
     drop(tx);
+    // A part is counted as pending before the one that handed it off
+    // replies, so a total cannot come out complete while parts are missing.
+    for part in rx.iter() {
+        let path = part.path.clone();
+        let Some((total, pending)) = totals.get_mut(&path) else { continue };
+        total.add(part);
+        if pending.fetch_sub(1, Ordering::Relaxed) > 1 {
+            continue;
+        }
+        if let Some((mut sized, _)) = totals.remove(&path) {
+            let partial = if sized.partial { " (partial)" } else { "" };
+            log::debug!("size", "{}: {} bytes, {} allocated{}", path.display(), sized.size, sized.allocated, partial);
+            if sized.partial && !cancel.load(Ordering::Relaxed) {
+                log::warning!("size", "{} timed out", path.display());
+                sized.errors.push(ScanError {
+                    path,
+                    errno: Some(libc::ETIMEDOUT),
+                    message: format!(
+                        "timed out after {}s, size is a lower bound",
+                        limits.timeout.unwrap_or_default().as_secs()
+                    ),
+                });
+            }
+            each(sized);
+        }
+    }
+}
+
+// Sizes one folder or part, giving up at the timeout or on cancellation.
+// What was counted until then is returned as a partial (lower bound) size.
+fn size_dir(job: &SizeJob) -> Sized {
+    let (path, cancel) = (&*job.path, &job.cancel);
+    let _span = log::span(Level::Debug, "size", || path.display().to_string());
+    let started = job.started.unwrap_or_else(Instant::now);
+    let deadline = job.timeout.map(|t| started + t);
+    let expired = || cancel.load(Ordering::Relaxed) || deadline.is_some_and(|d| Instant::now() >= d);
+    // Subfolders go to the pool while it has a worker with nothing to do.
+    let pool = sizing_pool(0);
+    let hand_off = |dir: &Path| {
+        let idle = POOL_BUSY.load(Ordering::Relaxed) < POOL_WORKERS.load(Ordering::Relaxed);
+        if !idle || POOL_QUEUED.load(Ordering::Relaxed) > 0 || expired() {
+            return false;
+        }
+        log::trace!("size", "handing off {}", dir.display());
+        let part = SizeJob {
+            fs: Arc::clone(&job.fs),
+            path: dir.into(),
+            top: Arc::clone(&job.top),
+            exclude: Arc::clone(&job.exclude),
+            timeout: job.timeout,
+            started: Some(started),
+            count_links: job.count_links,
+            one_fs: job.one_fs,
+            inodes: Arc::clone(&job.inodes),
+            cancel: Arc::clone(&job.cancel),
+            pending: Arc::clone(&job.pending),
+            reply: job.reply.clone(),
+        };
+        send_job(&pool, part)
+    };
+    let walked = walk_size(job, &hand_off, &expired);
+    let mut sized = walked.unwrap_or_else(|message| Sized {
+        path: path.to_path_buf(),
+        size: 0,
+        allocated: 0,
+        count: 0,
+        partial: false,
+        mtime: 0,
+        errors: vec![ScanError {
+            path: path.to_path_buf(),
+            errno: None,
+            message,
+        }],
+    });
+    sized.path = job.top.to_path_buf();
+    sized
+}
 
-    let mut out = Vec::with_capacity(paths.len());
-    for item in rx.iter() {
-        out.push(item);
+fn walk_size(job: &SizeJob, hand_off: &dyn Fn(&Path) -> bool, expired: &dyn Fn() -> bool) -> Result<Sized, String> {
+    let opts = TreeOptions {
+        max_depth: 0,
+        max_children: 0,
+        keep_files: false,
+        follow_links: false,
+        allocated: false,
+        count_links: job.count_links,
+        one_fs: job.one_fs,
+        mounts: false,
+        du: false,
+    };
+    let (fs, path) = (job.fs.as_ref(), &*job.path);
+    let mut stopped = false;
+    let (root, errors) = walk_tree_split(fs, path, opts, &job.exclude, &job.inodes, hand_off, &mut |_, _| {
+        stopped = stopped || expired();
+        !stopped
+    })?;
+    Ok(Sized {
+        path: path.to_path_buf(),
+        size: root.size,
+        allocated: root.allocated,
+        count: root.count,
+        partial: stopped,
+        mtime: root.mtime,
+        errors,
+    })
+}
+
+// Cheap fingerprint of a directory and its immediate subdirectories: adding,
+// removing or renaming an entry in any of them changes its mtime or ctime.
+// Changes deeper down go unnoticed until a rescan.
+pub fn dir_stamp(fs: &dyn FileSystem, path: &Path, items: &[Item]) -> u64 {
+    let mut digest = Digest::new();
+    let dirs = items.iter().filter(|i| i.kind == ItemKind::Dir).map(|i| &*i.path);
+    for dir in std::iter::once(path).chain(dirs) {
+        if let Ok(m) = fs.metadata(dir) {
+            for n in [m.mtime, m.mtime_nsec, m.ctime, m.ctime_nsec] {
+                digest.number(n as u64);
+            }
+        }
     }
-    for h in handles {
-        let _ = h.join();
+    digest.finish()
+}
+
+fn strerror(code: i32) -> String {
+    let text = std::io::Error::from_raw_os_error(code).to_string();
+    match text.find(" (os error") {
+        Some(i) => text[..i].to_string(),
+        None => text,
     }
-    Ok(out)
 }
 
-fn du_size_single(path: &Path) -> Result<u64, String> {
-    let output = Command::new("du")
-        .arg("-k")
-        .arg("-x")
-        .arg("--apparent-size")
-        .arg("-s")
-        .arg("--")
-        .arg(path)
-        .output()
-        .map_err(|e| format!("du failed: {}", e))?;
-    if !output.status.success() {
-        return Err("du returned non-zero status".to_string());
+// The sizing pool takes folders in order, so the big ones go first and the
+// treemap takes its shape early in the scan. Sizes from an earlier scan say
+// which are big; folders without one follow in listing order rather than
+// being read once more to guess, since an idle worker takes over subfolders
+// of a big one anyway.
+fn largest_first(paths: &mut [Arc<Path>], hints: &SizeHints) {
+    paths.sort_by_key(|p| std::cmp::Reverse(hints.get(&**p).copied()));
+}
+
+fn mount_item(path: PathBuf) -> Item {
+    Item {
+        name: format!("mounted: {}", display_name(path.as_os_str())).into(),
+        path: path.into(),
+        size: 0,
+        allocated: 0,
+        kind: ItemKind::Mount,
+        count: 0,
+        partial: false,
+        mtime: 0,
     }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut parts = stdout.lines().next().unwrap_or("").splitn(2, '\t');
-    let size_kb = parts.next().unwrap_or("0").trim();
-    let size: u64 = size_kb.parse::<u64>().unwrap_or(0).saturating_mul(1024);
-    Ok(size)
 }
 
 fn is_proc_path(path: &Path) -> bool {
     path.starts_with("/proc")
 }
 
-fn normalize_path(base: &Path, p: &Path) -> PathBuf {
+fn normalize_path(fs: &dyn FileSystem, base: &Path, p: &Path) -> PathBuf {
     let joined = if p.is_absolute() {
         p.to_path_buf()
     } else {
         base.join(p)
     };
-    fs::canonicalize(&joined).unwrap_or(joined)
+    fs.canonicalize(&joined).unwrap_or(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::Memory;
+
+    // Two folders, a hard link from one to the other, a symlink and another
+    // filesystem mounted in /data/mnt.
+    fn fixture() -> Memory {
+        let fs = Memory::new();
+        fs.dir("/data")
+            .dir("/data/a")
+            .file("/data/a/big", 10_000)
+            .file("/data/a/small", 100)
+            .dir("/data/b")
+            .file("/data/b/log", 500)
+            .hard_link("/data/b/link", "/data/a/big")
+            .file("/data/top", 42)
+            .symlink("/data/sym", "a/big")
+            .mount("/data/mnt", 2)
+            .file("/data/mnt/disk", 1_000_000);
+        fs
+    }
+
+    fn deep() -> TreeOptions {
+        TreeOptions {
+            max_depth: usize::MAX,
+            max_children: usize::MAX,
+            keep_files: true,
+            follow_links: false,
+            allocated: false,
+            count_links: false,
+            one_fs: false,
+            mounts: false,
+            du: false,
+        }
+    }
+
+    fn size_of(fs: &Memory, opts: TreeOptions, exclude: &Exclude) -> (u64, u64) {
+        let (root, errors) = scan_tree(fs, Path::new("/data"), opts, exclude).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        (root.size, root.count)
+    }
+
+    #[test]
+    fn hard_links_count_once() {
+        let fs = fixture();
+        assert_eq!(size_of(&fs, deep(), &Exclude::default()), (1_010_642, 5));
+        let every = TreeOptions { count_links: true, ..deep() };
+        assert_eq!(size_of(&fs, every, &Exclude::default()), (1_020_642, 6));
+    }
+
+    #[test]
+    fn followed_links_count_their_target_once() {
+        let fs = fixture();
+        let follow = TreeOptions { follow_links: true, ..deep() };
+        assert_eq!(size_of(&fs, follow, &Exclude::default()), (1_010_642, 5));
+    }
+
+    #[test]
+    fn du_counts_folders_and_links_themselves() {
+        let fs = fixture();
+        let du = TreeOptions { du: true, ..deep() };
+        // Four folders and the five bytes of the link.
+        assert_eq!(size_of(&fs, du, &Exclude::default()), (1_010_642 + 4 * 4096 + 5, 5));
+    }
+
+    #[test]
+    fn excluded_entries_are_left_out() {
+        let fs = fixture();
+        let exclude = Exclude { patterns: vec!["b".to_string()], ..Exclude::default() };
+        // The hard link in b no longer matters either way.
+        assert_eq!(size_of(&fs, deep(), &exclude), (1_010_142, 4));
+        let exclude = Exclude { paths: vec![PathBuf::from("/data/mnt")], ..Exclude::default() };
+        assert_eq!(size_of(&fs, deep(), &exclude), (10_642, 4));
+    }
+
+    #[test]
+    fn one_file_system_keeps_mounts_unsized() {
+        let fs = fixture();
+        let opts = TreeOptions { one_fs: true, mounts: true, ..deep() };
+        let (root, _) = scan_tree(&fs, Path::new("/data"), opts, &Exclude::default()).unwrap();
+        assert_eq!((root.size, root.count), (10_642, 4));
+        let (items, _) = items_from_node(&root, root.path(), ViewMode::Dirs);
+        let mount = items.iter().find(|i| &*i.path == Path::new("/data/mnt")).unwrap();
+        assert_eq!((mount.kind, mount.size), (ItemKind::Mount, 0));
+        // A mount is not part of the tree to navigate.
+        assert!(root.find(Path::new("/data/mnt")).is_none());
+    }
+
+    #[test]
+    fn shallow_scan_sizes_each_folder() {
+        let fs: Arc<dyn FileSystem> = Arc::new(fixture());
+        let limits =
+            ScanLimits { workers: 2, timeout: None, files: 100, count_links: false, depth: None, one_fs: true };
+        let done = Mutex::new(None);
+        let emit = |msg: ScanMsg| {
+            if let ScanMsg::Done { items, .. } = msg {
+                *done.lock().unwrap() = Some(items);
+            }
+        };
+        let exclude = Arc::new(Exclude::default());
+        let cancel = Arc::default();
+        scan_dir_approx(&fs, Path::new("/data"), &exclude, limits, &SizeHints::new(), &emit, &cancel).unwrap();
+        let items = done.into_inner().unwrap().unwrap();
+        let find = |path: &str| items.iter().find(|i| &*i.path == Path::new(path)).unwrap();
+        // The hard link is counted in whichever folder is sized first.
+        assert_eq!(find("/data/a").size + find("/data/b").size, 10_600);
+        assert_eq!(find("/data/mnt").kind, ItemKind::Mount);
+        let files = items.iter().find(|i| i.kind == ItemKind::FilesAggregate).unwrap();
+        assert_eq!((files.size, files.count), (42, 1));
+    }
+
+    #[test]
+    fn deleting_takes_the_entry_out_of_the_tree() {
+        let fs = fixture();
+        let (mut root, _) = scan_tree(&fs, Path::new("/data"), deep(), &Exclude::default()).unwrap();
+        fs.remove(Path::new("/data/b")).unwrap();
+        let removed = root.remove(Path::new("/data/b")).unwrap();
+        assert_eq!(removed.size, 500);
+        assert!(!fs.exists("/data/b/log"));
+        // The tree now holds what a new scan finds.
+        assert_eq!((root.size, root.count), size_of(&fs, deep(), &Exclude::default()));
+        assert!(fs.remove(Path::new("/data/b")).is_err());
+    }
 }
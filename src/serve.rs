@@ -0,0 +1,155 @@
+use crate::export;
+use crate::scan::{self, Exclude, TreeOptions};
+use crate::vfs::Os;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub fn serve(path: &Path, bind: &str, port: u16, opts: TreeOptions, natural: bool) -> Result<(), String> {
+    let listener =
+        TcpListener::bind((bind, port)).map_err(|e| format!("cannot listen on {}:{}: {}", bind, port, e))?;
+    eprintln!("duviz: scanning {}", path.display());
+    let mut page = render(path, opts, natural)?;
+    eprintln!("duviz: serving http://{}:{}/ (Ctrl+C to stop)", bind, port);
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let _ = stream.set_write_timeout(Some(REQUEST_TIME));
+        let Some(request) = read_request(&stream) else {
+            continue;
+        };
+        let method = request.method.as_str();
+        let target = request.target.split('?').next().unwrap_or("");
+        let _ = match (method, target) {
+            ("GET" | "HEAD", "/") => respond(&mut stream, "200 OK", "text/html; charset=utf-8", &page, method == "HEAD"),
+            // Any page open in the browser could post to the loopback server,
+            // so a rescan has to come from the report itself.
+            ("POST", "/rescan") if !request.same_origin() => {
+                respond(&mut stream, "403 Forbidden", "text/plain", "cross-origin request", false)
+            }
+            ("POST", "/rescan") => match render(path, opts, natural) {
+                Ok(fresh) => {
+                    page = fresh;
+                    redirect(&mut stream, "/")
+                }
+                Err(err) => respond(&mut stream, "500 Internal Server Error", "text/plain", &err, false),
+            },
+            // A rescan is work done on the server, so a link prefetched or
+            // followed by a crawler must not start one.
+            (_, "/rescan") => respond(&mut stream, "405 Method Not Allowed", "text/plain", "use POST", false),
+            _ => respond(&mut stream, "404 Not Found", "text/plain", "not found", false),
+        };
+    }
+    Ok(())
+}
+
+fn render(path: &Path, opts: TreeOptions, natural: bool) -> Result<String, String> {
+    let (root, errors) = scan::scan_tree(&Os, path, opts, &Exclude::default())?;
+    let html = export::html_report(&root, errors.len() as u64, natural);
+    Ok(html.replacen(
+        "</header>",
+        "<form method=\"post\" action=\"/rescan\"><button style=\"color:#8cf;background:none;border:none;\
+         cursor:pointer;font:inherit\">Rescan</button></form></header>",
+        1,
+    ))
+}
+
+// What a request may take, so a client that never stops sending cannot hold
+// up the server, which answers one request at a time.
+const REQUEST_BYTES: u64 = 16 * 1024;
+const REQUEST_HEADERS: usize = 64;
+const REQUEST_TIME: Duration = Duration::from_secs(10);
+
+struct Request {
+    method: String,
+    target: String,
+    host: Option<String>,
+    origin: Option<String>,
+}
+
+impl Request {
+    // Browsers send Origin with every POST; tools like curl send none and
+    // are not a page the user happened to open.
+    fn same_origin(&self) -> bool {
+        match (&self.origin, &self.host) {
+            (None, _) => true,
+            (Some(origin), Some(host)) => origin.strip_prefix("http://") == Some(host.as_str()),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+// Reads from the stream until `until`, however slowly the bytes come in.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        let mut stream = self.stream;
+        stream.set_read_timeout(Some(left))?;
+        stream.read(buf)
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let deadline = Deadline { stream, until: Instant::now() + REQUEST_TIME };
+    let mut reader = BufReader::new(deadline.take(REQUEST_BYTES));
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let mut request = Request {
+        method: parts.next()?.to_string(),
+        target: parts.next()?.to_string(),
+        host: None,
+        origin: None,
+    };
+    for _ in 0..REQUEST_HEADERS {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        if header.trim().is_empty() {
+            return Some(request);
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = Some(value.trim().to_string());
+        match name.trim().to_ascii_lowercase().as_str() {
+            "host" => request.host = value,
+            "origin" => request.origin = value,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str, head: bool) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if !head {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+fn redirect(stream: &mut TcpStream, location: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 303 See Other\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )?;
+    stream.flush()
+}
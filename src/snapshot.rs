@@ -0,0 +1,200 @@
+use crate::export;
+use crate::json::{self, Value};
+use crate::scan::Node;
+use crate::units::format_size;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u64 = 1;
+
+pub struct Snapshot {
+    pub root: Node,
+    pub time: u64,
+    pub errors: u64,
+}
+
+pub struct DiffRow {
+    pub path: PathBuf,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl DiffRow {
+    pub fn delta(&self) -> i128 {
+        self.new as i128 - self.old as i128
+    }
+}
+
+pub fn to_json(root: &Node, errors: u64) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut out = format!(
+        "{{\"duviz_snapshot\":{},\"time\":{},\"errors\":{},\"root\":",
+        FORMAT_VERSION, time, errors
+    );
+    export::node_json(root, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+pub fn write_rotated(dir: &Path, root: &Node, errors: u64, keep: Option<usize>) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = dir.join(export::timestamped_name("duviz", "json"));
+    export::write_output(Some(&path), &to_json(root, errors))?;
+    if let Some(keep) = keep {
+        let mut old: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_rotated_name(p))
+            .collect();
+        old.sort();
+        let excess = old.len().saturating_sub(keep);
+        for stale in &old[..excess] {
+            fs::remove_file(stale).map_err(|e| format!("{}: {}", stale.display(), e))?;
+        }
+    }
+    Ok(path)
+}
+
+// Matches the names produced by write_rotated: duviz-YYYYMMDD-HHMMSS.json
+fn is_rotated_name(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let Some(stamp) = name.strip_prefix("duviz-").and_then(|n| n.strip_suffix(".json")) else {
+        return false;
+    };
+    stamp.len() == 15
+        && stamp
+            .char_indices()
+            .all(|(i, c)| if i == 8 { c == '-' } else { c.is_ascii_digit() })
+}
+
+pub fn read(path: &Path) -> Result<Snapshot, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let doc = json::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if doc.get("duviz_snapshot").and_then(Value::as_u64) != Some(FORMAT_VERSION) {
+        return Err(format!("{}: not a duviz snapshot", path.display()));
+    }
+    let root = doc
+        .get("root")
+        .ok_or_else(|| format!("{}: missing root", path.display()))?;
+    Ok(Snapshot {
+        root: node_from_json(root)
+            .map_err(|e| format!("{}: {}", path.display(), e))?,
+        time: doc.get("time").and_then(Value::as_u64).unwrap_or(0),
+        errors: doc.get("errors").and_then(Value::as_u64).unwrap_or(0),
+    })
+}
+
+fn node_from_json(value: &Value) -> Result<Node, String> {
+    let name = value.get("name").and_then(Value::as_str).ok_or("node without a name")?;
+    let mut children = Vec::new();
+    for child in value.get("children").and_then(Value::as_array).unwrap_or(&[]) {
+        children.push(node_from_json(child)?);
+    }
+    let size = value.get("size").and_then(Value::as_u64).unwrap_or(0);
+    Ok(Node {
+        name: name.into(),
+        size,
+        // Snapshots only record one size.
+        allocated: size,
+        count: value.get("count").and_then(Value::as_u64).unwrap_or(0),
+        is_dir: value.get("dir").and_then(Value::as_bool).unwrap_or(false),
+        mount: false,
+        mtime: 0,
+        children,
+    })
+}
+
+pub fn diff(old: &Node, new: &Node) -> Vec<DiffRow> {
+    let mut old_dirs = HashMap::new();
+    collect_dirs(old, Path::new(""), &mut old_dirs);
+    let mut new_dirs = HashMap::new();
+    collect_dirs(new, Path::new(""), &mut new_dirs);
+
+    let mut rows: Vec<DiffRow> = Vec::new();
+    for (rel, &new_size) in &new_dirs {
+        let old_size = old_dirs.get(rel).copied().unwrap_or(0);
+        rows.push(DiffRow {
+            path: join_rel(new.path(), rel),
+            old: old_size,
+            new: new_size,
+        });
+    }
+    for (rel, &old_size) in &old_dirs {
+        if !new_dirs.contains_key(rel) {
+            rows.push(DiffRow {
+                path: join_rel(new.path(), rel),
+                old: old_size,
+                new: 0,
+            });
+        }
+    }
+    rows.retain(|r| r.delta() != 0);
+    rows.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    rows
+}
+
+fn join_rel(root: &Path, rel: &Path) -> PathBuf {
+    if rel.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(rel)
+    }
+}
+
+fn collect_dirs(node: &Node, rel: &Path, out: &mut HashMap<PathBuf, u64>) {
+    if !node.is_dir {
+        return;
+    }
+    out.insert(rel.to_path_buf(), node.size);
+    for child in &node.children {
+        collect_dirs(child, &rel.join(&child.name), out);
+    }
+}
+
+pub fn format_diff(old: &Snapshot, new: &Snapshot, rows: &[DiffRow], limit: usize) -> String {
+    let mut out = format!(
+        "{} -> {}  total {} -> {} ({})\n\n",
+        export::timestamp(old.time),
+        export::timestamp(new.time),
+        format_size(old.root.size),
+        format_size(new.root.size),
+        format_delta(new.root.size as i128 - old.root.size as i128)
+    );
+    if old.errors > 0 || new.errors > 0 {
+        out.insert_str(
+            out.len() - 1,
+            &format!("(scan errors: {} old, {} new)\n", old.errors, new.errors),
+        );
+    }
+    for row in rows.iter().take(limit) {
+        out.push_str(&format!(
+            "{:>12}  {:>10} -> {:<10}  {}\n",
+            format_delta(row.delta()),
+            format_size(row.old),
+            format_size(row.new),
+            row.path.display()
+        ));
+    }
+    if rows.len() > limit {
+        out.push_str(&format!("... {} more changed directories\n", rows.len() - limit));
+    }
+    out
+}
+
+fn format_delta(delta: i128) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs() as u64))
+}
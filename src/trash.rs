@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::Path;
+
+// Moves a file or directory to the platform trash so it can be restored.
+// Only the folder it is in is resolved: a link is trashed itself, not
+// whatever it points to.
+pub fn send(path: &Path) -> Result<(), String> {
+    let failed = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    fs::symlink_metadata(path).map_err(failed)?;
+    let name = path.file_name().ok_or_else(|| format!("{}: cannot trash this", path.display()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let path = fs::canonicalize(parent).map_err(failed)?.join(name);
+    backend::send(&path).map_err(|e| format!("Move to trash failed: {}", e))
+}
+
+#[cfg(target_os = "macos")]
+mod backend {
+    use std::path::Path;
+    use std::process::Command;
+
+    // Going through Finder keeps "Put Back" working, unlike a rename into ~/.Trash.
+    pub fn send(path: &Path) -> Result<(), String> {
+        let target = path.to_str().ok_or("path is not valid UTF-8")?;
+        let quoted = target.replace('\\', "\\\\").replace('"', "\\\"");
+        let output = Command::new("osascript")
+            .arg("-e")
+            .arg(format!("tell application \"Finder\" to delete POSIX file \"{}\"", quoted))
+            .output()
+            .map_err(|e| format!("osascript: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+// The freedesktop.org trash specification, used by Linux and BSD desktops.
+#[cfg(not(target_os = "macos"))]
+mod backend {
+    use crate::fsinfo;
+    use std::ffi::OsStr;
+    use std::fs::{self, OpenOptions};
+    use std::io::{ErrorKind, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+
+    pub fn send(path: &Path) -> Result<(), String> {
+        let (trash, topdir) = trash_dir_for(path)?;
+        let files = trash.join("files");
+        let info = trash.join("info");
+        for dir in [&trash, &files, &info] {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        // Paths in a per-volume trash are stored relative to the volume.
+        let recorded = match &topdir {
+            Some(top) => path.strip_prefix(top).unwrap_or(path),
+            None => path,
+        };
+        let content = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(recorded.as_os_str()),
+            deletion_date()
+        );
+        let name = path.file_name().ok_or("cannot trash the root directory")?;
+        let (info_path, target) = reserve(&info, &files, name, &content)?;
+        if let Err(e) = fs::rename(path, &target) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e.to_string());
+        }
+        Ok(())
+    }
+
+    // Files on the home filesystem go to the home trash. Anything else goes to
+    // the volume's shared $top/.Trash/$uid when an admin set one up, or to
+    // $top/.Trash-$uid, so trashing never has to copy across devices.
+    fn trash_dir_for(path: &Path) -> Result<(PathBuf, Option<PathBuf>), String> {
+        let home_trash = std::env::var_os("XDG_DATA_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+            .ok_or("HOME is not set")?
+            .join("Trash");
+        let dev = fs::symlink_metadata(path).map_err(|e| e.to_string())?.dev();
+        let home_dev = home_trash
+            .ancestors()
+            .find_map(|p| fs::metadata(p).ok())
+            .map(|m| m.dev());
+        if home_dev == Some(dev) {
+            return Ok((home_trash, None));
+        }
+        let top = fsinfo::mount_table()
+            .into_iter()
+            .map(|m| m.path)
+            .filter(|p| path.starts_with(p))
+            .max_by_key(|p| p.as_os_str().len())
+            .ok_or("no mount point found")?;
+        let uid = unsafe { libc::getuid() };
+        let shared = top.join(".Trash");
+        let usable = fs::symlink_metadata(&shared)
+            .is_ok_and(|m| m.is_dir() && m.permissions().mode() & 0o1000 != 0);
+        let trash = if usable {
+            shared.join(uid.to_string())
+        } else {
+            top.join(format!(".Trash-{}", uid))
+        };
+        Ok((trash, Some(top)))
+    }
+
+    // Claims a free name by creating the .trashinfo file exclusively, which
+    // is how the spec avoids races between programs trashing at once.
+    fn reserve(info: &Path, files: &Path, name: &OsStr, content: &str) -> Result<(PathBuf, PathBuf), String> {
+        for n in 1..10_000 {
+            let mut candidate = name.to_os_string();
+            if n > 1 {
+                candidate.push(format!(".{}", n));
+            }
+            let mut info_name = candidate.clone();
+            info_name.push(".trashinfo");
+            let info_path = info.join(info_name);
+            let target = files.join(&candidate);
+            if fs::symlink_metadata(&target).is_ok() {
+                continue;
+            }
+            match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+                Ok(mut file) => {
+                    file.write_all(content.as_bytes()).map_err(|e| e.to_string())?;
+                    return Ok((info_path, target));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("{}: {}", info_path.display(), e)),
+            }
+        }
+        Err("no free name in the trash".to_string())
+    }
+
+    fn percent_encode(path: &OsStr) -> String {
+        let mut out = String::new();
+        for &b in path.as_bytes() {
+            if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        }
+        out
+    }
+
+    // The spec wants local time without a zone, e.g. 2024-05-01T13:45:00.
+    fn deletion_date() -> String {
+        let now = unsafe { libc::time(std::ptr::null_mut()) };
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe { libc::localtime_r(&now, &mut tm) };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// How sizes are written everywhere: on the screen, in exports and reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitMode {
+    // KiB, MiB, ...: powers of 1024.
+    Binary,
+    // kB, MB, ...: powers of 1000, as drive makers and some file managers count.
+    Si,
+    // The exact number of bytes, with thousands separators.
+    Bytes,
+}
+
+const MODES: [UnitMode; 3] = [UnitMode::Binary, UnitMode::Si, UnitMode::Bytes];
+
+impl UnitMode {
+    pub fn parse(value: &str) -> Option<UnitMode> {
+        MODES.into_iter().find(|m| m.name() == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitMode::Binary => "binary",
+            UnitMode::Si => "si",
+            UnitMode::Bytes => "bytes",
+        }
+    }
+
+    pub fn next(self) -> UnitMode {
+        MODES[(self as usize + 1) % MODES.len()]
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(UnitMode::Binary as u8);
+
+pub fn mode() -> UnitMode {
+    MODES[MODE.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_mode(mode: UnitMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let (base, units) = match mode() {
+        UnitMode::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+        UnitMode::Si => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+        UnitMode::Bytes => return format!("{} B", format_count(bytes)),
+    };
+    let mut size = bytes as f64;
+    let mut unit = 0usize;
+    while size >= base && unit + 1 < units.len() {
+        size /= base;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else if size >= 100.0 {
+        format!("{:.0} {}", size, units[unit])
+    } else if size >= 10.0 {
+        format!("{:.1} {}", size, units[unit])
+    } else {
+        format!("{:.2} {}", size, units[unit])
+    }
+}
+
+// 84212 as "84,212", for counts read at a glance.
+pub fn format_count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
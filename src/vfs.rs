@@ -0,0 +1,253 @@
+use crate::fsinfo;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Meta {
+    pub kind: Kind,
+    pub len: u64,
+    pub blocks: u64,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    // Seconds since the epoch.
+    pub mtime: i64,
+    // The nanoseconds of mtime, and the last status change: together they
+    // tell whether anything in a folder was added, removed or renamed.
+    pub mtime_nsec: i64,
+    pub ctime: i64,
+    pub ctime_nsec: i64,
+}
+
+pub struct Entry {
+    pub name: OsString,
+    // The listed path joined with the name, as std::fs::read_dir gives it.
+    pub path: PathBuf,
+    // The entry itself, not what a symlink points to.
+    pub kind: Kind,
+}
+
+pub type Entries<'a> = Box<dyn Iterator<Item = io::Result<Entry>> + 'a>;
+
+// Everything the scanner asks of a filesystem, and deleting. The OS is the
+// only real backend today; another one (a remote host, an imported listing)
+// just has to answer these calls, as the in-memory one of the tests does.
+pub trait FileSystem: Send + Sync {
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Entries<'a>>;
+    // Follows symlinks.
+    fn metadata(&self, path: &Path) -> io::Result<Meta>;
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta>;
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+    // Used and total bytes of the filesystem holding `path`.
+    fn usage(&self, path: &Path) -> Option<(u64, u64)>;
+    // The device or source of the filesystem holding `path`, like /dev/sda1.
+    fn device(&self, path: &Path) -> Option<String>;
+    // Deletes a file or symlink, or a folder with everything in it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+}
+
+pub struct Os;
+
+impl FileSystem for Os {
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Entries<'a>> {
+        let entries = fs::read_dir(path)?.map(|entry| {
+            let entry = entry?;
+            Ok(Entry {
+                kind: kind_of(entry.file_type()?),
+                name: entry.file_name(),
+                path: entry.path(),
+            })
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Meta> {
+        fs::metadata(path).map(|m| meta_of(&m))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta> {
+        fs::symlink_metadata(path).map(|m| meta_of(&m))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn usage(&self, path: &Path) -> Option<(u64, u64)> {
+        fsinfo::fs_usage(path)
+    }
+
+    fn device(&self, path: &Path) -> Option<String> {
+        fsinfo::device_of(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+}
+
+fn kind_of(t: fs::FileType) -> Kind {
+    if t.is_symlink() {
+        Kind::Symlink
+    } else if t.is_dir() {
+        Kind::Dir
+    } else if t.is_file() {
+        Kind::File
+    } else {
+        Kind::Other
+    }
+}
+
+fn meta_of(m: &fs::Metadata) -> Meta {
+    Meta {
+        kind: kind_of(m.file_type()),
+        len: m.len(),
+        blocks: m.blocks(),
+        dev: m.dev(),
+        ino: m.ino(),
+        nlink: m.nlink(),
+        mtime: m.mtime(),
+        mtime_nsec: m.mtime_nsec(),
+        ctime: m.ctime(),
+        ctime_nsec: m.ctime_nsec(),
+    }
+}
+
+// A filesystem held in memory, for tests: folders, files, symlinks and hard
+// links under /, on device 1 unless mounted from another.
+#[cfg(test)]
+pub struct Memory {
+    nodes: std::sync::Mutex<std::collections::BTreeMap<PathBuf, (Meta, Option<PathBuf>)>>,
+}
+
+#[cfg(test)]
+impl Memory {
+    pub fn new() -> Memory {
+        let root = Memory::meta(Kind::Dir, 4096, 1, 1);
+        Memory { nodes: std::sync::Mutex::new([(PathBuf::from("/"), (root, None))].into()) }
+    }
+
+    fn meta(kind: Kind, len: u64, dev: u64, ino: u64) -> Meta {
+        let mtime = ino as i64;
+        let (mtime_nsec, ctime, ctime_nsec) = (0, mtime, 0);
+        Meta { kind, len, blocks: len.div_ceil(512), dev, ino, nlink: 1, mtime, mtime_nsec, ctime, ctime_nsec }
+    }
+
+    fn add(&self, path: &str, kind: Kind, len: u64, dev: Option<u64>, target: Option<PathBuf>) -> &Memory {
+        let mut nodes = self.nodes.lock().unwrap();
+        let path = PathBuf::from(path);
+        let parent = path.parent().and_then(|p| nodes.get(p)).expect("parent folder");
+        let dev = dev.unwrap_or(parent.0.dev);
+        let ino = nodes.len() as u64 + 1;
+        let meta = Memory::meta(kind, len, dev, ino);
+        nodes.insert(path, (meta, target));
+        self
+    }
+
+    pub fn dir(&self, path: &str) -> &Memory {
+        self.add(path, Kind::Dir, 4096, None, None)
+    }
+
+    pub fn file(&self, path: &str, len: u64) -> &Memory {
+        self.add(path, Kind::File, len, None, None)
+    }
+
+    pub fn symlink(&self, path: &str, target: &str) -> &Memory {
+        self.add(path, Kind::Symlink, target.len() as u64, None, Some(target.into()))
+    }
+
+    // Another filesystem mounted at `path`.
+    pub fn mount(&self, path: &str, dev: u64) -> &Memory {
+        self.add(path, Kind::Dir, 4096, Some(dev), None)
+    }
+
+    // A second name for the file at `existing`.
+    pub fn hard_link(&self, path: &str, existing: &str) -> &Memory {
+        let mut nodes = self.nodes.lock().unwrap();
+        let (mut meta, _) = nodes[Path::new(existing)];
+        meta.nlink += 1;
+        for (other, _) in nodes.values_mut().filter(|(m, _)| m.dev == meta.dev && m.ino == meta.ino) {
+            other.nlink = meta.nlink;
+        }
+        nodes.insert(path.into(), (meta, None));
+        self
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.nodes.lock().unwrap().contains_key(Path::new(path))
+    }
+}
+
+#[cfg(test)]
+impl FileSystem for Memory {
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Entries<'a>> {
+        let nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some((meta, _)) if meta.kind == Kind::Dir => {}
+            Some(_) => return Err(io::Error::from_raw_os_error(libc::ENOTDIR)),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        }
+        let entries: Vec<io::Result<Entry>> = nodes
+            .iter()
+            .filter(|(child, _)| child.parent() == Some(path) && child.as_path() != path)
+            .map(|(child, (meta, _))| {
+                let name = child.file_name().unwrap_or_default().to_os_string();
+                Ok(Entry { name, path: child.clone(), kind: meta.kind })
+            })
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Meta> {
+        let mut path = path.to_path_buf();
+        for _ in 0..40 {
+            let (meta, target) = self.nodes.lock().unwrap().get(&path).cloned().ok_or(io::ErrorKind::NotFound)?;
+            match target {
+                Some(target) => path = path.parent().unwrap_or(Path::new("/")).join(target),
+                None => return Ok(meta),
+            }
+        }
+        Err(io::Error::from_raw_os_error(libc::ELOOP))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta> {
+        self.nodes.lock().unwrap().get(path).map(|(meta, _)| *meta).ok_or(io::ErrorKind::NotFound.into())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.metadata(path).map(|_| path.to_path_buf())
+    }
+
+    fn usage(&self, _path: &Path) -> Option<(u64, u64)> {
+        None
+    }
+
+    fn device(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let (meta, _) = *nodes.get(path).ok_or(io::ErrorKind::NotFound)?;
+        nodes.retain(|p, _| !p.starts_with(path));
+        for (other, _) in nodes.values_mut().filter(|(m, _)| m.dev == meta.dev && m.ino == meta.ino) {
+            other.nlink -= 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::{Config, Threshold};
+use crate::export;
+use crate::fsinfo;
+use crate::hook::{self, Field};
+use crate::scan::{self, Exclude, TreeOptions};
+use crate::vfs::Os;
+use crate::units::format_size;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const TOTALS_ONLY: TreeOptions = TreeOptions {
+    max_depth: 0,
+    max_children: 0,
+    keep_files: false,
+    follow_links: false,
+    allocated: false,
+    count_links: false,
+    one_fs: false,
+    mounts: false,
+    du: false,
+};
+
+struct Reading {
+    bytes: u64,
+    fs_percent: Option<f64>,
+}
+
+pub fn run(path: &Path, interval: Duration, config: &Config) -> Result<(), String> {
+    let mut fired = vec![false; config.hooks.len()];
+    let opts = TreeOptions { one_fs: config.one_file_system, ..TOTALS_ONLY };
+    let exclude = Exclude {
+        paths: Vec::new(),
+        pseudo: fsinfo::pseudo_mounts(&config.pseudo_filesystems),
+        patterns: config.exclude.clone(),
+    };
+    loop {
+        let started = Instant::now();
+        let (root, errors) = scan::scan_tree(&Os, path, opts, &exclude)?;
+        let reading = Reading {
+            bytes: root.size,
+            fs_percent: fsinfo::fs_usage(path)
+                .filter(|&(_, total)| total > 0)
+                .map(|(used, total)| used as f64 * 100.0 / total as f64),
+        };
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        println!(
+            "{}  {}  {}  fs {}  errors {}",
+            export::timestamp(secs),
+            root.path().display(),
+            format_size(reading.bytes),
+            reading.fs_percent.map(|p| format!("{:.1}%", p)).unwrap_or_else(|| "?".to_string()),
+            errors.len()
+        );
+
+        for (hook, fired) in config.hooks.iter().zip(fired.iter_mut()) {
+            let over = match hook.threshold {
+                Some(Threshold::FsPercent(limit)) => reading.fs_percent.is_some_and(|p| p >= limit),
+                Some(Threshold::Bytes(limit)) => reading.bytes >= limit,
+                None => false,
+            };
+            if !over {
+                *fired = false;
+            } else if !*fired {
+                let fields = vec![
+                    ("path", Field::Text(root.path().to_string_lossy().into_owned())),
+                    ("bytes", Field::Number(reading.bytes)),
+                    ("fs_percent", Field::Decimal(reading.fs_percent)),
+                ];
+                // An alert that did not go out is tried again on the next scan.
+                match hook::fire(hook, "threshold", &fields) {
+                    Ok(()) => *fired = true,
+                    Err(err) => eprintln!("duviz: hook '{}': {} (retrying next scan)", hook.name, err),
+                }
+            }
+        }
+
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}